@@ -0,0 +1,29 @@
+use x2y::{Format, InputOptions, Limits, OutputOptions};
+
+// Reads the input as `format` and writes whatever it decodes to in every
+// format. Nothing may panic or hang, and any output written must read back.
+// The limits are small so each run stays fast.
+pub fn convert(data: &[u8], format: Format) {
+    let Ok(contents) = std::str::from_utf8(data) else {
+        return;
+    };
+    let options = InputOptions {
+        limits: Limits {
+            max_input_size: Some(64 * 1024),
+            max_nodes: Some(10_000),
+            max_alias_expansion: 10_000,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let Ok(value) = x2y::decode_with(contents, format, &options) else {
+        return;
+    };
+    for output_format in [Format::Yaml, Format::Json, Format::Toml] {
+        if let Ok(output) = x2y::encode(&value, output_format, &OutputOptions::default()) {
+            if let Err(e) = x2y::decode(&output, output_format) {
+                panic!("the {} output can't be read back: {}\n{}", output_format, e, output);
+            }
+        }
+    }
+}
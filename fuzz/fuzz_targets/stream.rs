@@ -0,0 +1,30 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use x2y::{Format, InputOptions, Limits, Transcoder};
+
+// Converts the input as JSON streamed to YAML. Whatever the stream writes
+// when it succeeds must be what converting the whole document writes.
+fuzz_target!(|data: &[u8]| {
+    let transcoder = Transcoder::new(Format::Json, Format::Yaml).input_options(InputOptions {
+        limits: Limits {
+            max_input_size: Some(64 * 1024),
+            max_nodes: Some(10_000),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    let mut streamed = Vec::new();
+    if transcoder.transcode_reader_to_writer(data, &mut streamed).is_err() {
+        return;
+    }
+    let streamed = String::from_utf8(streamed).expect("the streamed output is utf-8");
+    match transcoder.transcode_bytes(data) {
+        Ok(whole) => assert_eq!(
+            streamed,
+            String::from_utf8(whole).expect("the output is utf-8"),
+            "the streamed output differs"
+        ),
+        Err(e) => panic!("the stream converted a document that can't be read whole: {}", e),
+    }
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use x2y::Format;
+
+mod common;
+
+fuzz_target!(|data: &[u8]| common::convert(data, Format::Yaml));
@@ -1,82 +1,1268 @@
-use crate::error::X2YError;
+use crate::cache::{self, Cache};
+use crate::completions;
+use crate::config::{self, Config, Settings};
+use crate::diagnostic;
+use crate::diff;
+use crate::edit;
+use crate::error::{VerificationError, Violation, X2YError};
+use crate::format::Format;
 use crate::fs::*;
-use clap::Parser;
+use crate::infer::Inference;
+use crate::limits;
+use crate::merge::{self, ArrayMerge, MergeOptions};
+use crate::options::{
+    InputOptions, Limits, LineEnding, OutputOptions, QuoteStyle, SequenceStyle, TableStyle,
+    YamlTags,
+};
+use crate::patch;
+use crate::query::Query;
+use crate::schema::Schema;
+use crate::transcoder;
+use crate::traversal::walk_dir;
+use crate::value::Value;
+use crate::verify;
+use crate::watch::{self, Change, Watcher};
+use clap::builder::{EnumValueParser, PossibleValue, TypedValueParser};
+use clap::{Arg, ColorChoice, CommandFactory, Parser, Subcommand, ValueEnum, ValueHint};
+use clap_complete::Shell;
+use clap_mangen::Man;
+use std::cell::OnceCell;
+use std::env;
+use std::ffi::OsStr;
 use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Parser)]
 #[command(
     name = "x2y",
     author,
     version,
-    about = "A data-serialisation file transcoder"
+    about = "A data-serialisation file transcoder",
+    subcommand_negates_reqs = true
 )]
 pub struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(
         value_name = "input",
-        help = "a filesystem entry, can be a file or directory",
+        required = true,
+        value_hint = ValueHint::AnyPath,
+        help = "a filesystem entry, can be a file or directory, or - for stdin",
         long_help
     )]
-    input: String,
+    input: Option<String>,
     #[arg(
         short = 'x',
         long = "input_format",
+        global = true,
+        value_parser = FormatParser,
         help = "the format of the input file(s)",
-        long_help = "the format of the input file(s)
-          \n\
-          for single files, this value can be omitted.\n
-          Possible values: \n
-          yaml\n
-          toml\n
-          json\n"
+        long_help = "the format of the input file(s)\n\n\
+          For single files, this value can be omitted, the format is taken from the file \
+          extension or guessed from the content. Given for stdin, JSON converted to YAML is \
+          written to stdout as it's read, so an error partway through leaves the output \
+          before it on stdout."
     )]
-    input_format: Option<String>,
+    input_format: Option<Format>,
     #[arg(
         short = 'y',
         long = "output_format",
-        help = "the output format for the file(s)",
-        long_help = "the output format for the file(s)
-          \n\
-          Possible values: \n
-          yaml\n
-          toml\n
-          json\n"
-    )]
-    output_format: String,
+        global = true,
+        value_parser = FormatParser,
+        help = "the output format for the file(s)"
+    )]
+    output_format: Option<Format>,
+    #[arg(
+        long = "query",
+        value_name = "expression",
+        help = "print the part of a single file selected by a jq expression instead of converting it",
+        long_help = "print the part of a single file selected by a jq expression instead of \
+          converting it\n\n\
+          The file is left untouched. Paths (.a.b, .\"a b\", .[0], .[1:3], .[], ..), pipes, \
+          commas, comparisons, and/or/not, keys, length and select() are supported, and ? \
+          after a path ignores its errors. Building sequences or mappings with [...] or {...}, \
+          arithmetic, variables and other functions such as map aren't supported."
+    )]
+    query: Option<String>,
+    #[arg(
+        long = "schema",
+        value_name = "file",
+        value_hint = ValueHint::FilePath,
+        help = "validate each document against a JSON Schema before writing the output"
+    )]
+    schema: Option<String>,
+    #[arg(
+        long = "verify",
+        help = "read each output back and fail, keeping the source, if any value was lost or changed type"
+    )]
+    verify: bool,
+    #[arg(
+        long = "strict",
+        help = "fail instead of warning when a value has to be changed to fit the output format"
+    )]
+    strict: bool,
+    #[arg(
+        long = "cache",
+        value_name = "file",
+        num_args = 0..=1,
+        require_equals = true,
+        value_hint = ValueHint::FilePath,
+        help = "keep the sources in a directory and only convert files that changed since the last run",
+        long_help = "keep the sources in a directory and only convert files that changed since \
+          the last run\n\n\
+          The hash of each source, its output and the options are recorded in the cache, \
+          .x2y-cache in the directory unless a file is given with --cache=<file>. Outputs \
+          whose source was removed are removed too, unless they were changed."
+    )]
+    cache: Option<Option<String>>,
+    #[arg(
+        long = "yaml-tags",
+        global = true,
+        value_name = "mode",
+        default_value = "wrap",
+        help = "how yaml values with a custom tag such as !Ref are read"
+    )]
+    yaml_tags: YamlTags,
+    #[arg(
+        long = "max-input-size",
+        global = true,
+        value_name = "bytes",
+        help = "refuse inputs larger than this"
+    )]
+    max_input_size: Option<usize>,
+    #[arg(
+        long = "max-depth",
+        global = true,
+        value_name = "levels",
+        default_value_t = Limits::default().max_depth,
+        help = "refuse documents with mappings and sequences nested deeper than this"
+    )]
+    max_depth: usize,
+    #[arg(
+        long = "max-nodes",
+        global = true,
+        value_name = "count",
+        help = "refuse documents with more keys and values than this"
+    )]
+    max_nodes: Option<usize>,
+    #[arg(
+        long = "max-alias-expansion",
+        global = true,
+        value_name = "count",
+        default_value_t = Limits::default().max_alias_expansion,
+        help = "refuse yaml documents whose aliases add more values than this beyond the size of the input"
+    )]
+    max_alias_expansion: usize,
+    #[arg(
+        long = "max-string-length",
+        global = true,
+        value_name = "bytes",
+        help = "refuse documents with a string or key longer than this"
+    )]
+    max_string_length: Option<usize>,
+    #[arg(
+        long = "sort-keys",
+        global = true,
+        overrides_with = "no_sort_keys",
+        help = "sort mapping keys instead of keeping the source order"
+    )]
+    sort_keys: bool,
+    #[arg(
+        long = "no-sort-keys",
+        global = true,
+        overrides_with = "sort_keys",
+        help = "keep the source key order even if .x2y.toml sorts keys"
+    )]
+    no_sort_keys: bool,
+    #[arg(
+        long = "indent",
+        global = true,
+        value_name = "width",
+        help = "the indentation width, defaults to 2 for json and yaml and 4 for toml arrays"
+    )]
+    indent: Option<usize>,
+    #[arg(
+        long = "compact",
+        global = true,
+        overrides_with = "no_compact",
+        help = "write json on a single line and toml arrays inline"
+    )]
+    compact: bool,
+    #[arg(
+        long = "no-compact",
+        global = true,
+        overrides_with = "compact",
+        help = "don't write compact output even if .x2y.toml asks for it"
+    )]
+    no_compact: bool,
+    #[arg(
+        long = "canonical",
+        global = true,
+        overrides_with = "no_canonical",
+        help = "write json in the rfc 8785 canonical form, ignoring other style options"
+    )]
+    canonical: bool,
+    #[arg(
+        long = "no-canonical",
+        global = true,
+        overrides_with = "canonical",
+        help = "don't write canonical json even if .x2y.toml asks for it"
+    )]
+    no_canonical: bool,
+    #[arg(
+        long = "yaml-sequences",
+        global = true,
+        value_name = "style",
+        help = "how yaml sequences are written [default: block]"
+    )]
+    sequence_style: Option<SequenceStyle>,
+    #[arg(
+        long = "yaml-anchors",
+        global = true,
+        overrides_with = "no_yaml_anchors",
+        help = "write repeated yaml mappings and sequences once and refer back to them with aliases"
+    )]
+    yaml_anchors: bool,
+    #[arg(
+        long = "no-yaml-anchors",
+        global = true,
+        overrides_with = "yaml_anchors",
+        help = "write repeated yaml values in full even if .x2y.toml asks for anchors"
+    )]
+    no_yaml_anchors: bool,
+    #[arg(
+        long = "quote-style",
+        global = true,
+        value_name = "style",
+        help = "how yaml string values are quoted [default: auto]"
+    )]
+    quote_style: Option<QuoteStyle>,
+    #[arg(
+        long = "toml-tables",
+        global = true,
+        value_name = "style",
+        help = "how nested toml tables are written [default: tables]"
+    )]
+    table_style: Option<TableStyle>,
+    #[arg(
+        long = "no-trailing-newline",
+        global = true,
+        overrides_with = "trailing_newline",
+        help = "don't end the output with a newline"
+    )]
+    no_trailing_newline: bool,
+    #[arg(
+        long = "trailing-newline",
+        global = true,
+        overrides_with = "no_trailing_newline",
+        help = "end the output with a newline even if .x2y.toml says not to"
+    )]
+    trailing_newline: bool,
+    #[arg(
+        long = "line-ending",
+        global = true,
+        value_name = "ending",
+        help = "the line ending used in the output [default: lf]"
+    )]
+    line_ending: Option<LineEnding>,
+    #[arg(
+        long = "no-config",
+        global = true,
+        help = "ignore any .x2y.toml in this directory or its parents"
+    )]
+    no_config: bool,
+    #[arg(
+        long = "color",
+        global = true,
+        value_name = "when",
+        default_value = "auto",
+        help = "when to colour error messages"
+    )]
+    color: ColorChoice,
+    #[arg(
+        long = "error-format",
+        global = true,
+        value_name = "format",
+        default_value = "human",
+        help = "how errors are written to stderr"
+    )]
+    error_format: ErrorFormat,
+    // Loaded when the command runs.
+    #[arg(skip)]
+    config: OnceCell<Option<Config>>,
+}
+
+// Edits write the file back in its own format. A file of - reads stdin and
+// writes the result to stdout.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Print the value at a path, e.g. .servers[0].host
+    Get {
+        path: String,
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: String,
+    },
+    /// Set the value at a path, the value is read as YAML so '"80"' is a string
+    ///
+    /// TOML files keep their comments and layout. YAML and JSON files are written
+    /// again as a whole with the output options, so YAML comments, anchors and
+    /// aliases and << merge keys aren't kept, which is warned about.
+    Set {
+        path: String,
+        value: String,
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: String,
+    },
+    /// Delete the value at a path
+    ///
+    /// TOML files keep their comments and layout. YAML and JSON files are written
+    /// again as a whole with the output options, so YAML comments, anchors and
+    /// aliases and << merge keys aren't kept, which is warned about.
+    #[command(alias = "delete")]
+    Del {
+        path: String,
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: String,
+    },
+    /// Deep-merge files into one document on stdout, later files take precedence
+    Merge {
+        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        files: Vec<String>,
+        #[arg(
+            long = "arrays",
+            value_name = "strategy",
+            default_value = "replace",
+            help = "how sequences are merged"
+        )]
+        arrays: ArrayMerge,
+        #[arg(
+            long = "merge-key",
+            value_name = "key",
+            default_value = "name",
+            help = "the key identifying items with --arrays merge-by-key"
+        )]
+        merge_key: String,
+    },
+    /// Compare the data in two files of any format, exits with 1 if they differ
+    Diff {
+        #[arg(value_hint = ValueHint::FilePath)]
+        a: String,
+        #[arg(value_hint = ValueHint::FilePath)]
+        b: String,
+        #[arg(
+            long = "format",
+            value_name = "format",
+            default_value = "human",
+            help = "how the differences are written"
+        )]
+        format: DiffFormat,
+    },
+    /// Validate files, or every file in directories, against a JSON Schema
+    Validate {
+        #[arg(
+            long = "schema",
+            value_name = "file",
+            value_hint = ValueHint::FilePath,
+            help = "the JSON Schema, in any format"
+        )]
+        schema: String,
+        #[arg(required = true, value_hint = ValueHint::AnyPath)]
+        paths: Vec<String>,
+    },
+    /// Infer a JSON Schema that files, or every file in directories, match
+    InferSchema {
+        #[arg(required = true, value_hint = ValueHint::AnyPath)]
+        paths: Vec<String>,
+    },
+    /// Apply a JSON Patch or JSON Merge Patch, in any format, to a file
+    Patch {
+        #[arg(
+            long = "patch",
+            value_name = "file",
+            value_hint = ValueHint::FilePath,
+            help = "the patch, a sequence of operations is a JSON Patch and a mapping a JSON Merge Patch"
+        )]
+        patch: String,
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: String,
+    },
+    /// Print a completion script for a shell
+    Completions { shell: Shell },
+    /// Print the man page
+    Man,
+    /// Convert files again whenever they change, keeping the sources
+    Watch {
+        #[arg(value_hint = ValueHint::AnyPath)]
+        path: String,
+        #[arg(
+            long = "debounce",
+            value_name = "ms",
+            default_value_t = 200,
+            help = "how long a file has to be left unchanged before it's converted"
+        )]
+        debounce: u64,
+    },
+    /// Show the settings from .x2y.toml
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the settings in effect, with the rules for a file applied when one is given
+    Show {
+        #[arg(value_hint = ValueHint::FilePath)]
+        file: Option<String>,
+    },
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub enum DiffFormat {
+    /// One added, removed or changed path per line.
+    Human,
+    /// An RFC 6902 JSON Patch that turns the first document into the second.
+    JsonPatch,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub enum ErrorFormat {
+    /// A description with the source snippet, for people.
+    Human,
+    /// One JSON object per error, for tools.
+    Json,
+}
+
+// A flag and its `--no-` form as a setting, None when neither was given. Each
+// overrides the other, so only the last one given is set.
+fn switch(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+// Parses -x and -y as a `Format`, ignoring surrounding whitespace so a quoted
+// `"-y json"` still works. Help, suggestions and completions come from the
+// formats' `ValueEnum`.
+#[derive(Clone)]
+struct FormatParser;
+
+impl TypedValueParser for FormatParser {
+    type Value = Format;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Format, clap::Error> {
+        let value = value.to_str().map_or(value, |s| OsStr::new(s.trim()));
+        EnumValueParser::<Format>::new().parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(
+            Format::value_variants()
+                .iter()
+                .filter_map(ValueEnum::to_possible_value),
+        ))
+    }
 }
 
 impl Args {
-    pub fn run(&self) -> Result<(), X2YError> {
+    /// Prints an error to stderr in the requested format.
+    pub fn report(&self, error: &X2YError) {
+        if self.error_format == ErrorFormat::Json {
+            eprintln!("{}", diagnostic::to_json(error));
+            return;
+        }
+        eprint!("{}", diagnostic::render(error, self.color_enabled()));
+    }
+
+    /// Prints the values a conversion changed to stderr in the requested format.
+    fn warn(&self, file: Option<&Path>, warnings: &[Violation]) {
+        for warning in warnings {
+            if self.error_format == ErrorFormat::Json {
+                eprintln!("{}", diagnostic::warning_to_json(file, warning));
+            } else {
+                eprint!(
+                    "{}",
+                    diagnostic::render_warning(file, warning, self.color_enabled())
+                );
+            }
+        }
+    }
+
+    fn color_enabled(&self) -> bool {
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        }
+    }
+
+    fn input_options(&self) -> InputOptions {
+        InputOptions {
+            yaml_tags: self.yaml_tags,
+            limits: Limits {
+                max_input_size: self.max_input_size,
+                max_depth: self.max_depth,
+                max_nodes: self.max_nodes,
+                max_alias_expansion: self.max_alias_expansion,
+                max_string_length: self.max_string_length,
+            },
+        }
+    }
+
+    // The settings given as flags, which take precedence over .x2y.toml.
+    fn flags(&self) -> Settings {
+        Settings {
+            input_format: self.input_format,
+            output_format: self.output_format,
+            sort_keys: switch(self.sort_keys, self.no_sort_keys),
+            indent: self.indent,
+            compact: switch(self.compact, self.no_compact),
+            canonical: switch(self.canonical, self.no_canonical),
+            yaml_sequences: self.sequence_style,
+            yaml_anchors: switch(self.yaml_anchors, self.no_yaml_anchors),
+            quote_style: self.quote_style,
+            toml_tables: self.table_style,
+            trailing_newline: switch(self.trailing_newline, self.no_trailing_newline),
+            line_ending: self.line_ending,
+        }
+    }
+
+    // The settings for `file`, or for stdin and subcommands without one.
+    fn settings(&self, file: Option<&Path>) -> Settings {
+        let mut settings = match self.config() {
+            Some(config) => config.settings_for(file),
+            None => Settings::default(),
+        };
+        settings.overlay(&self.flags());
+        settings
+    }
+
+    fn config(&self) -> Option<&Config> {
+        self.config.get().and_then(Option::as_ref)
+    }
+
+    fn output_options(&self) -> OutputOptions {
+        self.settings(None).output_options()
+    }
+
+    fn output_format(&self, file: Option<&Path>) -> Result<Format, X2YError> {
+        self.settings(file).output_format.ok_or_else(|| {
+            X2YError::InvalidInput("an output format is required, choose one with -y".to_string())
+        })
+    }
+
+    // How a file found in a directory is converted, if it is.
+    fn plan(&self, file: &Path) -> Result<Option<Plan>, X2YError> {
+        if self.config().is_some_and(|config| !config.includes(file)) {
+            return Ok(None);
+        }
+        let settings = self.settings(Some(file));
+        let Some(input_format) = settings.input_format else {
+            return Err(X2YError::InvalidInput(
+                "converting a directory needs an input format, choose one with -x".to_string(),
+            ));
+        };
+        Ok(Some(Plan {
+            input_format,
+            output_format: self.output_format(Some(file))?,
+            options: settings.output_options(),
+        }))
+    }
+
+    pub fn run(&self) -> Result<ExitCode, X2YError> {
         log::info!("Running X2Y...");
-        let Ok(metadata) = fs::symlink_metadata(&self.input) else {
+        // Completions and the man page only describe the command line, so a
+        // broken .x2y.toml doesn't stop them.
+        let describes = matches!(
+            self.command,
+            Some(Command::Completions { .. } | Command::Man)
+        );
+        let config = match self.no_config || describes {
+            true => None,
+            false => Config::discover(&env::current_dir()?)?,
+        };
+        if let Some(config) = &config {
+            log::info!("Using settings from {}", config.path.display());
+        }
+        let _ = self.config.set(config);
+        let options = self.output_options();
+        if let Some(command) = &self.command {
+            return self.run_command(command, &options);
+        }
+        // Clap requires the input when there's no subcommand.
+        let Some(input) = &self.input else {
+            return Err(X2YError::InvalidInput("an input is required".to_string()));
+        };
+        let query = self.query.as_deref().map(Query::parse).transpose()?;
+        let schema = self
+            .schema
+            .as_deref()
+            .map(|s| self.load_schema(s))
+            .transpose()?;
+        let checks = Checks {
+            schema: schema.as_ref(),
+            verify: self.verify,
+            strict: self.strict,
+        };
+        let input_options = self.input_options();
+        if self.cache.is_some() && !Path::new(input).is_dir() {
+            return Err(X2YError::InvalidInput(
+                "--cache can only be used with a directory".to_string(),
+            ));
+        }
+        if input == "-" {
+            log::info!("Processing stdin");
+            let output_format = self.output_format(None)?;
+            self.run_stdin(output_format, query.as_ref(), &checks, &options)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        let Ok(metadata) = fs::symlink_metadata(input) else {
             // Need a valid input.
             // If we can't determine the file type we don't know how to process it.
             return Err(X2YError::InvalidInput(format!(
                 "failed to get input metadata: {:?}",
-                &self.input
+                input
             )));
         };
         log::info!("Checking input file type");
         let file_type = metadata.file_type();
 
-        let input_format = &self.input_format;
-        let output_format = &self.output_format;
         // What file formats are we going to look for
-        if file_type.is_dir() && input_format.is_some() {
+        if file_type.is_dir() && query.is_some() {
+            return Err(X2YError::InvalidInput(
+                "--query can only be used with a single file or stdin".to_string(),
+            ));
+        } else if file_type.is_dir() {
             log::info!("Processing input as directory");
-            process_directory(
-                self.input.as_ref(),
-                input_format.as_ref().unwrap().as_ref(),
-                output_format.as_ref(),
+            let mut cache = match &self.cache {
+                Some(path) => {
+                    let path = match path {
+                        Some(path) => Path::new(path).to_path_buf(),
+                        None => Path::new(input).join(cache::FILE_NAME),
+                    };
+                    Some(Cache::load(&path)?)
+                }
+                None => None,
+            };
+            let warnings = process_directory(
+                input.as_ref(),
+                &input_options,
+                &checks,
+                |file| self.plan(file),
+                cache.as_mut(),
             )?;
+            for (file, warnings) in &warnings {
+                self.warn(Some(file), warnings);
+            }
         } else if file_type.is_file() {
             log::info!("Processing input as file");
-            process_file(self.input.as_ref(), self.output_format.as_ref())?;
+            let path = Path::new(input);
+            let input_format = self.file_format(path)?;
+            let output_format = self.output_format(Some(path))?;
+            let options = self.settings(Some(path)).output_options();
+            match &query {
+                Some(query) => {
+                    let contents = limits::read_to_string(path, &input_options.limits)?;
+                    if let Some(schema) = &schema {
+                        schema
+                            .check(&contents, input_format, &input_options)
+                            .map_err(|e| e.with_path(path))?;
+                    }
+                    let output = query_document(
+                        &contents,
+                        input_format,
+                        output_format,
+                        query,
+                        &input_options,
+                        &options,
+                    )
+                    .map_err(|e| e.with_path(path))?;
+                    io::stdout().write_all(output.as_bytes())?;
+                }
+                None => {
+                    let warnings = process_file(
+                        path,
+                        input_format,
+                        output_format,
+                        &input_options,
+                        &options,
+                        &checks,
+                    )?;
+                    self.warn(Some(path), &warnings);
+                }
+            }
         } else if file_type.is_symlink() {
             return Err(X2YError::InvalidInput(format!(
                 "unable to perform operations on file type: {:?}",
                 file_type
             )));
         };
+        Ok(ExitCode::SUCCESS)
+    }
+
+    // Stdin is converted to stdout, there's no file to replace.
+    fn run_stdin(
+        &self,
+        output_format: Format,
+        query: Option<&Query>,
+        checks: &Checks,
+        options: &OutputOptions,
+    ) -> Result<(), X2YError> {
+        if let Some(input_format) = self.settings(None).input_format {
+            if query.is_none()
+                && checks.schema.is_none()
+                && !checks.verify
+                && transcoder::streams(input_format, output_format, options)
+            {
+                transcoder::transcode_stream(
+                    io::stdin().lock(),
+                    io::stdout().lock(),
+                    input_format,
+                    output_format,
+                    &self.input_options(),
+                    options,
+                )?;
+                return Ok(());
+            }
+        }
+        let (contents, input_format) = self.read_stdin()?;
+        let input_options = self.input_options();
+        if let Some(schema) = checks.schema {
+            schema.check(&contents, input_format, &input_options)?;
+        }
+        let output = match query {
+            Some(query) => query_document(
+                &contents,
+                input_format,
+                output_format,
+                query,
+                &input_options,
+                options,
+            )?,
+            None => {
+                let (output, warnings) = transcoder::transcode_with_warnings(
+                    &contents,
+                    input_format,
+                    output_format,
+                    &input_options,
+                    options,
+                )?;
+                if checks.strict && !warnings.is_empty() {
+                    return Err(X2YError::Verification(Box::new(VerificationError {
+                        format: output_format,
+                        losses: warnings,
+                        path: None,
+                    })));
+                }
+                self.warn(None, &warnings);
+                if checks.verify {
+                    verify::verify(
+                        &contents,
+                        input_format,
+                        &output,
+                        output_format,
+                        &input_options,
+                    )?;
+                }
+                output
+            }
+        };
+        io::stdout().write_all(output.as_bytes())?;
+        Ok(())
+    }
+
+    fn run_command(
+        &self,
+        command: &Command,
+        options: &OutputOptions,
+    ) -> Result<ExitCode, X2YError> {
+        let (path, file) = match command {
+            Command::Get { path, file }
+            | Command::Set { path, file, .. }
+            | Command::Del { path, file } => (path, file),
+            Command::Merge {
+                files,
+                arrays,
+                merge_key,
+            } => {
+                let merge_options = MergeOptions {
+                    arrays: *arrays,
+                    merge_key: merge_key.clone(),
+                };
+                self.run_merge(files, &merge_options, options)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            Command::Diff { a, b, format } => return self.run_diff(a, b, *format, options),
+            Command::Validate { schema, paths } => return self.run_validate(schema, paths),
+            Command::InferSchema { paths } => {
+                self.run_infer_schema(paths, options)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            Command::Patch { patch, file } => {
+                self.run_patch(patch, file, options)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            Command::Completions { shell } => {
+                let script = completions::script(*shell, &mut Args::command());
+                io::stdout().write_all(script.as_bytes())?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            Command::Man => {
+                Man::new(Args::command()).render(&mut io::stdout())?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            Command::Watch { path, debounce } => {
+                return self.run_watch(Path::new(path), Duration::from_millis(*debounce))
+            }
+            Command::Config {
+                command: ConfigCommand::Show { file },
+            } => {
+                self.run_config_show(file.as_deref().map(Path::new))?;
+                return Ok(ExitCode::SUCCESS);
+            }
+        };
+        let path = edit::parse_path(path)?;
+        let (contents, format) = self.read_document(file)?;
+        let input_options = self.input_options();
+        let file = Path::new(file);
+        let output = match command {
+            Command::Get { .. } => {
+                let value = edit::get(&contents, format, &input_options, &path)
+                    .map_err(|e| e.with_path(file))?;
+                let output = edit::display(&value, self.output_format_or(format), options)?;
+                io::stdout().write_all(output.as_bytes())?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            Command::Set { value, .. } => {
+                let value = transcoder::decode(value, Format::Yaml)?;
+                edit::set(&contents, format, &input_options, &path, value, options)
+            }
+            Command::Del { .. } => edit::delete(&contents, format, &input_options, &path, options),
+            Command::Merge { .. }
+            | Command::Diff { .. }
+            | Command::Validate { .. }
+            | Command::InferSchema { .. }
+            | Command::Patch { .. }
+            | Command::Completions { .. }
+            | Command::Man
+            | Command::Watch { .. }
+            | Command::Config { .. } => unreachable!("handled above"),
+        }
+        .map_err(|e| e.with_path(file))?;
+        self.warn(Some(file), &edit::losses(&contents, format));
+        write_document(file, &output)?;
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn run_merge(
+        &self,
+        files: &[String],
+        merge_options: &MergeOptions,
+        options: &OutputOptions,
+    ) -> Result<(), X2YError> {
+        let mut merged: Option<(Value, Format)> = None;
+        for file in files {
+            let (value, format) = self.decode_document(file)?;
+            match &mut merged {
+                Some((base, _)) => merge::merge(base, value, merge_options),
+                None => merged = Some((value, format)),
+            }
+        }
+        let Some((mut value, first_format)) = merged else {
+            return Ok(());
+        };
+        if options.sort_keys {
+            value.sort_keys();
+        }
+        let output = transcoder::encode(&value, self.output_format_or(first_format), options)?;
+        io::stdout().write_all(output.as_bytes())?;
+        Ok(())
+    }
+
+    // Like diff(1), the exit code is 1 when the documents differ.
+    fn run_diff(
+        &self,
+        a: &str,
+        b: &str,
+        format: DiffFormat,
+        options: &OutputOptions,
+    ) -> Result<ExitCode, X2YError> {
+        let (a, _) = self.decode_document(a)?;
+        let (b, _) = self.decode_document(b)?;
+        let changes = diff::diff(&a, &b);
+        let output = match format {
+            DiffFormat::Human => diff::render(&changes),
+            DiffFormat::JsonPatch => {
+                transcoder::encode(&diff::to_json_patch(&changes), Format::Json, options)?
+            }
+        };
+        io::stdout().write_all(output.as_bytes())?;
+        Ok(if changes.is_empty() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::from(1)
+        })
+    }
+
+    fn run_patch(&self, patch: &str, file: &str, options: &OutputOptions) -> Result<(), X2YError> {
+        let (patch, _) = self.decode_document(patch)?;
+        let (mut value, format) = self.decode_document(file)?;
+        let file = Path::new(file);
+        patch::apply(&mut value, patch).map_err(|e| e.with_path(file))?;
+        let output = transcoder::encode(&value, format, options).map_err(|e| e.with_path(file))?;
+        write_document(file, &output)
+    }
+
+    // Every file is checked and reported, the exit code is that of the first failure.
+    fn run_validate(&self, schema: &str, paths: &[String]) -> Result<ExitCode, X2YError> {
+        let schema = self.load_schema(schema)?;
+        let input_options = self.input_options();
+        let files = collect_files(paths);
+        let mut failure = None;
+        let mut failed = 0;
+        for file in &files {
+            let result = self
+                .read_document(file)
+                .and_then(|(contents, format)| schema.check(&contents, format, &input_options))
+                .map_err(|e| e.with_path(Path::new(file)));
+            if let Err(e) = result {
+                self.report(&e);
+                failure.get_or_insert(e.exit_code());
+                failed += 1;
+            }
+        }
+        if self.error_format == ErrorFormat::Human {
+            eprintln!(
+                "{} of {} files match the schema",
+                files.len() - failed,
+                files.len()
+            );
+        }
+        Ok(failure.map_or(ExitCode::SUCCESS, ExitCode::from))
+    }
+
+    fn run_infer_schema(&self, paths: &[String], options: &OutputOptions) -> Result<(), X2YError> {
+        let mut inference = Inference::new();
+        for file in collect_files(paths) {
+            let (value, _) = self.decode_document(&file)?;
+            inference.add(&value);
+        }
+        let mut schema = inference.schema();
+        if options.sort_keys {
+            schema.sort_keys();
+        }
+        let output = transcoder::encode(&schema, self.output_format_or(Format::Json), options)?;
+        io::stdout().write_all(output.as_bytes())?;
+        Ok(())
+    }
+
+    // Runs until interrupted. Sources are never removed, and a failed
+    // conversion is reported and tried again when the file next changes.
+    fn run_watch(&self, path: &Path, debounce: Duration) -> Result<ExitCode, X2YError> {
+        let input_options = self.input_options();
+        let checks = Checks::default();
+        let mut watcher = Watcher::new(debounce);
+        let files = self.watched_files(path)?;
+        let stale = watcher.start(files.iter().map(|(file, plan)| {
+            (
+                file.as_path(),
+                file.with_extension(plan.output_format.to_string()),
+            )
+        }));
+        if self.error_format == ErrorFormat::Human {
+            eprintln!("watching {} files in {}", files.len(), path.display());
+        }
+        self.derive_files(&files, &stale, &input_options, &checks);
+        let (sender, changes) = mpsc::channel();
+        match watch::subscribe(path, sender) {
+            // Changes stop being reported when `_subscription` is dropped.
+            Ok(_subscription) => self.watch_changes(path, &mut watcher, &changes, &input_options),
+            Err(e) => {
+                log::warn!(
+                    "Can't be told about changes to {} ({}), scanning it every {}ms instead",
+                    path.display(),
+                    e,
+                    watch::INTERVAL.as_millis()
+                );
+                self.scan_changes(path, &mut watcher, &input_options)
+            }
+        }
+    }
+
+    // Converts the files the file system reports as changed once they settle.
+    fn watch_changes(
+        &self,
+        path: &Path,
+        watcher: &mut Watcher,
+        changes: &Receiver<Change>,
+        input_options: &InputOptions,
+    ) -> Result<ExitCode, X2YError> {
+        loop {
+            let change = match watcher.next_due() {
+                Some(due) => changes.recv_timeout(due.saturating_duration_since(Instant::now())),
+                None => changes.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            let first = match change {
+                Ok(change) => Some(change),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return self.scan_changes(path, watcher, input_options)
+                }
+            };
+            let mut touched = Vec::new();
+            let mut rescan = false;
+            for change in first.into_iter().chain(changes.try_iter()) {
+                match change {
+                    Change::Paths(paths) => touched.extend(paths),
+                    Change::Rescan => rescan = true,
+                }
+            }
+            let now = Instant::now();
+            let files = self.watched_files(path)?;
+            let sources = files.iter().map(|(file, _)| file.as_path());
+            let changed = if rescan {
+                watcher.poll(sources, now)
+            } else {
+                watcher.notice(sources, &touched, now);
+                watcher.ready(now)
+            };
+            self.derive_files(&files, &changed, input_options, &Checks::default());
+        }
+    }
+
+    // Converts the files whose modification time or size changed once they
+    // settle, for when the file system can't report changes.
+    fn scan_changes(
+        &self,
+        path: &Path,
+        watcher: &mut Watcher,
+        input_options: &InputOptions,
+    ) -> Result<ExitCode, X2YError> {
+        loop {
+            thread::sleep(watch::INTERVAL);
+            let files = self.watched_files(path)?;
+            let changed =
+                watcher.poll(files.iter().map(|(file, _)| file.as_path()), Instant::now());
+            self.derive_files(&files, &changed, input_options, &Checks::default());
+        }
+    }
+
+    // The sources under `path` and how they're converted. In a directory only
+    // files with the extension of their input format are sources, so outputs
+    // aren't converted back.
+    fn watched_files(&self, path: &Path) -> Result<Vec<(PathBuf, Plan)>, X2YError> {
+        if !path.is_dir() {
+            let plan = Plan {
+                input_format: self.file_format(path)?,
+                output_format: self.output_format(Some(path))?,
+                options: self.settings(Some(path)).output_options(),
+            };
+            if path.with_extension(plan.output_format.to_string()) == path {
+                return Err(X2YError::InvalidInput(format!(
+                    "{} is already {}",
+                    path.display(),
+                    plan.output_format
+                )));
+            }
+            return Ok(vec![(path.to_path_buf(), plan)]);
+        }
+        let mut entries = Vec::new();
+        walk_dir(path, &mut entries);
+        let mut files = Vec::new();
+        for entry in entries {
+            let file = entry.path();
+            if let Some(plan) = self.plan(&file)? {
+                if plan.derives_from(&file) {
+                    files.push((file, plan));
+                }
+            }
+        }
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(files)
+    }
+
+    fn derive_files(
+        &self,
+        files: &[(PathBuf, Plan)],
+        changed: &[PathBuf],
+        input_options: &InputOptions,
+        checks: &Checks,
+    ) {
+        for (file, plan) in files.iter().filter(|(file, _)| changed.contains(file)) {
+            match derive_file(file, plan, input_options, checks) {
+                Ok(warnings) => {
+                    self.warn(Some(file), &warnings);
+                    if self.error_format == ErrorFormat::Human {
+                        eprintln!(
+                            "wrote {}",
+                            file.with_extension(plan.output_format.to_string())
+                                .display()
+                        );
+                    }
+                }
+                Err(e) => self.report(&e),
+            }
+        }
+    }
+
+    // The settings are written as a .x2y.toml would set them, with the
+    // defaults for those that aren't set.
+    fn run_config_show(&self, file: Option<&Path>) -> Result<(), X2YError> {
+        let Value::Mapping(mut settings) = self.settings(file).to_value() else {
+            unreachable!("settings are a mapping");
+        };
+        let source = match self.config() {
+            Some(config) => {
+                for (key, globs) in [("include", &config.include), ("exclude", &config.exclude)] {
+                    if !globs.is_empty() {
+                        let globs = globs
+                            .iter()
+                            .map(|glob| Value::String(glob.to_string()))
+                            .collect();
+                        settings.insert(key.to_string(), Value::Sequence(globs));
+                    }
+                }
+                format!("# from {}\n", config.path.display())
+            }
+            None => format!("# no {} found\n", config::FILE_NAME),
+        };
+        let output = transcoder::encode(
+            &Value::Mapping(settings),
+            Format::Toml,
+            &OutputOptions::default(),
+        )?;
+        io::stdout().write_all(source.as_bytes())?;
+        io::stdout().write_all(output.as_bytes())?;
         Ok(())
     }
+
+    fn load_schema(&self, file: &str) -> Result<Schema, X2YError> {
+        let (schema, _) = self.decode_document(file)?;
+        Schema::new(schema).map_err(|e| e.with_path(Path::new(file)))
+    }
+
+    // Reads a file, or stdin for -, along with its format.
+    fn read_document(&self, file: &str) -> Result<(String, Format), X2YError> {
+        if file == "-" {
+            return self.read_stdin();
+        }
+        let file = Path::new(file);
+        let format = self.file_format(file)?;
+        let contents = limits::read_to_string(file, &self.input_options().limits)?;
+        Ok((contents, format))
+    }
+
+    fn decode_document(&self, file: &str) -> Result<(Value, Format), X2YError> {
+        let (contents, format) = self.read_document(file)?;
+        let value = transcoder::decode_with(&contents, format, &self.input_options())
+            .map_err(|e| e.with_path(Path::new(file)))?;
+        Ok((value, format))
+    }
+
+    // Subcommands write in the format of their input unless -y is given.
+    fn output_format_or(&self, format: Format) -> Format {
+        self.settings(None).output_format.unwrap_or(format)
+    }
+
+    fn read_stdin(&self) -> Result<(String, Format), X2YError> {
+        let contents = limits::read(io::stdin(), &self.input_options().limits)?;
+        let format = match self.settings(None).input_format {
+            Some(format) => format,
+            None => self.guess_format("stdin", &contents)?,
+        };
+        let contents = String::from_utf8(contents)
+            .map_err(|_| X2YError::InvalidInput("stdin is not valid utf-8".to_string()))?;
+        Ok((contents, format))
+    }
+
+    // -x, or the input format in .x2y.toml, takes precedence over the
+    // extension, which takes precedence over the content.
+    fn file_format(&self, path: &Path) -> Result<Format, X2YError> {
+        if let Some(format) = self.settings(Some(path)).input_format {
+            return Ok(format);
+        }
+        match Format::try_from(path) {
+            Ok(format) => Ok(format),
+            Err(_) => {
+                let contents = limits::read_file(path, &self.input_options().limits)?;
+                self.guess_format(&path.display().to_string(), &contents)
+            }
+        }
+    }
+
+    fn guess_format(&self, name: &str, contents: &[u8]) -> Result<Format, X2YError> {
+        let detection = Format::detect(contents);
+        let format = detection.format()?;
+        log::info!("Detected {} for {}: {:?}", format, name, detection);
+        if detection.is_ambiguous() && self.error_format == ErrorFormat::Human {
+            let alternatives: Vec<String> = detection.candidates()[1..]
+                .iter()
+                .map(|c| c.content.to_string())
+                .collect();
+            eprint!("note: guessed {} for {}", format, name);
+            if !alternatives.is_empty() {
+                eprint!(", it could also be {}", alternatives.join(" or "));
+            }
+            eprintln!(". Use -x to choose the format.");
+        }
+        Ok(format)
+    }
+}
+
+// Directories are replaced by the supported files inside them.
+fn collect_files(paths: &[String]) -> Vec<String> {
+    let mut files = Vec::new();
+    for path in paths {
+        if Path::new(path).is_dir() {
+            let mut entries = Vec::new();
+            walk_dir(Path::new(path), &mut entries);
+            files.extend(entries.iter().map(|e| e.path().display().to_string()));
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files
+}
+
+// Edits replace the file, or go to stdout if the input was stdin.
+fn write_document(file: &Path, output: &str) -> Result<(), X2YError> {
+    if file == Path::new("-") {
+        io::stdout().write_all(output.as_bytes())?;
+    } else {
+        write_atomically(file, output)?;
+    }
+    Ok(())
+}
+
+// Unlike a conversion, a query may be written in the format it was read from.
+fn query_document(
+    contents: &str,
+    input_format: Format,
+    output_format: Format,
+    query: &Query,
+    input_options: &InputOptions,
+    options: &OutputOptions,
+) -> Result<String, X2YError> {
+    let mut value = query.apply(&transcoder::decode_with(
+        contents,
+        input_format,
+        input_options,
+    )?)?;
+    if options.sort_keys {
+        value.sort_keys();
+    }
+    transcoder::encode(&value, output_format, options)
 }
@@ -0,0 +1,310 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::normalize;
+use crate::error::X2YError;
+use crate::format::Format;
+use crate::options::OutputOptions;
+use crate::transcoder;
+use crate::value::{Mapping, Value};
+
+// The cache lets a directory be converted again cheaply: a source is skipped
+// when it, the options it was converted with and its output are all as they
+// were. It's JSON, with the paths relative to its own directory so the tree
+// can be moved, and it's discarded when it was written by another version of
+// x2y. The hashes are 64-bit FNV-1a, which are stable across platforms and
+// Rust releases, and only need to tell apart versions of the same file.
+
+/// The name of the cache in the converted directory, unless another is given.
+pub const FILE_NAME: &str = ".x2y-cache";
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Entry {
+    source: u64,
+    options: u64,
+    output: PathBuf,
+    output_hash: u64,
+}
+
+/// The sources converted by earlier runs and what they were converted to.
+#[derive(Clone, Debug)]
+pub struct Cache {
+    path: PathBuf,
+    directory: PathBuf,
+    entries: BTreeMap<PathBuf, Entry>,
+    // Outputs replaced by an output elsewhere, removed by `prune`.
+    orphans: Vec<(PathBuf, u64)>,
+}
+
+impl Cache {
+    /// The cache at `path`, empty if there's none yet or it can't be used.
+    pub fn load(path: &Path) -> Result<Cache, X2YError> {
+        let path = absolute(path);
+        let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut cache = Cache {
+            path,
+            directory,
+            entries: BTreeMap::new(),
+            orphans: Vec::new(),
+        };
+        let contents = match fs::read_to_string(&cache.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(cache),
+            Err(e) => return Err(X2YError::io(&cache.path, e)),
+        };
+        match cache.read(&contents) {
+            Some(entries) => cache.entries = entries,
+            None => log::warn!(
+                "Ignoring {}, it isn't a cache for this version",
+                cache.path.display()
+            ),
+        }
+        Ok(cache)
+    }
+
+    // None unless every entry can be read.
+    fn read(&self, contents: &str) -> Option<BTreeMap<PathBuf, Entry>> {
+        let Ok(Value::Mapping(map)) = transcoder::decode(contents, Format::Json) else {
+            return None;
+        };
+        if map.get("version") != Some(&Value::String(VERSION.to_string())) {
+            return None;
+        }
+        let Some(Value::Mapping(files)) = map.get("files") else {
+            return None;
+        };
+        let mut entries = BTreeMap::new();
+        for (source, entry) in files {
+            let Value::Mapping(entry) = entry else {
+                return None;
+            };
+            let hash = |key: &str| match entry.get(key) {
+                Some(Value::String(hex)) => u64::from_str_radix(hex, 16).ok(),
+                _ => None,
+            };
+            let Some(Value::String(output)) = entry.get("output") else {
+                return None;
+            };
+            entries.insert(
+                normalize(&self.directory.join(source)),
+                Entry {
+                    source: hash("source")?,
+                    options: hash("options")?,
+                    output: normalize(&self.directory.join(output)),
+                    output_hash: hash("output_hash")?,
+                },
+            );
+        }
+        Some(entries)
+    }
+
+    /// Whether `file` is the cache itself.
+    pub fn is_cache(&self, file: &Path) -> bool {
+        absolute(file) == self.path
+    }
+
+    /// Whether `source`, with the hash `source_hash`, was converted to `output`
+    /// with the options hashed to `options`, and the output hasn't changed
+    /// since.
+    pub fn is_fresh(&self, source: &Path, source_hash: u64, output: &Path, options: u64) -> bool {
+        self.entries.get(&absolute(source)).is_some_and(|entry| {
+            entry.source == source_hash
+                && entry.options == options
+                && entry.output == absolute(output)
+                && hash_file(output).is_ok_and(|hash| hash == entry.output_hash)
+        })
+    }
+
+    /// Records that `source` was converted to `output`, which is read to
+    /// notice if it's changed later.
+    pub fn record(
+        &mut self,
+        source: &Path,
+        source_hash: u64,
+        output: &Path,
+        options: u64,
+    ) -> Result<(), X2YError> {
+        let entry = Entry {
+            source: source_hash,
+            options,
+            output: absolute(output),
+            output_hash: hash_file(output)?,
+        };
+        if let Some(previous) = self.entries.insert(absolute(source), entry) {
+            if previous.output != absolute(output) {
+                self.orphans.push((previous.output, previous.output_hash));
+            }
+        }
+        Ok(())
+    }
+
+    /// Forgets the sources that aren't in `sources` and removes the outputs
+    /// they, and replaced outputs, left behind. An output that was changed
+    /// since it was written, or that is now a source, is kept. Returns the
+    /// outputs removed.
+    pub fn prune(&mut self, sources: &[PathBuf]) -> Vec<PathBuf> {
+        let sources: Vec<PathBuf> = sources.iter().map(|source| absolute(source)).collect();
+        let mut stale = std::mem::take(&mut self.orphans);
+        self.entries.retain(|source, entry| {
+            let keep = sources.contains(source);
+            if !keep {
+                stale.push((entry.output.clone(), entry.output_hash));
+            }
+            keep
+        });
+        let mut removed = Vec::new();
+        for (output, hash) in stale {
+            let written = hash_file(&output).is_ok_and(|current| current == hash);
+            if written
+                && !sources.contains(&output)
+                && !self.entries.values().any(|entry| entry.output == output)
+                && fs::remove_file(&output).is_ok()
+            {
+                log::info!("Removed stale output {}", output.display());
+                removed.push(output);
+            }
+        }
+        removed
+    }
+
+    pub fn save(&self) -> Result<(), X2YError> {
+        let mut files = Mapping::new();
+        for (source, entry) in &self.entries {
+            let mut map = Mapping::new();
+            let hex = |hash: u64| Value::String(format!("{:016x}", hash));
+            map.insert("source".to_string(), hex(entry.source));
+            map.insert("options".to_string(), hex(entry.options));
+            map.insert(
+                "output".to_string(),
+                Value::String(self.relative(&entry.output)),
+            );
+            map.insert("output_hash".to_string(), hex(entry.output_hash));
+            files.insert(self.relative(source), Value::Mapping(map));
+        }
+        let mut map = Mapping::new();
+        map.insert("version".to_string(), Value::String(VERSION.to_string()));
+        map.insert("files".to_string(), Value::Mapping(files));
+        let contents = transcoder::encode(
+            &Value::Mapping(map),
+            Format::Json,
+            &OutputOptions::default(),
+        )?;
+        fs::write(&self.path, contents).map_err(|e| X2YError::io(&self.path, e))
+    }
+
+    // Paths outside the directory of the cache stay absolute.
+    fn relative(&self, path: &Path) -> String {
+        match path.strip_prefix(&self.directory) {
+            Ok(relative) => relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join("/"),
+            Err(_) => path.display().to_string(),
+        }
+    }
+}
+
+/// The 64-bit FNV-1a hash of `bytes`.
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub fn hash_file(path: &Path) -> Result<u64, X2YError> {
+    let contents = fs::read(path).map_err(|e| X2YError::io(path, e))?;
+    Ok(hash(&contents))
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    normalize(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_are_fnv_1a() {
+        assert_eq!(hash(b""), 0xcbf29ce484222325);
+        assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(hash(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn a_conversion_is_fresh_until_anything_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("a.yaml");
+        let output = dir.path().join("a.json");
+        fs::write(&source, "a: 1\n").unwrap();
+        fs::write(&output, "{\"a\": 1}\n").unwrap();
+        let path = dir.path().join(FILE_NAME);
+        let source_hash = hash_file(&source).unwrap();
+
+        let mut cache = Cache::load(&path).unwrap();
+        assert!(!cache.is_fresh(&source, source_hash, &output, 1));
+        cache.record(&source, source_hash, &output, 1).unwrap();
+        cache.save().unwrap();
+
+        let cache = Cache::load(&path).unwrap();
+        assert!(cache.is_fresh(&source, source_hash, &output, 1));
+        assert!(!cache.is_fresh(&source, source_hash + 1, &output, 1));
+        assert!(!cache.is_fresh(&source, source_hash, &output, 2));
+        assert!(!cache.is_fresh(&source, source_hash, &source.with_extension("toml"), 1));
+        fs::write(&output, "{\"a\": 2}\n").unwrap();
+        assert!(!cache.is_fresh(&source, source_hash, &output, 1));
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains("\"a.yaml\": {\n"));
+    }
+
+    #[test]
+    fn outputs_left_behind_are_removed_unless_they_were_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = Cache::load(&dir.path().join(FILE_NAME)).unwrap();
+        let mut record = |name: &str, output: &str| {
+            let source = dir.path().join(name);
+            let output = dir.path().join(output);
+            fs::write(&source, name).unwrap();
+            fs::write(&output, output.display().to_string()).unwrap();
+            cache.record(&source, 0, &output, 0).unwrap();
+            source
+        };
+        let kept = record("kept.yaml", "kept.json");
+        record("removed.yaml", "removed.json");
+        record("changed.yaml", "changed.json");
+        record("kept.yaml", "kept.toml");
+        fs::write(dir.path().join("changed.json"), "edited").unwrap();
+
+        let removed = cache.prune(&[kept]);
+
+        assert_eq!(
+            removed,
+            vec![
+                absolute(&dir.path().join("kept.json")),
+                absolute(&dir.path().join("removed.json")),
+            ]
+        );
+        assert!(dir.path().join("kept.toml").exists());
+        assert!(dir.path().join("changed.json").exists());
+    }
+
+    #[test]
+    fn a_cache_from_another_version_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FILE_NAME);
+        fs::write(
+            &path,
+            "{\"version\": \"0.0.0\", \"files\": {\"a.yaml\": {}}}",
+        )
+        .unwrap();
+
+        let cache = Cache::load(&path).unwrap();
+
+        assert!(cache.entries.is_empty());
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::HashSet;
+
+use serde_yaml::Value as YamlValue;
+
+use crate::emitter::YAML_1_1_BOOLEANS;
+use crate::error::Violation;
+use crate::format::Format;
+use crate::options::YamlTags;
+use crate::transcoder::Node;
+use crate::value::{display_path, index_path, key_path, Value};
+
+// Finds the values a conversion changes to fit the output format, so they can
+// be reported as warnings or, with `--strict`, refused. Values the output
+// format can't hold at all, such as nulls in TOML, are errors either way.
+
+/// Every value in `value` that is changed by converting it to
+/// `output_format`, along with what `yaml` found in the source if it was
+/// decoded from YAML.
+pub fn coercions(
+    yaml: Option<&YamlFindings>,
+    value: &Value,
+    output_format: Format,
+) -> Vec<Violation> {
+    let no_booleans = HashSet::new();
+    let (mut warnings, plain_booleans) = match yaml {
+        Some(findings) => (findings.changes.clone(), &findings.plain_booleans),
+        None => (Vec::new(), &no_booleans),
+    };
+    let mut coercion = Coercion {
+        output_format,
+        plain_booleans,
+        warnings: &mut warnings,
+    };
+    coercion.value(value, "");
+    warnings
+}
+
+/// What decoding a YAML document changes and what it leaves ambiguous, which
+/// only the source shows.
+pub(crate) struct YamlFindings {
+    /// The mapping keys that aren't strings and the tags that are dropped.
+    pub(crate) changes: Vec<Violation>,
+    // The paths of the values written as plain, untagged YAML 1.1 booleans,
+    // such as `key: yes` or `[on, off]`. Quoted ones were meant as strings.
+    // Only the parser's events tell plain scalars from quoted ones, the decoded
+    // values don't.
+    plain_booleans: HashSet<String>,
+}
+
+impl YamlFindings {
+    /// Looks through `value`, read from the source as `node`.
+    pub(crate) fn new(value: &YamlValue, node: &Node, tags: YamlTags) -> YamlFindings {
+        let mut findings = YamlFindings {
+            changes: Vec::new(),
+            plain_booleans: HashSet::new(),
+        };
+        findings.find(value, node, "", tags);
+        findings
+    }
+
+    fn find(&mut self, value: &YamlValue, node: &Node, path: &str, tags: YamlTags) {
+        match value {
+            YamlValue::String(s)
+                if node.is_plain_scalar() && YAML_1_1_BOOLEANS.contains(&s.as_str()) =>
+            {
+                self.plain_booleans.insert(path.to_string());
+            }
+            YamlValue::Sequence(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.find(item, node.item(i), &index_path(path, i), tags);
+                }
+            }
+            YamlValue::Mapping(map) => {
+                for (i, (key, value)) in map.iter().enumerate() {
+                    let Some((name, kind)) = yaml_key(key) else {
+                        continue;
+                    };
+                    let path = key_path(path, &name);
+                    if let Some(kind) = kind {
+                        warn(
+                            &mut self.changes,
+                            &path,
+                            format!("the key was {}, is now a string", kind),
+                        );
+                    }
+                    if let YamlValue::Tagged(tagged) = key {
+                        warn(
+                            &mut self.changes,
+                            &path,
+                            format!("the tag {} on the key was dropped", tagged.tag),
+                        );
+                    }
+                    self.find(value, node.value(i), &path, tags);
+                }
+            }
+            YamlValue::Tagged(tagged) => {
+                let path = if tags == YamlTags::Wrap {
+                    key_path(path, &tagged.tag.to_string())
+                } else {
+                    warn(
+                        &mut self.changes,
+                        path,
+                        format!("the tag {} was dropped", tagged.tag),
+                    );
+                    path.to_string()
+                };
+                self.find(&tagged.value, node, &path, tags);
+            }
+            _ => {}
+        }
+    }
+}
+
+// The key as it's written in the output, along with its type when that isn't
+// a string.
+fn yaml_key(key: &YamlValue) -> Option<(String, Option<&'static str>)> {
+    match key {
+        YamlValue::String(s) => Some((s.clone(), None)),
+        YamlValue::Null => Some(("null".to_string(), Some("null"))),
+        YamlValue::Bool(b) => Some((b.to_string(), Some("a boolean"))),
+        YamlValue::Number(n) => Some((n.to_string(), Some("a number"))),
+        YamlValue::Tagged(tagged) => yaml_key(&tagged.value),
+        _ => None,
+    }
+}
+
+struct Coercion<'a> {
+    output_format: Format,
+    plain_booleans: &'a HashSet<String>,
+    warnings: &'a mut Vec<Violation>,
+}
+
+impl Coercion<'_> {
+    fn value(&mut self, value: &Value, path: &str) {
+        match value {
+            Value::String(s) if self.plain_booleans.contains(path) => warn(
+                self.warnings,
+                path,
+                format!("`{}` is a string, YAML 1.1 reads it as a boolean", s),
+            ),
+            Value::Float(f) if !f.is_finite() && self.output_format == Format::Json => warn(
+                self.warnings,
+                path,
+                format!("json has no {}, it's written as null", f),
+            ),
+            Value::Datetime(s) if self.output_format != Format::Toml => warn(
+                self.warnings,
+                path,
+                format!(
+                    "{} has no datetimes, `{}` is written as a string",
+                    self.output_format, s
+                ),
+            ),
+            Value::Sequence(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.value(item, &index_path(path, i));
+                }
+            }
+            Value::Mapping(map) => {
+                for (key, value) in map {
+                    self.value(value, &key_path(path, key));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn warn(warnings: &mut Vec<Violation>, path: &str, message: String) {
+    warnings.push(Violation {
+        value_path: display_path(path).to_string(),
+        message,
+        location: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::InputOptions;
+    use crate::transcoder::decode_inspected;
+
+    fn coerced(
+        contents: &str,
+        input_format: Format,
+        output_format: Format,
+        yaml_tags: YamlTags,
+    ) -> Vec<Violation> {
+        let options = InputOptions {
+            yaml_tags,
+            ..Default::default()
+        };
+        let (value, yaml) = decode_inspected(contents, input_format, &options).unwrap();
+        coercions(yaml.as_ref(), &value, output_format)
+    }
+
+    fn warnings(contents: &str, input_format: Format, output_format: Format) -> Vec<String> {
+        coerced(contents, input_format, output_format, YamlTags::Drop)
+            .iter()
+            .map(|w| format!("{}: {}", w.value_path, w.message))
+            .collect()
+    }
+
+    #[test]
+    fn yaml_coercions_are_found() {
+        let contents = "1: one\nenabled: yes\nquoted: 'on'\nref: !Ref bucket\nnan: .nan\n";
+
+        assert_eq!(
+            warnings(contents, Format::Yaml, Format::Json),
+            vec![
+                ".\"1\": the key was a number, is now a string",
+                ".ref: the tag !Ref was dropped",
+                ".enabled: `yes` is a string, YAML 1.1 reads it as a boolean",
+                ".nan: json has no NaN, it's written as null",
+            ]
+        );
+    }
+
+    #[test]
+    fn only_plain_yaml_1_1_booleans_are_reported() {
+        let contents =
+            "a: yes\nb: \"yes\"\nc: [on, 'off', Off]\nd: {e: no}\nf:\n  - \"y\"\n  - N # no\n";
+
+        assert_eq!(
+            warnings(contents, Format::Yaml, Format::Json),
+            vec![
+                ".a: `yes` is a string, YAML 1.1 reads it as a boolean",
+                ".c[0]: `on` is a string, YAML 1.1 reads it as a boolean",
+                ".c[2]: `Off` is a string, YAML 1.1 reads it as a boolean",
+                ".d.e: `no` is a string, YAML 1.1 reads it as a boolean",
+                ".f[1]: `N` is a string, YAML 1.1 reads it as a boolean",
+            ]
+        );
+        let tagged = "g: !Ref {h: yes}\n";
+        let wrapped: Vec<String> = coerced(tagged, Format::Yaml, Format::Json, YamlTags::Wrap)
+            .iter()
+            .map(|w| w.value_path.clone())
+            .collect();
+        assert_eq!(wrapped, vec![".g.\"!Ref\".h"]);
+    }
+
+    #[test]
+    fn yaml_1_1_booleans_are_reported_where_they_are_merged() {
+        let contents = "base: &base {a: on, b: 'off'}\nuses:\n  <<: *base\n  c: [*base]\n";
+
+        assert_eq!(
+            warnings(contents, Format::Yaml, Format::Json),
+            vec![
+                ".base.a: `on` is a string, YAML 1.1 reads it as a boolean",
+                ".uses.a: `on` is a string, YAML 1.1 reads it as a boolean",
+                ".uses.c[0].a: `on` is a string, YAML 1.1 reads it as a boolean",
+            ]
+        );
+    }
+
+    #[test]
+    fn output_coercions_depend_on_the_format() {
+        let contents = "date = 1979-05-27\ninf = inf\n";
+
+        assert_eq!(
+            warnings(contents, Format::Toml, Format::Yaml),
+            vec![".date: yaml has no datetimes, `1979-05-27` is written as a string"]
+        );
+        // Integers TOML can't hold are an error rather than a lossy float.
+        assert!(warnings("big: 18446744073709551615", Format::Yaml, Format::Toml).is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+use clap::{Arg, Command, ValueHint};
+use clap_complete::Shell;
+
+// clap_complete completes any file where a path is expected. x2y only reads
+// YAML, JSON and TOML, so the bash, zsh and fish scripts are changed to offer
+// directories and files with those extensions instead. The other shells keep
+// completing any file.
+
+const EXTENSIONS: [&str; 4] = ["yaml", "yml", "json", "toml"];
+
+const BASH_DOCUMENTS: &str = r#"
+# The directories and the files x2y reads that start with $1, one per line.
+_x2y_documents() {
+    local file
+    compgen -f -- "$1" | while IFS= read -r file; do
+        case "${file}" in
+            *.yaml|*.yml|*.json|*.toml)
+                printf '%s\n' "${file}"
+                ;;
+            *)
+                if [[ -d "${file}" ]]; then
+                    printf '%s\n' "${file}"
+                fi
+                ;;
+        esac
+    done
+}
+
+# Where only options or nothing at all are offered for a word that isn't an
+# option, which is where a positional argument goes, the files x2y reads are.
+_x2y_or_documents() {
+    _x2y "$@"
+    local cur="${COMP_WORDS[COMP_CWORD]}" reply
+    if [[ "${cur}" == -* ]]; then
+        return
+    fi
+    for reply in "${COMPREPLY[@]}"; do
+        if [[ "${reply}" != -* ]]; then
+            return
+        fi
+    done
+    local IFS=$'\n'
+    COMPREPLY=($(_x2y_documents "${cur}"))
+    if [[ "${BASH_VERSINFO[0]}" -ge 4 ]]; then
+        compopt -o filenames
+    fi
+}
+"#;
+
+const FISH_DOCUMENTS: &str = r#"
+# The directories and the files x2y reads.
+function __fish_x2y_documents
+    __fish_complete_suffix .yaml
+    __fish_complete_suffix .yml
+    __fish_complete_suffix .json
+    __fish_complete_suffix .toml
+end
+"#;
+
+/// The completion script for `shell`, generated from `command`.
+pub fn script(shell: Shell, command: &mut Command) -> String {
+    // Generated into a buffer first, `generate` panics if it can't write.
+    let mut script = Vec::new();
+    clap_complete::generate(shell, command, "x2y", &mut script);
+    let script = String::from_utf8_lossy(&script).into_owned();
+    let options = path_options(command, true);
+    match shell {
+        Shell::Bash => bash(script, &options),
+        Shell::Zsh => zsh(script, &path_options(command, false)),
+        Shell::Fish => fish(script, &options, command),
+        _ => script,
+    }
+}
+
+fn is_path(arg: &Arg) -> bool {
+    matches!(
+        arg.get_value_hint(),
+        ValueHint::FilePath | ValueHint::AnyPath
+    )
+}
+
+// Whether the argument is a file x2y reads. `--cache` names x2y's own file.
+fn is_document(arg: &Arg) -> bool {
+    is_path(arg) && arg.get_id() != "cache"
+}
+
+// The long names of the options, in any subcommand, whose paths are
+// `documents` or not.
+fn path_options(command: &Command, documents: bool) -> Vec<String> {
+    let mut names: Vec<String> = command
+        .get_opts()
+        .filter(|arg| is_path(arg) && is_document(arg) == documents)
+        .filter_map(|arg| arg.get_long().map(str::to_string))
+        .collect();
+    for subcommand in command.get_subcommands() {
+        names.extend(path_options(subcommand, documents));
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+// The options' values come from a `case` on the previous word, positional
+// arguments are left to `-o default`, which the wrapper takes over.
+fn bash(script: String, options: &[String]) -> String {
+    let mut output = String::new();
+    let mut in_document_option = false;
+    for line in script.lines() {
+        let trimmed = line.trim();
+        if let Some(option) = trimmed.strip_prefix("--").and_then(|s| s.strip_suffix(')')) {
+            in_document_option = options.iter().any(|name| name == option);
+        } else if trimmed == ";;" {
+            in_document_option = false;
+        }
+        let line = if in_document_option {
+            line.replace(
+                r#"COMPREPLY=($(compgen -f "${cur}"))"#,
+                r#"COMPREPLY=($(_x2y_documents "${cur}"))"#,
+            )
+        } else {
+            line.replace("complete -F _x2y ", "complete -F _x2y_or_documents ")
+        };
+        output.push_str(&line);
+        output.push('\n');
+    }
+    output.push_str(BASH_DOCUMENTS);
+    output
+}
+
+// Every path argument is an `_files` action, only those for documents are
+// limited to their extensions.
+fn zsh(script: String, other_options: &[String]) -> String {
+    let filtered = format!("_files -g \"*.({})\"'", EXTENSIONS.join("|"));
+    let mut output = String::new();
+    for line in script.lines() {
+        let other = other_options
+            .iter()
+            .any(|name| line.starts_with(&format!("'--{}=", name)));
+        if other {
+            output.push_str(line);
+        } else {
+            output.push_str(&line.replace("_files'", &filtered));
+        }
+        output.push('\n');
+    }
+    output
+}
+
+// Options taking a path force file completion with `-F`, positional arguments
+// get it by default, so rules offering the documents instead are added for
+// the commands that take them.
+fn fish(script: String, options: &[String], command: &Command) -> String {
+    let mut output = String::new();
+    for line in script.lines() {
+        let document_option = options
+            .iter()
+            .any(|name| line.contains(&format!(" -l {} ", name)));
+        match line.strip_suffix(" -r -F") {
+            Some(start) if document_option => {
+                output.push_str(start);
+                output.push_str(" -r -f -a \"(__fish_x2y_documents)\"");
+            }
+            _ => output.push_str(line),
+        }
+        output.push('\n');
+    }
+    output.push_str(FISH_DOCUMENTS);
+    let mut conditions = Vec::new();
+    if command.get_positionals().any(is_document) {
+        conditions.push("__fish_x2y_needs_command".to_string());
+    }
+    for subcommand in command.get_subcommands() {
+        let name = subcommand.get_name();
+        if subcommand.get_positionals().any(is_document) {
+            conditions.push(format!("__fish_x2y_using_subcommand {}", name));
+        }
+        for nested in subcommand.get_subcommands() {
+            if nested.get_positionals().any(is_document) {
+                conditions.push(format!(
+                    "__fish_x2y_using_subcommand {}; and __fish_seen_subcommand_from {}",
+                    name,
+                    nested.get_name()
+                ));
+            }
+        }
+    }
+    for condition in conditions {
+        output.push_str(&format!(
+            "complete -c x2y -n \"{}\" -f -a \"(__fish_x2y_documents)\"\n",
+            condition
+        ));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Args;
+    use clap::CommandFactory;
+
+    fn lines(shell: Shell, needle: &str) -> Vec<String> {
+        script(shell, &mut Args::command())
+            .lines()
+            .filter(|line| line.contains(needle))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn zsh_completes_documents_except_for_the_cache() {
+        let files = lines(Shell::Zsh, "_files");
+
+        assert!(files.iter().any(|line| line.starts_with("'--schema=")
+            && line.ends_with(":_files -g \"*.(yaml|yml|json|toml)\"' \\")));
+        assert!(files
+            .iter()
+            .any(|line| line.starts_with("'--cache=") && line.ends_with(":_files' \\")));
+        assert_eq!(
+            files
+                .iter()
+                .filter(|line| line.ends_with(":_files' \\"))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn fish_completes_documents_for_options_and_arguments() {
+        let documents = lines(Shell::Fish, "(__fish_x2y_documents)");
+
+        assert!(documents.iter().any(|line| line.contains(" -l schema ")
+            && line.ends_with(" -r -f -a \"(__fish_x2y_documents)\"")));
+        assert!(documents.contains(
+            &"complete -c x2y -n \"__fish_x2y_using_subcommand merge\" -f -a \"(__fish_x2y_documents)\""
+                .to_string()
+        ));
+        assert!(!documents.iter().any(|line| line.contains("completions")));
+        assert_eq!(lines(Shell::Fish, " -l cache ").len(), 1);
+        assert!(lines(Shell::Fish, " -l cache ")[0].ends_with(" -r -F"));
+    }
+}
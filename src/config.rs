@@ -0,0 +1,471 @@
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use clap::ValueEnum;
+use globset::{GlobBuilder, GlobMatcher};
+
+use crate::error::X2YError;
+use crate::format::Format;
+use crate::options::{LineEnding, OutputOptions, QuoteStyle, SequenceStyle, TableStyle};
+use crate::transcoder;
+use crate::value::{Mapping, Value};
+
+// A `.x2y.toml` holds the flags a project would otherwise repeat on every
+// run. It's found by looking in the current directory and then each of its
+// parents. Each key is the default for the flag of the same name, rules
+// change them for the files matching a glob, and flags on the command line
+// take precedence over both.
+
+/// The name of the file looked for.
+pub const FILE_NAME: &str = ".x2y.toml";
+
+/// Formats and output style, each `None` unless it's set.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Settings {
+    pub input_format: Option<Format>,
+    pub output_format: Option<Format>,
+    pub sort_keys: Option<bool>,
+    pub indent: Option<usize>,
+    pub compact: Option<bool>,
+    pub canonical: Option<bool>,
+    pub yaml_sequences: Option<SequenceStyle>,
+    pub yaml_anchors: Option<bool>,
+    pub quote_style: Option<QuoteStyle>,
+    pub toml_tables: Option<TableStyle>,
+    pub trailing_newline: Option<bool>,
+    pub line_ending: Option<LineEnding>,
+}
+
+impl Settings {
+    /// Replaces every setting that `other` sets.
+    pub fn overlay(&mut self, other: &Settings) {
+        self.input_format = other.input_format.or(self.input_format);
+        self.output_format = other.output_format.or(self.output_format);
+        self.sort_keys = other.sort_keys.or(self.sort_keys);
+        self.indent = other.indent.or(self.indent);
+        self.compact = other.compact.or(self.compact);
+        self.canonical = other.canonical.or(self.canonical);
+        self.yaml_sequences = other.yaml_sequences.or(self.yaml_sequences);
+        self.yaml_anchors = other.yaml_anchors.or(self.yaml_anchors);
+        self.quote_style = other.quote_style.or(self.quote_style);
+        self.toml_tables = other.toml_tables.or(self.toml_tables);
+        self.trailing_newline = other.trailing_newline.or(self.trailing_newline);
+        self.line_ending = other.line_ending.or(self.line_ending);
+    }
+
+    /// The output options, with the default for each one that isn't set.
+    pub fn output_options(&self) -> OutputOptions {
+        let default = OutputOptions::default();
+        OutputOptions {
+            sort_keys: self.sort_keys.unwrap_or(default.sort_keys),
+            indent: self.indent.or(default.indent),
+            compact: self.compact.unwrap_or(default.compact),
+            canonical: self.canonical.unwrap_or(default.canonical),
+            sequence_style: self.yaml_sequences.unwrap_or(default.sequence_style),
+            yaml_anchors: self.yaml_anchors.unwrap_or(default.yaml_anchors),
+            quote_style: self.quote_style.unwrap_or(default.quote_style),
+            table_style: self.toml_tables.unwrap_or(default.table_style),
+            trailing_newline: self.trailing_newline.unwrap_or(default.trailing_newline),
+            line_ending: self.line_ending.unwrap_or(default.line_ending),
+        }
+    }
+
+    /// The settings as a mapping, with the default for each output option
+    /// that isn't set.
+    pub fn to_value(&self) -> Value {
+        let options = self.output_options();
+        let mut map = Mapping::new();
+        let mut insert = |key: &str, value: Option<Value>| {
+            if let Some(value) = value {
+                map.insert(key.to_string(), value);
+            }
+        };
+        insert("input_format", self.input_format.map(name));
+        insert("output_format", self.output_format.map(name));
+        insert("sort_keys", Some(Value::Bool(options.sort_keys)));
+        insert("indent", options.indent.map(|i| Value::Integer(i as i128)));
+        insert("compact", Some(Value::Bool(options.compact)));
+        insert("canonical", Some(Value::Bool(options.canonical)));
+        insert("yaml_sequences", Some(name(options.sequence_style)));
+        insert("yaml_anchors", Some(Value::Bool(options.yaml_anchors)));
+        insert("quote_style", Some(name(options.quote_style)));
+        insert("toml_tables", Some(name(options.table_style)));
+        insert(
+            "trailing_newline",
+            Some(Value::Bool(options.trailing_newline)),
+        );
+        insert("line_ending", Some(name(options.line_ending)));
+        Value::Mapping(map)
+    }
+
+    fn read(map: &Mapping, file: &Path, other_keys: &[&str]) -> Result<Settings, X2YError> {
+        let mut settings = Settings::default();
+        for (key, value) in map {
+            let key = key.as_str();
+            match key {
+                "input_format" => settings.input_format = Some(choice(file, key, value)?),
+                "output_format" => settings.output_format = Some(choice(file, key, value)?),
+                "sort_keys" => settings.sort_keys = Some(boolean(file, key, value)?),
+                "indent" => settings.indent = Some(count(file, key, value)?),
+                "compact" => settings.compact = Some(boolean(file, key, value)?),
+                "canonical" => settings.canonical = Some(boolean(file, key, value)?),
+                "yaml_sequences" => settings.yaml_sequences = Some(choice(file, key, value)?),
+                "yaml_anchors" => settings.yaml_anchors = Some(boolean(file, key, value)?),
+                "quote_style" => settings.quote_style = Some(choice(file, key, value)?),
+                "toml_tables" => settings.toml_tables = Some(choice(file, key, value)?),
+                "trailing_newline" => settings.trailing_newline = Some(boolean(file, key, value)?),
+                "line_ending" => settings.line_ending = Some(choice(file, key, value)?),
+                _ if other_keys.contains(&key) => {}
+                _ => return Err(invalid(file, format!("{} is not a setting", key))),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+/// A `.x2y.toml`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Where the file is, globs are relative to its directory.
+    pub path: PathBuf,
+    pub settings: Settings,
+    /// The files converted in a directory, all of them when it's empty.
+    pub include: Vec<Glob>,
+    /// Files in a directory that are left alone even when they're included.
+    pub exclude: Vec<Glob>,
+    pub rules: Vec<Rule>,
+}
+
+/// Settings for the files matching a glob, such as `k8s/**/*.yaml`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub path: Glob,
+    pub settings: Settings,
+}
+
+/// A glob matched against paths relative to the directory of the config and
+/// separated by `/`. `*` and `?` match within a component, `**` matches any
+/// number of them, `[...]` is a character class, `{a,b}` an alternation and
+/// `\` escapes the character after it. A glob without a `/` matches the file
+/// name in any directory.
+#[derive(Clone, Debug)]
+pub struct Glob {
+    glob: String,
+    matcher: GlobMatcher,
+}
+
+impl Glob {
+    pub fn new(glob: &str) -> Result<Glob, globset::Error> {
+        let matcher = GlobBuilder::new(glob)
+            .literal_separator(true)
+            .backslash_escape(true)
+            .build()?
+            .compile_matcher();
+        Ok(Glob {
+            glob: glob.to_string(),
+            matcher,
+        })
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        match self.glob.contains('/') {
+            true => self.matcher.is_match(path),
+            false => self
+                .matcher
+                .is_match(path.rsplit('/').next().unwrap_or(path)),
+        }
+    }
+}
+
+impl PartialEq for Glob {
+    fn eq(&self, other: &Self) -> bool {
+        self.glob == other.glob
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.glob)
+    }
+}
+
+impl Config {
+    /// The `.x2y.toml` in `directory` or the closest of its parents, if
+    /// there is one.
+    pub fn discover(directory: &Path) -> Result<Option<Config>, X2YError> {
+        for directory in directory.ancestors() {
+            let path = directory.join(FILE_NAME);
+            if path.is_file() {
+                return Config::load(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn load(path: &Path) -> Result<Config, X2YError> {
+        let contents = fs::read_to_string(path).map_err(|e| X2YError::io(path, e))?;
+        Config::parse(&contents, path)
+    }
+
+    pub fn parse(contents: &str, path: &Path) -> Result<Config, X2YError> {
+        let value = transcoder::decode(contents, Format::Toml).map_err(|e| e.with_path(path))?;
+        let Value::Mapping(map) = value else {
+            return Err(invalid(path, "the settings should be a table".to_string()));
+        };
+        let settings = Settings::read(&map, path, &["include", "exclude", "rules"])?;
+        let mut rules = Vec::new();
+        if let Some(value) = map.get("rules") {
+            let Value::Sequence(tables) = value else {
+                return Err(invalid(
+                    path,
+                    "rules should be an array of tables".to_string(),
+                ));
+            };
+            for table in tables {
+                let Value::Mapping(table) = table else {
+                    return Err(invalid(path, "each rule should be a table".to_string()));
+                };
+                let Some(Value::String(glob)) = table.get("path") else {
+                    return Err(invalid(path, "each rule needs a path glob".to_string()));
+                };
+                rules.push(Rule {
+                    path: compile(path, glob)?,
+                    settings: Settings::read(table, path, &["path"])?,
+                });
+            }
+        }
+        Ok(Config {
+            path: path.to_path_buf(),
+            settings,
+            include: globs(path, &map, "include")?,
+            exclude: globs(path, &map, "exclude")?,
+            rules,
+        })
+    }
+
+    /// The settings for `file`: the top-level ones with those of every rule
+    /// matching it applied in order, so a later rule wins.
+    pub fn settings_for(&self, file: Option<&Path>) -> Settings {
+        let mut settings = self.settings.clone();
+        if let Some(file) = file.and_then(|file| self.relative(file)) {
+            for rule in self.rules.iter().filter(|rule| rule.path.is_match(&file)) {
+                settings.overlay(&rule.settings);
+            }
+        }
+        settings
+    }
+
+    /// Whether a file found in a directory is converted. The config itself
+    /// never is.
+    pub fn includes(&self, file: &Path) -> bool {
+        let Some(file) = self.relative(file) else {
+            return true;
+        };
+        file != FILE_NAME
+            && (self.include.is_empty() || self.include.iter().any(|glob| glob.is_match(&file)))
+            && !self.exclude.iter().any(|glob| glob.is_match(&file))
+    }
+
+    // The path of `file` from the directory of the config, with `/` between
+    // the components. Files outside that directory have none.
+    fn relative(&self, file: &Path) -> Option<String> {
+        let root = normalize(self.path.parent()?);
+        let file = normalize(&std::path::absolute(file).ok()?);
+        let components: Vec<String> = file
+            .strip_prefix(root)
+            .ok()?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        Some(components.join("/"))
+    }
+}
+
+// Resolves `.` and `..` without touching the filesystem, so symlinked
+// directories keep the path they were reached by.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+fn compile(file: &Path, glob: &str) -> Result<Glob, X2YError> {
+    Glob::new(glob)
+        .map_err(|e| invalid(file, format!("{} is not a valid glob, {}", glob, e.kind())))
+}
+
+fn globs(file: &Path, map: &Mapping, key: &str) -> Result<Vec<Glob>, X2YError> {
+    match map.get(key) {
+        None => Ok(Vec::new()),
+        Some(Value::Sequence(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(glob) => compile(file, glob),
+                _ => Err(invalid(
+                    file,
+                    format!("{} should be an array of globs", key),
+                )),
+            })
+            .collect(),
+        Some(_) => Err(invalid(
+            file,
+            format!("{} should be an array of globs", key),
+        )),
+    }
+}
+
+fn boolean(file: &Path, key: &str, value: &Value) -> Result<bool, X2YError> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        _ => Err(invalid(file, format!("{} should be true or false", key))),
+    }
+}
+
+fn count(file: &Path, key: &str, value: &Value) -> Result<usize, X2YError> {
+    match value {
+        Value::Integer(i) => usize::try_from(*i).ok(),
+        _ => None,
+    }
+    .ok_or_else(|| invalid(file, format!("{} should be a whole number", key)))
+}
+
+fn choice<T: ValueEnum>(file: &Path, key: &str, value: &Value) -> Result<T, X2YError> {
+    match value {
+        Value::String(s) => T::from_str(s, false).ok(),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        let names: Vec<String> = T::value_variants()
+            .iter()
+            .filter_map(ValueEnum::to_possible_value)
+            .map(|value| value.get_name().to_string())
+            .collect();
+        invalid(
+            file,
+            format!("{} should be one of {}", key, names.join(", ")),
+        )
+    })
+}
+
+fn name<T: ValueEnum>(value: T) -> Value {
+    let name = value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default();
+    Value::String(name)
+}
+
+fn invalid(file: &Path, message: String) -> X2YError {
+    X2YError::InvalidInput(format!("{}: {}", file.display(), message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(contents: &str) -> Result<Config, X2YError> {
+        Config::parse(contents, Path::new("/project/.x2y.toml"))
+    }
+
+    fn glob_match(glob: &str, path: &str) -> bool {
+        Glob::new(glob).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn globs_match_components_and_file_names() {
+        assert!(glob_match("k8s/**/*.yaml", "k8s/app/base/deploy.yaml"));
+        assert!(glob_match("k8s/**/*.yaml", "k8s/deploy.yaml"));
+        assert!(!glob_match("k8s/**/*.yaml", "docs/k8s/deploy.yaml"));
+        assert!(glob_match("*.lock.json", "web/package.lock.json"));
+        assert!(glob_match("config/?.toml", "config/a.toml"));
+        assert!(!glob_match("config/*.toml", "config/a/b.toml"));
+    }
+
+    #[test]
+    fn double_stars_match_any_number_of_components() {
+        // At the start.
+        assert!(glob_match("**/deploy.yaml", "deploy.yaml"));
+        assert!(glob_match("**/deploy.yaml", "k8s/app/deploy.yaml"));
+        assert!(!glob_match("**/deploy.yaml", "k8s/app/deploy.yaml.bak"));
+        // In the middle.
+        assert!(glob_match("k8s/**/base/*.yaml", "k8s/base/a.yaml"));
+        assert!(glob_match("k8s/**/base/*.yaml", "k8s/app/x/base/a.yaml"));
+        assert!(!glob_match("k8s/**/base/*.yaml", "k8s/app/base/x/a.yaml"));
+        // At the end.
+        assert!(glob_match("vendor/**", "vendor/a/b.json"));
+        assert!(glob_match("vendor/**", "vendor/b.json"));
+        assert!(!glob_match("vendor/**", "other/vendor/b.json"));
+    }
+
+    #[test]
+    fn globs_have_character_classes_and_escapes() {
+        assert!(glob_match("config/[ab].toml", "config/a.toml"));
+        assert!(!glob_match("config/[ab].toml", "config/c.toml"));
+        assert!(glob_match("config/[!ab].toml", "config/c.toml"));
+        assert!(glob_match("env-[0-9].yaml", "deploy/env-3.yaml"));
+        assert!(glob_match("*.{yaml,yml}", "a/b.yml"));
+        assert!(glob_match("weird\\*.yaml", "weird*.yaml"));
+        assert!(!glob_match("weird\\*.yaml", "weirder.yaml"));
+        assert!(glob_match("what\\?.json", "what?.json"));
+        assert!(!glob_match("what\\?.json", "whatx.json"));
+    }
+
+    #[test]
+    fn rules_matching_a_file_are_applied_in_order() {
+        let config = config(
+            r#"
+output_format = "yaml"
+indent = 4
+
+[[rules]]
+path = "k8s/**/*.yaml"
+output_format = "json"
+indent = 2
+
+[[rules]]
+path = "k8s/legacy/*"
+indent = 8
+"#,
+        )
+        .unwrap();
+
+        let settings = config.settings_for(Some(Path::new("/project/k8s/app/deploy.yaml")));
+        assert_eq!(settings.output_format, Some(Format::Json));
+        assert_eq!(settings.indent, Some(2));
+        let settings = config.settings_for(Some(Path::new("/project/k8s/legacy/old.yaml")));
+        assert_eq!(settings.indent, Some(8));
+        let settings = config.settings_for(Some(Path::new("/project/other.json")));
+        assert_eq!(settings.output_format, Some(Format::Yaml));
+        assert_eq!(settings.indent, Some(4));
+    }
+
+    #[test]
+    fn included_files_can_be_excluded() {
+        let config =
+            config("include = [\"**/*.yaml\", \"*.toml\"]\nexclude = [\"vendor/**\"]\n").unwrap();
+
+        assert!(config.includes(Path::new("/project/a/b.yaml")));
+        assert!(!config.includes(Path::new("/project/a/b.json")));
+        assert!(!config.includes(Path::new("/project/vendor/b.yaml")));
+        assert!(!config.includes(Path::new("/project/.x2y.toml")));
+    }
+
+    #[test]
+    fn mistakes_are_reported_with_the_file() {
+        let message = |contents: &str| config(contents).unwrap_err().to_string();
+
+        assert!(message("indnt = 2").contains("/project/.x2y.toml: indnt is not a setting"));
+        assert!(message("output_format = \"xml\"").contains("should be one of yaml, json, toml"));
+        assert!(message("[[rules]]\nindent = 2").contains("each rule needs a path glob"));
+        assert!(message("exclude = [\"vendor/[a\"]").contains("vendor/[a is not a valid glob"));
+    }
+}
@@ -0,0 +1,282 @@
+use std::fmt;
+
+use crate::error::X2YError;
+use crate::format::Format;
+use crate::options::Limits;
+use crate::transcoder;
+
+use serde_yaml::Value as YamlValue;
+
+// Guesses the format of a file from its contents. Every candidate is given a
+// confidence between 0 and 1, binary formats are recognised by their magic
+// bytes so they can be reported instead of failing to parse.
+
+const MAGIC_BYTES: [(&[u8], &str); 11] = [
+    (b"\x1f\x8b", "gzip"),
+    (b"PK\x03\x04", "zip"),
+    (b"\x28\xb5\x2f\xfd", "zstd"),
+    (b"BZh", "bzip2"),
+    (b"\xfd7zXZ\x00", "xz"),
+    (b"%PDF-", "pdf"),
+    (b"\x89PNG\r\n\x1a\n", "png"),
+    (b"SQLite format 3\x00", "sqlite"),
+    (b"\xd9\xd9\xf7", "cbor"),
+    (b"\xff\xfe", "utf-16"),
+    (b"\xfe\xff", "utf-16"),
+];
+
+// The best guess is ambiguous if the runner-up is at least this close to it.
+const AMBIGUITY_MARGIN: f32 = 0.2;
+
+/// What some content looks like.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Content {
+    Format(Format),
+    Xml,
+    Binary(&'static str),
+}
+
+impl fmt::Display for Content {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Content::Format(format) => write!(f, "{}", format),
+            Content::Xml => write!(f, "xml"),
+            Content::Binary(name) => write!(f, "{} (binary)", name),
+        }
+    }
+}
+
+/// A guess at what some content is, with a confidence between 0 and 1.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub content: Content,
+    pub confidence: f32,
+}
+
+/// The possible formats of some content, most likely first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Detection {
+    candidates: Vec<Candidate>,
+}
+
+impl Detection {
+    /// Every format the content could be, most likely first.
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    /// The most likely format, if the content could be any.
+    pub fn best(&self) -> Option<&Candidate> {
+        self.candidates.first()
+    }
+
+    /// Whether more than one format is a reasonable guess.
+    pub fn is_ambiguous(&self) -> bool {
+        match self.candidates.as_slice() {
+            [] => true,
+            [best] => best.confidence < 0.5,
+            [best, second, ..] => {
+                best.confidence < 0.5 || best.confidence - second.confidence < AMBIGUITY_MARGIN
+            }
+        }
+    }
+
+    /// The most likely format, if it's one that can be transcoded.
+    pub fn format(&self) -> Result<Format, X2YError> {
+        match self.best() {
+            Some(Candidate {
+                content: Content::Format(format),
+                ..
+            }) => Ok(*format),
+            Some(other) => Err(X2YError::InvalidInput(format!(
+                "the input looks like {}, which is not a supported format",
+                other.content
+            ))),
+            None => Err(X2YError::InvalidInput(
+                "unable to detect the format of the input".to_string(),
+            )),
+        }
+    }
+}
+
+pub fn detect(bytes: &[u8]) -> Detection {
+    if let Some((_, name)) = MAGIC_BYTES
+        .iter()
+        .find(|(magic, _)| bytes.starts_with(magic))
+    {
+        return Detection {
+            candidates: vec![Candidate {
+                content: Content::Binary(name),
+                confidence: 1.0,
+            }],
+        };
+    }
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return Detection {
+            candidates: vec![Candidate {
+                content: Content::Binary("unknown"),
+                confidence: 1.0,
+            }],
+        };
+    };
+
+    let mut candidates = vec![
+        Candidate {
+            content: Content::Format(Format::Json),
+            confidence: json_confidence(text),
+        },
+        Candidate {
+            content: Content::Format(Format::Toml),
+            confidence: toml_confidence(text),
+        },
+        Candidate {
+            content: Content::Format(Format::Yaml),
+            confidence: yaml_confidence(text),
+        },
+        Candidate {
+            content: Content::Xml,
+            confidence: xml_confidence(text),
+        },
+    ];
+    candidates.retain(|c| c.confidence > 0.0);
+    // The sort is stable, so ties keep the order above: JSON is the most
+    // specific and YAML the most permissive.
+    candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    Detection { candidates }
+}
+
+fn json_confidence(text: &str) -> f32 {
+    let trimmed = text.trim_start();
+    let structured = trimmed.starts_with('{') || trimmed.starts_with('[');
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(_) if structured => 1.0,
+        Ok(_) => 0.5,
+        Err(_) if structured => 0.3,
+        Err(_) => 0.0,
+    }
+}
+
+fn toml_confidence(text: &str) -> f32 {
+    let lines = significant_lines(text);
+    if lines.is_empty() {
+        return 0.0;
+    }
+    let tomlish = lines
+        .iter()
+        .filter(|line| is_table_header(line) || is_key_value(line))
+        .count();
+    let ratio = tomlish as f32 / lines.len() as f32;
+    if toml::from_str::<toml::Table>(text).is_ok() {
+        0.5 + 0.45 * ratio
+    } else {
+        0.4 * ratio
+    }
+}
+
+fn yaml_confidence(text: &str) -> f32 {
+    let lines = significant_lines(text);
+    if lines.is_empty() {
+        return 0.1;
+    }
+    let yamlish = lines
+        .iter()
+        .filter(|line| {
+            line.starts_with("- ") || **line == "-" || line.contains(": ") || line.ends_with(':')
+        })
+        .count();
+    let ratio = yamlish as f32 / lines.len() as f32;
+    let document_start = lines[0] == "---" || lines[0].starts_with("%YAML");
+    let flow = lines[0].starts_with('{') || lines[0].starts_with('[');
+    match transcoder::parse_yaml(text, &Limits::default()) {
+        Ok(YamlValue::Mapping(_) | YamlValue::Sequence(_)) if document_start => 0.95,
+        // JSON is also valid YAML, but it's less likely to be what was meant.
+        Ok(YamlValue::Mapping(_) | YamlValue::Sequence(_)) if flow => 0.5,
+        Ok(YamlValue::Mapping(_) | YamlValue::Sequence(_)) => 0.4 + 0.5 * ratio,
+        // Almost anything parses as a plain YAML string.
+        Ok(_) => 0.1,
+        Err(_) => 0.3 * ratio,
+    }
+}
+
+fn xml_confidence(text: &str) -> f32 {
+    let trimmed = text.trim_start();
+    if trimmed.starts_with("<?xml") {
+        1.0
+    } else if trimmed.starts_with('<') && (trimmed.contains("</") || trimmed.contains("/>")) {
+        0.8
+    } else {
+        0.0
+    }
+}
+
+fn significant_lines(text: &str) -> Vec<&str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+fn is_table_header(line: &str) -> bool {
+    line.starts_with('[') && line.ends_with(']') && !line.contains(',')
+}
+
+fn is_key_value(line: &str) -> bool {
+    match line.split_once('=') {
+        Some((key, _)) => {
+            let key = key.trim();
+            !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_-.\"' ".contains(c))
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best(text: &str) -> Content {
+        detect(text.as_bytes()).best().unwrap().content
+    }
+
+    #[test]
+    fn json_is_detected() {
+        assert_eq!(best("{\"a\": [1, 2]}"), Content::Format(Format::Json));
+    }
+
+    #[test]
+    fn yaml_is_detected() {
+        assert_eq!(
+            best("name: x\nitems:\n  - 1\n  - 2\n"),
+            Content::Format(Format::Yaml)
+        );
+    }
+
+    #[test]
+    fn toml_is_detected() {
+        assert_eq!(
+            best("# settings\nname = \"x\"\n\n[server]\nport = 80\n"),
+            Content::Format(Format::Toml)
+        );
+    }
+
+    #[test]
+    fn xml_and_binary_content_is_recognised() {
+        assert_eq!(best("<?xml version=\"1.0\"?><a/>"), Content::Xml);
+        assert_eq!(
+            detect(b"\x1f\x8b\x08\x00").best().unwrap().content,
+            Content::Binary("gzip")
+        );
+        assert!(detect(b"\x1f\x8b\x08\x00").format().is_err());
+    }
+
+    #[test]
+    fn a_plain_word_is_ambiguous() {
+        assert!(detect(b"hello").is_ambiguous());
+        assert!(!detect(b"{\"a\": 1}").is_ambiguous());
+    }
+}
@@ -0,0 +1,321 @@
+use anstyle::{AnsiColor, Style};
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::error::{Violation, X2YError};
+use crate::limits::Limit;
+
+const ERROR: Style = AnsiColor::Red.on_default().bold();
+const WARNING: Style = AnsiColor::Yellow.on_default().bold();
+const EMPHASIS: Style = Style::new().bold();
+const GUTTER: Style = AnsiColor::Blue.on_default().bold();
+
+// Renders an error for a terminal, in the style of rustc:
+//
+// error[E102]: invalid json: expected `,` or `}`
+//   --> config.json:3:5
+//    |
+//  3 |     "b" 2
+//    |         ^
+pub fn render(error: &X2YError, color: bool) -> String {
+    let paint = |style: Style, text: &str| {
+        if color {
+            format!("{}{}{}", style.render(), text, style.render_reset())
+        } else {
+            text.to_string()
+        }
+    };
+
+    let mut output = String::new();
+    let listed = match error {
+        X2YError::Validation(validation) => Some((
+            validation.path.as_ref(),
+            "doesn't match the schema".to_string(),
+            &validation.violations,
+        )),
+        X2YError::Verification(verification) => Some((
+            verification.path.as_ref(),
+            format!("can't be converted to {} without loss", verification.format),
+            &verification.losses,
+        )),
+        _ => None,
+    };
+    if let Some((path, problem, violations)) = listed {
+        let path = path.map(|p| p.display().to_string());
+        let _ = writeln!(
+            output,
+            "{}: {}",
+            paint(ERROR, &format!("error[{}]", error.code())),
+            paint(
+                EMPHASIS,
+                &format!("{} {}", path.as_deref().unwrap_or("the document"), problem)
+            )
+        );
+        for violation in violations {
+            let _ = writeln!(output, "  {}: {}", violation.value_path, violation.message);
+            if let (Some(path), Some(location)) = (&path, violation.location) {
+                let _ = writeln!(
+                    output,
+                    "    {} {}:{}:{}",
+                    paint(GUTTER, "-->"),
+                    path,
+                    location.line,
+                    location.column
+                );
+            }
+        }
+        return output;
+    }
+    let X2YError::Parse(parse) = error else {
+        let _ = writeln!(
+            output,
+            "{}: {}",
+            paint(ERROR, &format!("error[{}]", error.code())),
+            paint(EMPHASIS, &message(error))
+        );
+        return output;
+    };
+
+    let _ = writeln!(
+        output,
+        "{}: {}",
+        paint(ERROR, &format!("error[{}]", error.code())),
+        paint(
+            EMPHASIS,
+            &format!("invalid {}: {}", parse.format, parse.message)
+        )
+    );
+
+    let path = parse
+        .path
+        .as_ref()
+        .map_or_else(|| "<input>".to_string(), |p| p.display().to_string());
+    let Some(location) = parse.location else {
+        let _ = writeln!(output, "  {} {}", paint(GUTTER, "-->"), path);
+        return output;
+    };
+    let number = location.line.to_string();
+    let margin = " ".repeat(number.len());
+    let _ = writeln!(
+        output,
+        "{}{} {}:{}:{}",
+        margin,
+        paint(GUTTER, "-->"),
+        path,
+        location.line,
+        location.column
+    );
+    if let Some(line) = &parse.source_line {
+        // Tabs are kept so the caret lines up with the source however wide they're shown.
+        let caret_offset: String = line
+            .chars()
+            .take(location.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let _ = writeln!(output, "{} {}", margin, paint(GUTTER, "|"));
+        let _ = writeln!(
+            output,
+            "{} {}",
+            paint(GUTTER, &format!("{} |", number)),
+            line
+        );
+        let _ = writeln!(
+            output,
+            "{} {} {}{}",
+            margin,
+            paint(GUTTER, "|"),
+            caret_offset,
+            paint(ERROR, "^")
+        );
+    }
+    output
+}
+
+// The error's message, with the option that raises a limit that was reached.
+fn message(error: &X2YError) -> String {
+    match error {
+        X2YError::Limit(limit) => format!("{}, raise it with {}", error, flag(limit.limit)),
+        _ => error.to_string(),
+    }
+}
+
+// The option that sets the limit on the command line.
+fn flag(limit: Limit) -> &'static str {
+    match limit {
+        Limit::InputSize => "--max-input-size",
+        Limit::Depth => "--max-depth",
+        Limit::Nodes => "--max-nodes",
+        Limit::AliasExpansion => "--max-alias-expansion",
+        Limit::StringLength => "--max-string-length",
+    }
+}
+
+// Renders a value a conversion changed, in the same style as errors:
+//
+// warning: .enabled: `yes` is a string, YAML 1.1 reads it as a boolean
+//   --> config.yaml
+pub fn render_warning(file: Option<&Path>, warning: &Violation, color: bool) -> String {
+    let paint = |style: Style, text: &str| {
+        if color {
+            format!("{}{}{}", style.render(), text, style.render_reset())
+        } else {
+            text.to_string()
+        }
+    };
+    let mut output = format!(
+        "{}: {}\n",
+        paint(WARNING, "warning"),
+        paint(
+            EMPHASIS,
+            &format!("{}: {}", warning.value_path, warning.message)
+        )
+    );
+    if let Some(file) = file {
+        let _ = writeln!(output, "  {} {}", paint(GUTTER, "-->"), file.display());
+    }
+    output
+}
+
+pub fn warning_to_json(file: Option<&Path>, warning: &Violation) -> serde_json::Value {
+    serde_json::json!({
+        "kind": "warning",
+        "message": warning.message,
+        "path": file.map(|p| p.display().to_string()),
+        "value_path": warning.value_path,
+    })
+}
+
+// Describes an error as a single JSON object for editors and CI. Fields that
+// don't apply to the error are null.
+pub fn to_json(error: &X2YError) -> serde_json::Value {
+    let mut json = serde_json::json!({
+        "code": error.code(),
+        "kind": error.kind(),
+        "exit_code": error.exit_code(),
+        "message": message(error),
+        "path": error.path().map(|p| p.display().to_string()),
+        "format": null,
+        "line": null,
+        "column": null,
+        "value_path": null,
+        "violations": null,
+    });
+    match error {
+        X2YError::Parse(parse) => {
+            json["message"] = parse.message.clone().into();
+            json["format"] = parse.format.to_string().into();
+            if let Some(location) = parse.location {
+                json["line"] = location.line.into();
+                json["column"] = location.column.into();
+            }
+        }
+        X2YError::Unsupported(unsupported) => {
+            json["message"] = unsupported.message.clone().into();
+            json["format"] = unsupported.format.to_string().into();
+            json["value_path"] = unsupported.value_path.clone().into();
+        }
+        X2YError::Patch(patch) => json["message"] = patch.message.clone().into(),
+        X2YError::Validation(validation) => {
+            json["message"] = "the document doesn't match the schema".into();
+            json["violations"] = violations_json(&validation.violations);
+        }
+        X2YError::Verification(verification) => {
+            json["message"] = format!(
+                "the document can't be converted to {} without loss",
+                verification.format
+            )
+            .into();
+            json["format"] = verification.format.to_string().into();
+            json["violations"] = violations_json(&verification.losses);
+        }
+        X2YError::Limit(limit) => json["value_path"] = limit.value_path.clone().into(),
+        _ => {}
+    }
+    json
+}
+
+fn violations_json(violations: &[Violation]) -> serde_json::Value {
+    violations
+        .iter()
+        .map(|violation| {
+            serde_json::json!({
+                "value_path": violation.value_path,
+                "message": violation.message,
+                "line": violation.location.map(|l| l.line),
+                "column": violation.location.map(|l| l.column),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+    use crate::transcoder::decode;
+    use std::path::PathBuf;
+
+    #[test]
+    fn a_parse_error_points_at_the_source() {
+        let error = decode("{\n  \"a\": 1,\n  \"b\" 2\n}", Format::Json)
+            .unwrap_err()
+            .with_path(&PathBuf::from("config.json"));
+
+        let output = render(&error, false);
+
+        assert_eq!(
+            output,
+            "error[E102]: invalid json: expected `:`\n \
+             --> config.json:3:7\n  \
+             |\n\
+             3 |   \"b\" 2\n  \
+             |       ^\n"
+        );
+    }
+
+    #[test]
+    fn colour_is_optional() {
+        let error = decode("a = ", Format::Toml).unwrap_err();
+
+        assert!(render(&error, true).contains("\u{1b}["));
+        assert!(!render(&error, false).contains("\u{1b}["));
+    }
+
+    #[test]
+    fn an_error_is_described_as_json() {
+        let error = decode("a = 1\nb = \n", Format::Toml)
+            .unwrap_err()
+            .with_path(&PathBuf::from("config.toml"));
+
+        let json = to_json(&error);
+
+        assert_eq!(json["code"], "E103");
+        assert_eq!(json["kind"], "parse");
+        assert_eq!(json["exit_code"], 4);
+        assert_eq!(json["path"], "config.toml");
+        assert_eq!(json["format"], "toml");
+        assert_eq!(json["line"], 2);
+        assert_eq!(json["column"], 5);
+        assert_eq!(json["value_path"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn a_limit_names_the_option_that_raises_it() {
+        let error = crate::limits::exceeded(Limit::Depth, 2, Some(".a.b"))
+            .with_path(&PathBuf::from("nested.json"));
+
+        assert_eq!(
+            error.to_string(),
+            "nested.json is over the limit of 2 levels of nesting at .a.b"
+        );
+        assert_eq!(
+            render(&error, false),
+            "error[E700]: nested.json is over the limit of 2 levels of nesting at .a.b, \
+             raise it with --max-depth\n"
+        );
+        assert_eq!(
+            to_json(&error)["message"],
+            "nested.json is over the limit of 2 levels of nesting at .a.b, raise it with --max-depth"
+        );
+    }
+}
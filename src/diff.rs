@@ -0,0 +1,213 @@
+use std::fmt::Write;
+
+use crate::emitter;
+use crate::value::{display_path, json_pointer, segments_path, Mapping, Segment, Value};
+
+// Compares two decoded documents. Values that only differ in how their format
+// stores them, such as 1 and 1.0 or a TOML datetime and the same text in a
+// string, are treated as equal so a conversion can be checked.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Added {
+        path: Vec<Segment>,
+        value: Value,
+    },
+    Removed {
+        path: Vec<Segment>,
+        value: Value,
+    },
+    Changed {
+        path: Vec<Segment>,
+        from: Value,
+        to: Value,
+    },
+}
+
+/// The changes that turn `a` into `b`, in the order they can be applied.
+pub fn diff(a: &Value, b: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    compare(a, b, &mut Vec::new(), &mut changes);
+    changes
+}
+
+fn compare(a: &Value, b: &Value, path: &mut Vec<Segment>, changes: &mut Vec<Change>) {
+    match (a, b) {
+        (Value::Mapping(a), Value::Mapping(b)) => compare_mappings(a, b, path, changes),
+        (Value::Sequence(a), Value::Sequence(b)) => {
+            for (i, (a, b)) in a.iter().zip(b).enumerate() {
+                path.push(Segment::Index(i as i128));
+                compare(a, b, path, changes);
+                path.pop();
+            }
+            // Removed from the end first so the indexes stay valid as a patch.
+            for (i, value) in a.iter().enumerate().skip(b.len()).rev() {
+                path.push(Segment::Index(i as i128));
+                changes.push(Change::Removed {
+                    path: path.clone(),
+                    value: value.clone(),
+                });
+                path.pop();
+            }
+            for (i, value) in b.iter().enumerate().skip(a.len()) {
+                path.push(Segment::Index(i as i128));
+                changes.push(Change::Added {
+                    path: path.clone(),
+                    value: value.clone(),
+                });
+                path.pop();
+            }
+        }
+        (a, b) if !equivalent(a, b) => changes.push(Change::Changed {
+            path: path.clone(),
+            from: a.clone(),
+            to: b.clone(),
+        }),
+        _ => {}
+    }
+}
+
+fn compare_mappings(a: &Mapping, b: &Mapping, path: &mut Vec<Segment>, changes: &mut Vec<Change>) {
+    for (key, a_value) in a {
+        path.push(Segment::Key(key.clone()));
+        match b.get(key) {
+            Some(b_value) => compare(a_value, b_value, path, changes),
+            None => changes.push(Change::Removed {
+                path: path.clone(),
+                value: a_value.clone(),
+            }),
+        }
+        path.pop();
+    }
+    for (key, value) in b.iter().filter(|(key, _)| !a.contains_key(*key)) {
+        path.push(Segment::Key(key.clone()));
+        changes.push(Change::Added {
+            path: path.clone(),
+            value: value.clone(),
+        });
+        path.pop();
+    }
+}
+
+fn equivalent(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Integer(i), Value::Float(f)) | (Value::Float(f), Value::Integer(i)) => {
+            *i as f64 == *f
+        }
+        // A document with `.nan` is the same as itself.
+        (Value::Float(a), Value::Float(b)) if a.is_nan() && b.is_nan() => true,
+        (Value::String(a) | Value::Datetime(a), Value::String(b) | Value::Datetime(b)) => a == b,
+        (a, b) => a == b,
+    }
+}
+
+/// Describes the changes one per line, `+` for added, `-` for removed and `~`
+/// for changed values. Values are written as JSON.
+pub fn render(changes: &[Change]) -> String {
+    let json = emitter::inline_json;
+    let mut output = String::new();
+    for change in changes {
+        let (sign, path, value) = match change {
+            Change::Added { path, value } => ("+", path, json(value)),
+            Change::Removed { path, value } => ("-", path, json(value)),
+            Change::Changed { path, from, to } => {
+                ("~", path, format!("{} -> {}", json(from), json(to)))
+            }
+        };
+        let _ = writeln!(
+            output,
+            "{} {}: {}",
+            sign,
+            display_path(&segments_path(path)),
+            value
+        );
+    }
+    output
+}
+
+/// The changes as an RFC 6902 JSON Patch.
+pub fn to_json_patch(changes: &[Change]) -> Value {
+    let operation = |op: &str, path: &[Segment], value: Option<&Value>| {
+        let mut operation = Mapping::new();
+        operation.insert("op".to_string(), Value::String(op.to_string()));
+        operation.insert("path".to_string(), Value::String(json_pointer(path)));
+        if let Some(value) = value {
+            operation.insert("value".to_string(), value.clone());
+        }
+        Value::Mapping(operation)
+    };
+    Value::Sequence(
+        changes
+            .iter()
+            .map(|change| match change {
+                Change::Added { path, value } => operation("add", path, Some(value)),
+                Change::Removed { path, .. } => operation("remove", path, None),
+                Change::Changed { path, to, .. } => operation("replace", path, Some(to)),
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+    use crate::transcoder::decode;
+
+    #[test]
+    fn documents_are_compared_by_value() {
+        let a = decode("a = 1.0\nb = 1979-05-27\nc = [1, 2, 3]\n", Format::Toml).unwrap();
+        let b = decode(
+            "{\"a\": 1, \"b\": \"1979-05-27\", \"c\": [1, 2, 3]}",
+            Format::Json,
+        )
+        .unwrap();
+
+        assert_eq!(diff(&a, &b), vec![]);
+    }
+
+    #[test]
+    fn not_a_number_is_the_same_as_itself() {
+        let a = decode("a: .nan\nb: [.NaN, 1.5]\n", Format::Yaml).unwrap();
+        let b = decode("a = nan\nb = [-nan, 1.5]\n", Format::Toml).unwrap();
+        let c = decode("a: 1.5\nb: [.nan, .nan]\n", Format::Yaml).unwrap();
+
+        assert_eq!(diff(&a, &a), vec![]);
+        assert_eq!(diff(&a, &b), vec![]);
+        assert_eq!(diff(&a, &c).len(), 2);
+    }
+
+    #[test]
+    fn changes_are_rendered() {
+        let a = decode(
+            "server: {host: a, port: 80}\nitems: [1, 2, 3]",
+            Format::Yaml,
+        )
+        .unwrap();
+        let b = decode("server: {port: 8080, tls: true}\nitems: [1]", Format::Yaml).unwrap();
+
+        assert_eq!(
+            render(&diff(&a, &b)),
+            "- .server.host: \"a\"\n\
+             ~ .server.port: 80 -> 8080\n\
+             + .server.tls: true\n\
+             - .items[2]: 3\n\
+             - .items[1]: 2\n"
+        );
+    }
+
+    #[test]
+    fn changes_are_a_json_patch() {
+        let a = decode("{\"a/b\": 1, \"c\": [1]}", Format::Json).unwrap();
+        let b = decode("{\"a/b\": 2, \"c\": [1, 2]}", Format::Json).unwrap();
+
+        assert_eq!(
+            to_json_patch(&diff(&a, &b)),
+            decode(
+                r#"[{"op": "replace", "path": "/a~1b", "value": 2}, {"op": "add", "path": "/c/1", "value": 2}]"#,
+                Format::Json
+            )
+            .unwrap()
+        );
+    }
+}
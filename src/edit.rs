@@ -0,0 +1,539 @@
+use crate::emitter;
+use crate::error::{Violation, X2YError};
+use crate::format::Format;
+use crate::options::{InputOptions, OutputOptions};
+use crate::query::Query;
+use crate::transcoder;
+use crate::value::{display_path, resolve_index, segments_path, Segment, Value};
+
+use toml_edit::{Document, Item, Table, Value as TomlValue};
+use yaml_rust2::parser::{Event, Parser};
+use yaml_rust2::scanner::TScalarStyle;
+
+// Reads or changes a single value in a document, writing the document back in
+// the format it was read from. TOML is edited in place so comments and layout
+// are kept, YAML and JSON are encoded again with the output options and keep
+// their key order, but not their comments, anchors and aliases or `<<` merge
+// keys, which are warned about.
+
+/// Parses a path such as `.servers[0].host`.
+pub fn parse_path(path: &str) -> Result<Vec<Segment>, X2YError> {
+    Query::parse(path)?.path().ok_or_else(|| {
+        X2YError::InvalidInput(format!(
+            "`{}` is not a path, expected something like .servers[0].host",
+            path
+        ))
+    })
+}
+
+pub fn get(
+    contents: &str,
+    format: Format,
+    input_options: &InputOptions,
+    path: &[Segment],
+) -> Result<Value, X2YError> {
+    let value = transcoder::decode_with(contents, format, input_options)?;
+    value.get(path).cloned().ok_or_else(|| not_found(path))
+}
+
+pub fn set(
+    contents: &str,
+    format: Format,
+    input_options: &InputOptions,
+    path: &[Segment],
+    new: Value,
+    options: &OutputOptions,
+) -> Result<String, X2YError> {
+    let mut value = transcoder::decode_with(contents, format, input_options)?;
+    value.set(path, new.clone())?;
+    match format {
+        Format::Toml if !path.is_empty() => {
+            let mut document = toml_document(contents)?;
+            toml_set(&mut document, path, &new)?;
+            Ok(document.to_string())
+        }
+        _ => transcoder::encode(&value, format, options),
+    }
+}
+
+pub fn delete(
+    contents: &str,
+    format: Format,
+    input_options: &InputOptions,
+    path: &[Segment],
+    options: &OutputOptions,
+) -> Result<String, X2YError> {
+    let mut value = transcoder::decode_with(contents, format, input_options)?;
+    if value.remove(path).is_none() {
+        return Err(not_found(path));
+    }
+    match format {
+        Format::Toml => {
+            let mut document = toml_document(contents)?;
+            toml_delete(&mut document, path).ok_or_else(|| not_found(path))?;
+            Ok(document.to_string())
+        }
+        _ => transcoder::encode(&value, format, options),
+    }
+}
+
+/// What of `contents` is lost by `set` and `delete` encoding it again: the
+/// comments, anchors and aliases and `<<` merge keys of a YAML document.
+pub fn losses(contents: &str, format: Format) -> Vec<Violation> {
+    if format != Format::Yaml {
+        return Vec::new();
+    }
+    let mut lost = Vec::new();
+    if has_yaml_comments(contents) {
+        lost.push("comments");
+    }
+    let (anchors, merges) = yaml_references(contents);
+    if anchors {
+        lost.push("anchors");
+    }
+    if merges {
+        lost.push("<< merge keys");
+    }
+    let Some((last, rest)) = lost.split_last() else {
+        return Vec::new();
+    };
+    let lost = match rest {
+        [] => last.to_string(),
+        _ => format!("{} and {}", rest.join(", "), last),
+    };
+    vec![Violation {
+        value_path: ".".to_string(),
+        message: format!(
+            "the {} in the file aren't kept, yaml is written again as a whole",
+            lost
+        ),
+        location: None,
+    }]
+}
+
+// Whether a YAML document has a `#` that starts a comment: one at the start of
+// a line or after a space, that isn't in a quoted or block scalar.
+fn has_yaml_comments(contents: &str) -> bool {
+    let chars: Vec<char> = contents.chars().collect();
+    let mut in_scalar = vec![false; chars.len()];
+    let mut parser = Parser::new_from_str(contents);
+    while let Ok((event, marker)) = parser.next_token() {
+        let start = marker.index();
+        let end = match event {
+            Event::Scalar(_, TScalarStyle::SingleQuoted | TScalarStyle::DoubleQuoted, ..) => {
+                quoted_end(&chars, start)
+            }
+            Event::Scalar(s, TScalarStyle::Literal | TScalarStyle::Folded, ..) if !s.is_empty() => {
+                block_end(&chars, start)
+            }
+            Event::StreamEnd => break,
+            _ => continue,
+        };
+        in_scalar[start..end.min(chars.len())].fill(true);
+    }
+    (0..chars.len())
+        .any(|i| chars[i] == '#' && !in_scalar[i] && (i == 0 || chars[i - 1].is_whitespace()))
+}
+
+// The end of the quoted scalar starting at `start`.
+fn quoted_end(chars: &[char], start: usize) -> usize {
+    let Some(&quote) = chars.get(start) else {
+        return start;
+    };
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if quote == '"' => i += 1,
+            '\'' if quote == '\'' && chars.get(i + 1) == Some(&'\'') => i += 1,
+            c if c == quote => return i + 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+// The end of the block scalar whose content starts at `start`: its lines run up
+// to the first one indented less than the first.
+fn block_end(chars: &[char], start: usize) -> usize {
+    let mut line = chars[..start]
+        .iter()
+        .rposition(|c| *c == '\n')
+        .map_or(0, |n| n + 1);
+    let mut indent = None;
+    while line < chars.len() {
+        let length = chars[line..]
+            .iter()
+            .position(|c| *c == '\n')
+            .map_or(chars.len() - line, |n| n + 1);
+        let text = &chars[line..line + length];
+        let spaces = text.iter().take_while(|c| **c == ' ').count();
+        if text.iter().any(|c| !c.is_whitespace()) {
+            match indent {
+                Some(indent) if spaces < indent => break,
+                None => indent = Some(spaces),
+                _ => {}
+            }
+        }
+        line += length;
+    }
+    line
+}
+
+// Whether a YAML document has anchors or aliases, and whether it has `<<`
+// merge keys.
+fn yaml_references(contents: &str) -> (bool, bool) {
+    let (mut anchors, mut merges) = (false, false);
+    // For each open collection, whether it's a mapping and how many nodes it
+    // has had, which tells keys from values.
+    let mut open: Vec<(bool, usize)> = Vec::new();
+    let mut parser = Parser::new_from_str(contents);
+    while let Ok((event, _)) = parser.next_token() {
+        let is_key = matches!(open.last(), Some((true, n)) if n % 2 == 0);
+        if let Some((_, n)) = open.last_mut() {
+            if !matches!(event, Event::SequenceEnd | Event::MappingEnd) {
+                *n += 1;
+            }
+        }
+        match event {
+            Event::Scalar(s, style, anchor, tag) => {
+                anchors |= anchor != 0;
+                merges |= is_key && s == "<<" && style == TScalarStyle::Plain && tag.is_none();
+            }
+            Event::Alias(_) => anchors = true,
+            Event::SequenceStart(anchor, _) | Event::MappingStart(anchor, _) => {
+                anchors |= anchor != 0;
+                open.push((matches!(event, Event::MappingStart(..)), 0));
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                open.pop();
+            }
+            Event::StreamEnd => break,
+            _ => {}
+        }
+    }
+    (anchors, merges)
+}
+
+/// Writes a value for use in scripts: strings as they are, other scalars as
+/// JSON and sequences and mappings in the given format.
+pub fn display(value: &Value, format: Format, options: &OutputOptions) -> Result<String, X2YError> {
+    match value {
+        Value::String(s) | Value::Datetime(s) => Ok(format!("{}\n", s)),
+        Value::Sequence(_) | Value::Mapping(_) => transcoder::encode(value, format, options),
+        scalar => emitter::to_json_string(scalar, &OutputOptions::default()),
+    }
+}
+
+fn not_found(path: &[Segment]) -> X2YError {
+    X2YError::InvalidInput(format!(
+        "there is no value at {}",
+        display_path(&segments_path(path))
+    ))
+}
+
+fn toml_document(contents: &str) -> Result<Document, X2YError> {
+    contents
+        .parse()
+        .map_err(|e: toml_edit::TomlError| X2YError::InvalidInput(e.to_string()))
+}
+
+// Anything in a TOML document that can contain other values.
+enum Node<'a> {
+    Item(&'a mut Item),
+    Value(&'a mut TomlValue),
+    Table(&'a mut Table),
+}
+
+fn toml_child<'a>(node: Node<'a>, segment: &Segment, create: bool) -> Option<Node<'a>> {
+    match (node, segment) {
+        (Node::Item(Item::Table(table)) | Node::Table(table), Segment::Key(key)) => {
+            if create && !table.contains_key(key) {
+                let mut child = Table::new();
+                child.set_implicit(true);
+                table.insert(key, Item::Table(child));
+            }
+            table.get_mut(key).map(Node::Item)
+        }
+        (Node::Item(Item::ArrayOfTables(tables)), Segment::Index(i)) => {
+            let i = resolve_index(*i, tables.len())?;
+            tables.get_mut(i).map(Node::Table)
+        }
+        (Node::Item(Item::Value(value)) | Node::Value(value), segment) => match (value, segment) {
+            (TomlValue::InlineTable(table), Segment::Key(key)) => {
+                if create && !table.contains_key(key) {
+                    table.insert(key.as_str(), TomlValue::InlineTable(Default::default()));
+                }
+                table.get_mut(key).map(Node::Value)
+            }
+            (TomlValue::Array(array), Segment::Index(i)) => {
+                let i = resolve_index(*i, array.len())?;
+                array.get_mut(i).map(Node::Value)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// The change has already been checked against the decoded value, so this only
+// fails for layouts the intermediate value can't tell apart.
+fn toml_set(document: &mut Document, path: &[Segment], new: &Value) -> Result<(), X2YError> {
+    let unsupported = || {
+        X2YError::unsupported(
+            Format::Toml,
+            &segments_path(path),
+            "the value can't be set in this toml layout",
+        )
+    };
+    let Some((last, parents)) = path.split_last() else {
+        return Err(unsupported());
+    };
+    let mut value = emitter::to_toml(new, &segments_path(path))?;
+    let mut node = Node::Table(document.as_table_mut());
+    for segment in parents {
+        node = toml_child(node, segment, true).ok_or_else(unsupported)?;
+    }
+    match (node, last) {
+        (Node::Item(Item::Table(table)) | Node::Table(table), Segment::Key(key)) => {
+            match table.get_mut(key) {
+                // Keeps the comments around the key and value.
+                Some(Item::Value(old)) => {
+                    *value.decor_mut() = old.decor().clone();
+                    *old = value;
+                }
+                Some(old) => *old = toml_item(value),
+                None => {
+                    table.insert(key, toml_item(value));
+                }
+            }
+        }
+        (Node::Item(Item::ArrayOfTables(tables)), Segment::Index(i)) => {
+            let TomlValue::InlineTable(table) = value else {
+                return Err(X2YError::unsupported(
+                    Format::Toml,
+                    &segments_path(path),
+                    "an array of tables can only contain tables",
+                ));
+            };
+            match resolve_index(*i, tables.len()) {
+                Some(i) => *tables.get_mut(i).ok_or_else(unsupported)? = table.into_table(),
+                None => tables.push(table.into_table()),
+            }
+        }
+        (Node::Item(Item::Value(parent)) | Node::Value(parent), last) => match (parent, last) {
+            (TomlValue::InlineTable(table), Segment::Key(key)) => match table.get_mut(key) {
+                Some(old) => {
+                    *value.decor_mut() = old.decor().clone();
+                    *old = value;
+                }
+                None => {
+                    table.insert(key.as_str(), value);
+                }
+            },
+            (TomlValue::Array(array), Segment::Index(i)) => match resolve_index(*i, array.len()) {
+                Some(i) => {
+                    *value.decor_mut() = array.get(i).ok_or_else(unsupported)?.decor().clone();
+                    array.replace_formatted(i, value);
+                }
+                None => array.push(value),
+            },
+            _ => return Err(unsupported()),
+        },
+        _ => return Err(unsupported()),
+    }
+    Ok(())
+}
+
+// Mappings become tables rather than inline tables where they're allowed.
+fn toml_item(value: TomlValue) -> Item {
+    match value {
+        TomlValue::InlineTable(table) => Item::Table(table.into_table()),
+        value => Item::Value(value),
+    }
+}
+
+fn toml_delete(document: &mut Document, path: &[Segment]) -> Option<()> {
+    let (last, parents) = path.split_last()?;
+    let mut node = Node::Table(document.as_table_mut());
+    for segment in parents {
+        node = toml_child(node, segment, false)?;
+    }
+    match (node, last) {
+        (Node::Item(Item::Table(table)) | Node::Table(table), Segment::Key(key)) => {
+            table.remove(key).map(|_| ())
+        }
+        (Node::Item(Item::ArrayOfTables(tables)), Segment::Index(i)) => {
+            tables.remove(resolve_index(*i, tables.len())?);
+            Some(())
+        }
+        (Node::Item(Item::Value(parent)) | Node::Value(parent), last) => match (parent, last) {
+            (TomlValue::InlineTable(table), Segment::Key(key)) => table.remove(key).map(|_| ()),
+            (TomlValue::Array(array), Segment::Index(i)) => {
+                array.remove(resolve_index(*i, array.len())?);
+                Some(())
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(s: &str) -> Vec<Segment> {
+        parse_path(s).unwrap()
+    }
+
+    #[test]
+    fn paths_are_parsed() {
+        assert_eq!(
+            path(".servers[0].\"host name\""),
+            vec![
+                Segment::Key("servers".to_string()),
+                Segment::Index(0),
+                Segment::Key("host name".to_string())
+            ]
+        );
+        assert!(parse_path(".servers[]").is_err());
+    }
+
+    #[test]
+    fn toml_edits_keep_comments() {
+        let toml =
+            "# settings\nname = \"x\" # the name\n\n[server]\nport = 80\n\n[[users]]\nid = 1\n";
+        let options = OutputOptions::default();
+
+        let output = set(
+            toml,
+            Format::Toml,
+            &InputOptions::default(),
+            &path(".name"),
+            Value::String("y".to_string()),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "# settings\nname = \"y\" # the name\n\n[server]\nport = 80\n\n[[users]]\nid = 1\n"
+        );
+
+        let output = set(
+            toml,
+            Format::Toml,
+            &InputOptions::default(),
+            &path(".server.tls.enabled"),
+            Value::Bool(true),
+            &options,
+        )
+        .unwrap();
+        assert!(
+            output.contains("[server.tls]\nenabled = true\n"),
+            "{}",
+            output
+        );
+
+        let output = delete(
+            toml,
+            Format::Toml,
+            &InputOptions::default(),
+            &path(".users[0]"),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "# settings\nname = \"x\" # the name\n\n[server]\nport = 80\n"
+        );
+    }
+
+    #[test]
+    fn yaml_and_json_are_encoded_again() {
+        let options = OutputOptions::default();
+
+        let output = set(
+            "b: 1\na: [1, 2]\n",
+            Format::Yaml,
+            &InputOptions::default(),
+            &path(".a[2]"),
+            Value::Integer(3),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(output, "b: 1\na:\n- 1\n- 2\n- 3\n");
+
+        let output = delete(
+            "{\"b\": 1, \"a\": 2}",
+            Format::Json,
+            &InputOptions::default(),
+            &path(".b"),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(output, "{\n  \"a\": 2\n}\n");
+    }
+
+    #[test]
+    fn what_encoding_yaml_again_loses_is_found() {
+        let lost = |contents: &str| {
+            losses(contents, Format::Yaml)
+                .into_iter()
+                .map(|v| v.message)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(lost("a: 'ü # x'\nb: \"#\"\nc: a#b\nd: |\n  # text\n  more\ne: 1\n").is_empty());
+        assert!(losses("{\"a\": \"# x\"}", Format::Json).is_empty());
+        assert_eq!(
+            lost("é: 'ü' # note\n"),
+            vec!["the comments in the file aren't kept, yaml is written again as a whole"]
+        );
+        assert_eq!(
+            lost("d: |\n  text\n# note\ne: 1\n"),
+            vec!["the comments in the file aren't kept, yaml is written again as a whole"]
+        );
+        assert_eq!(
+            lost("# defaults\nbase: &b {x: 1}\nuse:\n  <<: *b\n"),
+            vec![
+                "the comments, anchors and << merge keys in the file aren't kept, \
+                 yaml is written again as a whole"
+            ]
+        );
+        assert_eq!(lost("a: {'<<': 1}\nb: [<<]\n"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn missing_values_and_type_mismatches_are_errors() {
+        let options = OutputOptions::default();
+
+        assert!(get("a: 1", Format::Yaml, &InputOptions::default(), &path(".b")).is_err());
+        assert!(delete(
+            "a: 1",
+            Format::Yaml,
+            &InputOptions::default(),
+            &path(".b"),
+            &options
+        )
+        .is_err());
+        assert!(set(
+            "a: 1",
+            Format::Yaml,
+            &InputOptions::default(),
+            &path(".a.b"),
+            Value::Null,
+            &options
+        )
+        .is_err());
+        assert!(set(
+            "a = [1]",
+            Format::Toml,
+            &InputOptions::default(),
+            &path(".a[5]"),
+            Value::Integer(1),
+            &options
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,1043 @@
+use crate::error::X2YError;
+use crate::format::Format;
+use crate::options::{OutputOptions, QuoteStyle, SequenceStyle, TableStyle};
+use crate::value::{index_path, key_path, Mapping, Value};
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use serde_yaml::Value as YamlValue;
+use std::collections::{HashMap, HashSet};
+use toml_edit::visit_mut::{self, VisitMut};
+use toml_edit::Item as TomlItem;
+use toml_edit::Value as TomlValue;
+
+const DEFAULT_JSON_INDENT: usize = 2;
+const DEFAULT_YAML_INDENT: usize = 2;
+const DEFAULT_TOML_INDENT: usize = 4;
+
+// Each emitter writes lines ending in `\n`, line endings and the final newline
+// are applied afterwards so every format handles them the same way.
+pub(crate) fn finish(mut output: String, options: &OutputOptions) -> String {
+    if options.trailing_newline {
+        if !output.is_empty() && !output.ends_with('\n') {
+            output.push('\n');
+        }
+    } else if output.ends_with('\n') {
+        output.pop();
+    }
+    match options.line_ending.as_str() {
+        "\n" => output,
+        ending => output.replace('\n', ending),
+    }
+}
+
+pub fn to_json_string(value: &Value, options: &OutputOptions) -> Result<String, X2YError> {
+    if options.canonical {
+        let mut output = String::new();
+        canonical_json(value, "", &mut output)?;
+        return Ok(output);
+    }
+    let json_value = to_json(value);
+    let output = if options.compact {
+        serde_json::to_string(&json_value).expect("json values always serialize")
+    } else {
+        let indent = " ".repeat(options.indent.unwrap_or(DEFAULT_JSON_INDENT));
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+        let mut buffer = Vec::new();
+        let mut serializer = serde_json::Serializer::with_formatter(&mut buffer, formatter);
+        json_value
+            .serialize(&mut serializer)
+            .expect("json values always serialize");
+        String::from_utf8(buffer).expect("serde_json writes utf-8")
+    };
+    Ok(finish(output, options))
+}
+
+/// A value as JSON on a single line, for messages.
+pub(crate) fn inline_json(value: &Value) -> String {
+    to_json(value).to_string()
+}
+
+fn to_json(value: &Value) -> JsonValue {
+    match value {
+        Value::Null => JsonValue::Null,
+        Value::Bool(b) => JsonValue::Bool(*b),
+        Value::Integer(i) => {
+            if let Ok(i) = i64::try_from(*i) {
+                JsonValue::Number(i.into())
+            } else if let Ok(u) = u64::try_from(*i) {
+                JsonValue::Number(u.into())
+            } else {
+                float_to_json(*i as f64)
+            }
+        }
+        Value::Float(f) => float_to_json(*f),
+        Value::String(s) | Value::Datetime(s) => JsonValue::String(s.clone()),
+        Value::Sequence(items) => JsonValue::Array(items.iter().map(to_json).collect()),
+        Value::Mapping(map) => {
+            JsonValue::Object(map.iter().map(|(k, v)| (k.clone(), to_json(v))).collect())
+        }
+    }
+}
+
+// JSON has no representation for NaN or infinity, serde_json writes these as null.
+fn float_to_json(f: f64) -> JsonValue {
+    serde_json::Number::from_f64(f)
+        .map(JsonValue::Number)
+        .unwrap_or(JsonValue::Null)
+}
+
+// The JSON Canonicalization Scheme (RFC 8785): no whitespace, keys sorted by
+// their UTF-16 code units, numbers formatted as ECMAScript would and only the
+// escapes JSON requires.
+fn canonical_json(value: &Value, path: &str, output: &mut String) -> Result<(), X2YError> {
+    match value {
+        Value::Null => output.push_str("null"),
+        Value::Bool(b) => output.push_str(if *b { "true" } else { "false" }),
+        Value::Integer(i) => {
+            let f = *i as f64;
+            if f as i128 != *i {
+                return Err(X2YError::unsupported(
+                    Format::Json,
+                    path,
+                    format!("{} cannot be represented exactly in canonical json", i),
+                ));
+            }
+            canonical_number(f, path, output)?;
+        }
+        Value::Float(f) => canonical_number(*f, path, output)?,
+        Value::String(s) | Value::Datetime(s) => canonical_string(s, output),
+        Value::Sequence(items) => {
+            output.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                canonical_json(item, &index_path(path, i), output)?;
+            }
+            output.push(']');
+        }
+        Value::Mapping(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            output.push('{');
+            for (i, (k, v)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                canonical_string(k, output);
+                output.push(':');
+                canonical_json(v, &key_path(path, k), output)?;
+            }
+            output.push('}');
+        }
+    }
+    Ok(())
+}
+
+fn canonical_number(f: f64, path: &str, output: &mut String) -> Result<(), X2YError> {
+    if !f.is_finite() {
+        return Err(X2YError::unsupported(
+            Format::Json,
+            path,
+            format!("{} cannot be represented in canonical json", f),
+        ));
+    }
+    output.push_str(ryu_js::Buffer::new().format_finite(f));
+    Ok(())
+}
+
+fn canonical_string(s: &str, output: &mut String) {
+    output.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\u{8}' => output.push_str("\\b"),
+            '\u{c}' => output.push_str("\\f"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+pub fn to_yaml_string(value: &Value, options: &OutputOptions) -> Result<String, X2YError> {
+    let mut emitter = YamlEmitter::new(options);
+    emitter.anchors = options.yaml_anchors.then(|| Anchors::new(value));
+    emitter.document(value);
+    Ok(finish(emitter.output, options))
+}
+
+// serde_yaml has no formatting settings, so YAML is written by hand. The
+// output follows serde_yaml's layout: sequences inside a mapping are not
+// indented and multi-line strings become literal blocks.
+pub(crate) struct YamlEmitter<'a> {
+    output: String,
+    indent: usize,
+    options: &'a OutputOptions,
+    anchors: Option<Anchors>,
+}
+
+impl<'a> YamlEmitter<'a> {
+    pub(crate) fn new(options: &'a OutputOptions) -> Self {
+        Self {
+            output: String::new(),
+            indent: options.indent.unwrap_or(DEFAULT_YAML_INDENT).max(1),
+            options,
+            anchors: None,
+        }
+    }
+
+    /// The YAML written so far, leaving the emitter empty.
+    pub(crate) fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+
+    pub(crate) fn indent(&self) -> usize {
+        self.indent
+    }
+
+    pub(crate) fn document(&mut self, value: &Value) {
+        if let Some((tag, inner @ (Value::Mapping(_) | Value::Sequence(_)))) = yaml_tag(value) {
+            if is_collection(inner) && !self.flow_collection(inner) {
+                self.output.push_str(tag);
+                self.output.push('\n');
+                return self.document(inner);
+            }
+        }
+        match value {
+            Value::Mapping(map) if !map.is_empty() => self.mapping(map, 0, false),
+            Value::Sequence(items) if !items.is_empty() && !self.flow_sequences() => {
+                self.sequence(items, 0, false)
+            }
+            other => {
+                self.node(other, self.indent);
+                self.output.push('\n');
+            }
+        }
+    }
+
+    fn flow_sequences(&self) -> bool {
+        self.options.sequence_style == SequenceStyle::Flow
+    }
+
+    fn flow_collection(&self, value: &Value) -> bool {
+        matches!(value, Value::Sequence(_)) && self.flow_sequences()
+    }
+
+    pub(crate) fn pad(&mut self, level: usize) {
+        self.output.extend(std::iter::repeat_n(' ', level));
+    }
+
+    fn reference(&mut self, value: &Value, name: &str) -> Option<Reference> {
+        self.anchors.as_mut()?.reference(value, name)
+    }
+
+    // `inline` is set when the first entry continues a line that has already
+    // been started, e.g. a mapping that is an item of a block sequence.
+    fn mapping(&mut self, map: &Mapping, level: usize, mut inline: bool) {
+        for (key, value) in map {
+            if !inline {
+                self.pad(level);
+            }
+            inline = false;
+            self.output.push_str(&yaml_key(key));
+            self.output.push(':');
+            self.after_key(key, value, level);
+        }
+    }
+
+    /// Writes what follows `key:` in a mapping whose entries are at `level`.
+    pub(crate) fn after_key(&mut self, key: &str, value: &Value, level: usize) {
+        let anchor = match self.reference(value, key) {
+            Some(Reference::Alias(name)) => {
+                self.output.push_str(&format!(" *{}\n", name));
+                return;
+            }
+            Some(Reference::Anchor(name)) => Some(name),
+            None => None,
+        };
+        let (tag, value) = split_tag(value);
+        let properties = properties(anchor.as_deref(), tag);
+        match value {
+            Value::Mapping(map) if !map.is_empty() => {
+                self.output.push_str(&properties.after_line());
+                self.output.push('\n');
+                self.mapping(map, level + self.indent, false);
+            }
+            Value::Sequence(items) if !items.is_empty() && !self.flow_sequences() => {
+                self.output.push_str(&properties.after_line());
+                self.output.push('\n');
+                self.sequence(items, level, false);
+            }
+            other => {
+                self.output.push(' ');
+                self.output.push_str(&properties.before_value());
+                self.node(other, level + self.indent);
+                self.output.push('\n');
+            }
+        }
+    }
+
+    fn sequence(&mut self, items: &[Value], level: usize, mut inline: bool) {
+        for item in items {
+            if !inline {
+                self.pad(level);
+            }
+            inline = false;
+            self.output.push('-');
+            self.after_dash(item, level);
+        }
+    }
+
+    /// Writes what follows the `-` of an item in a sequence at `level`.
+    pub(crate) fn after_dash(&mut self, item: &Value, level: usize) {
+        let child = self.item_level(level);
+        let anchor = match self.reference(item, "") {
+            Some(Reference::Alias(name)) => {
+                self.output.push_str(&format!(" *{}\n", name));
+                return;
+            }
+            Some(Reference::Anchor(name)) => Some(name),
+            None => None,
+        };
+        let (tag, item) = split_tag(item);
+        let properties = properties(anchor.as_deref(), tag);
+        match item {
+            // An anchor or tag before `a: 1` on the same line would belong
+            // to the key, so a collection with one starts on the next line.
+            Value::Mapping(map) if !map.is_empty() => {
+                if properties.is_empty() {
+                    self.pad(child - level - 1);
+                    self.mapping(map, child, true);
+                } else {
+                    self.output.push_str(&properties.after_line());
+                    self.output.push('\n');
+                    self.mapping(map, child, false);
+                }
+            }
+            Value::Sequence(items) if !items.is_empty() && !self.flow_sequences() => {
+                if properties.is_empty() {
+                    self.pad(child - level - 1);
+                    self.sequence(items, child, true);
+                } else {
+                    self.output.push_str(&properties.after_line());
+                    self.output.push('\n');
+                    self.sequence(items, child, false);
+                }
+            }
+            other => {
+                self.output.push(' ');
+                self.output.push_str(&properties.before_value());
+                self.node(other, child);
+                self.output.push('\n');
+            }
+        }
+    }
+
+    /// Where the contents of an item in a sequence at `level` go. Entries of
+    /// a mapping inside a sequence have to line up with the first key, which
+    /// comes after the `- ` indicator.
+    pub(crate) fn item_level(&self, level: usize) -> usize {
+        level + self.indent.max(2)
+    }
+
+    // Writes a value that fits on the current line, `level` is the indentation
+    // used for the contents of a literal block.
+    fn node(&mut self, value: &Value, level: usize) {
+        match value {
+            Value::String(s) => {
+                let scalar = self.string(s, level, false);
+                self.output.push_str(&scalar);
+            }
+            other => {
+                let flow = self.flow(other);
+                self.output.push_str(&flow);
+            }
+        }
+    }
+
+    fn flow(&mut self, value: &Value) -> String {
+        if let Some((tag, inner)) = yaml_tag(value) {
+            let inner = self.flow(inner);
+            return format!("{} {}", tag, inner);
+        }
+        match value {
+            Value::Null => "null".to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::Integer(i) => i.to_string(),
+            Value::Float(f) => yaml_float(*f),
+            Value::String(s) => self.string(s, 0, true),
+            Value::Datetime(s) => plain_or_quoted(s, true),
+            Value::Sequence(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|item| self.flow_item(item, ""))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Value::Mapping(map) => format!(
+                "{{{}}}",
+                map.iter()
+                    .map(|(k, v)| format!("{}: {}", key_or_quoted(k, true), self.flow_item(v, k)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    fn flow_item(&mut self, value: &Value, name: &str) -> String {
+        match self.reference(value, name) {
+            Some(Reference::Alias(name)) => format!("*{}", name),
+            Some(Reference::Anchor(name)) => format!("&{} {}", name, self.flow(value)),
+            None => self.flow(value),
+        }
+    }
+
+    fn string(&self, s: &str, level: usize, in_flow: bool) -> String {
+        match self.options.quote_style {
+            QuoteStyle::Auto => {
+                if !in_flow && literal_block_allowed(s) {
+                    literal_block(s, level)
+                } else {
+                    plain_or_quoted(s, in_flow)
+                }
+            }
+            QuoteStyle::Single if single_quote_allowed(s) => single_quoted(s),
+            QuoteStyle::Single | QuoteStyle::Double => double_quoted(s),
+        }
+    }
+}
+
+// Repeated collections are written once with an `&anchor` and then as
+// `*alias`es. The document is walked in the order it's written, and only
+// collections that are actually written again get an anchor, so one nested
+// inside an aliased collection isn't anchored for nothing.
+struct Anchors {
+    aliased: HashSet<String>,
+    // The anchor name of each collection written so far, keyed by its JSON.
+    written: HashMap<String, String>,
+    names: HashSet<String>,
+}
+
+enum Reference {
+    Anchor(String),
+    Alias(String),
+}
+
+impl Anchors {
+    fn new(value: &Value) -> Self {
+        let mut counts = HashMap::new();
+        count_collections(value, &mut counts);
+        let mut seen = HashSet::new();
+        let mut aliased = HashSet::new();
+        find_aliases(value, &counts, &mut seen, &mut aliased);
+        Self {
+            aliased,
+            written: HashMap::new(),
+            names: HashSet::new(),
+        }
+    }
+
+    // `name` is the key the value is under, if any, which is used to name
+    // the anchor.
+    fn reference(&mut self, value: &Value, name: &str) -> Option<Reference> {
+        if !is_collection(value) || self.aliased.is_empty() {
+            return None;
+        }
+        let key = inline_json(value);
+        if !self.aliased.contains(&key) {
+            return None;
+        }
+        if let Some(anchor) = self.written.get(&key) {
+            return Some(Reference::Alias(anchor.clone()));
+        }
+        let base: String = name
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+            .collect();
+        let base = if base.is_empty() {
+            "anchor".to_string()
+        } else {
+            base
+        };
+        let mut anchor = base.clone();
+        let mut n = 1;
+        while !self.names.insert(anchor.clone()) {
+            n += 1;
+            anchor = format!("{}{}", base, n);
+        }
+        self.written.insert(key, anchor.clone());
+        Some(Reference::Anchor(anchor))
+    }
+}
+
+// The `&anchor` and `!tag` written before a node.
+struct Properties(Vec<String>);
+
+fn properties(anchor: Option<&str>, tag: Option<&str>) -> Properties {
+    Properties(
+        anchor
+            .map(|name| format!("&{}", name))
+            .into_iter()
+            .chain(tag.map(String::from))
+            .collect(),
+    )
+}
+
+impl Properties {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // At the end of the line that introduces a block collection.
+    fn after_line(&self) -> String {
+        self.0.iter().map(|p| format!(" {}", p)).collect()
+    }
+
+    // Before a value on the same line.
+    fn before_value(&self) -> String {
+        self.0.iter().map(|p| format!("{} ", p)).collect()
+    }
+}
+
+// A mapping with a single key such as `!Ref` is how a tagged value is read
+// with `--yaml-tags wrap`, so it's written back as the tagged value.
+fn yaml_tag(value: &Value) -> Option<(&str, &Value)> {
+    let Value::Mapping(map) = value else {
+        return None;
+    };
+    let (key, inner) = map.first().filter(|_| map.len() == 1)?;
+    is_tag(key).then_some((key.as_str(), inner))
+}
+
+pub(crate) fn is_tag(key: &str) -> bool {
+    key.strip_prefix('!').is_some_and(|name| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.:/!#".contains(c))
+    })
+}
+
+fn split_tag(value: &Value) -> (Option<&str>, &Value) {
+    match yaml_tag(value) {
+        Some((tag, inner)) => (Some(tag), inner),
+        None => (None, value),
+    }
+}
+
+fn is_collection(value: &Value) -> bool {
+    match value {
+        Value::Mapping(map) => !map.is_empty(),
+        Value::Sequence(items) => !items.is_empty(),
+        _ => false,
+    }
+}
+
+// A tagged value and its wrapper are a single YAML node, so the value itself
+// can't be anchored.
+fn children(value: &Value) -> Box<dyn Iterator<Item = &Value> + '_> {
+    match split_tag(value).1 {
+        Value::Mapping(map) => Box::new(map.values()),
+        Value::Sequence(items) => Box::new(items.iter()),
+        _ => Box::new(std::iter::empty()),
+    }
+}
+
+fn count_collections(value: &Value, counts: &mut HashMap<String, usize>) {
+    if is_collection(value) {
+        *counts.entry(inline_json(value)).or_default() += 1;
+    }
+    children(value).for_each(|child| count_collections(child, counts));
+}
+
+fn find_aliases(
+    value: &Value,
+    counts: &HashMap<String, usize>,
+    seen: &mut HashSet<String>,
+    aliased: &mut HashSet<String>,
+) {
+    if is_collection(value) {
+        let key = inline_json(value);
+        if counts[&key] > 1 && !seen.insert(key.clone()) {
+            aliased.insert(key);
+            return;
+        }
+    }
+    children(value).for_each(|child| find_aliases(child, counts, seen, aliased));
+}
+
+pub(crate) fn yaml_key(key: &str) -> String {
+    key_or_quoted(key, false)
+}
+
+// A plain `<<` key is read as a merge key, so a `<<` string is quoted.
+fn key_or_quoted(key: &str, in_flow: bool) -> String {
+    if key == "<<" {
+        single_quoted(key)
+    } else {
+        plain_or_quoted(key, in_flow)
+    }
+}
+
+fn yaml_float(f: f64) -> String {
+    if f.is_nan() {
+        ".nan".to_string()
+    } else if f.is_infinite() {
+        if f.is_sign_positive() {
+            ".inf".to_string()
+        } else {
+            "-.inf".to_string()
+        }
+    } else {
+        // Debug formatting always keeps a decimal point or exponent, so the
+        // value isn't read back as an integer.
+        format!("{:?}", f)
+    }
+}
+
+// YAML 1.1 parsers still read these as booleans.
+pub(crate) const YAML_1_1_BOOLEANS: [&str; 16] = [
+    "y", "Y", "yes", "Yes", "YES", "n", "N", "no", "No", "NO", "on", "On", "ON", "off", "Off",
+    "OFF",
+];
+
+fn plain_allowed(s: &str, in_flow: bool) -> bool {
+    if s.is_empty() || YAML_1_1_BOOLEANS.contains(&s) || s.chars().any(char::is_control) {
+        return false;
+    }
+    if in_flow && s.contains([',', '[', ']', '{', '}']) {
+        return false;
+    }
+    // A plain scalar is only safe if parsing it gives back the same string.
+    matches!(
+        serde_yaml::from_str::<YamlValue>(s),
+        Ok(YamlValue::String(parsed)) if parsed == s
+    )
+}
+
+fn plain_or_quoted(s: &str, in_flow: bool) -> String {
+    if plain_allowed(s, in_flow) {
+        s.to_string()
+    } else if single_quote_allowed(s) {
+        single_quoted(s)
+    } else {
+        double_quoted(s)
+    }
+}
+
+fn single_quote_allowed(s: &str) -> bool {
+    !s.chars().any(char::is_control)
+}
+
+fn single_quoted(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+// A JSON string is also a valid double quoted YAML scalar.
+fn double_quoted(s: &str) -> String {
+    serde_json::to_string(s).expect("strings always serialize")
+}
+
+// A block without a line of content reads back as an empty string, so
+// strings of only line breaks and spaces are quoted instead.
+fn literal_block_allowed(s: &str) -> bool {
+    s.contains('\n')
+        && s.split('\n').any(|line| !line.trim().is_empty())
+        && !s.chars().any(|c| c.is_control() && c != '\n')
+        && !s
+            .split('\n')
+            .find(|line| !line.is_empty())
+            .is_some_and(|line| line.starts_with(' '))
+}
+
+fn literal_block(s: &str, level: usize) -> String {
+    let (content, chomping) = if s.ends_with("\n\n") {
+        (&s[..s.len() - 1], "+")
+    } else if let Some(content) = s.strip_suffix('\n') {
+        (content, "")
+    } else {
+        (s, "-")
+    };
+    let mut block = format!("|{}", chomping);
+    for line in content.split('\n') {
+        block.push('\n');
+        if !line.is_empty() {
+            block.push_str(&" ".repeat(level));
+            block.push_str(line);
+        }
+    }
+    block
+}
+
+pub fn to_toml_string(value: &Value, options: &OutputOptions) -> Result<String, X2YError> {
+    let toml_value = to_toml(value, "")?;
+    let Ok(mut table) = TomlItem::Value(toml_value).into_table() else {
+        return Err(X2YError::unsupported(
+            Format::Toml,
+            "",
+            "toml documents must have a table at the top level",
+        ));
+    };
+    let mut formatter = TomlFormatter {
+        multiline_array: !options.compact,
+        indent: " ".repeat(options.indent.unwrap_or(DEFAULT_TOML_INDENT)),
+        table_style: options.table_style,
+        is_value: false,
+    };
+    formatter.visit_table_mut(&mut table);
+    let document: toml_edit::Document = table.into();
+    Ok(finish(document.to_string(), options))
+}
+
+pub(crate) fn to_toml(value: &Value, path: &str) -> Result<TomlValue, X2YError> {
+    Ok(match value {
+        Value::Null => {
+            return Err(X2YError::unsupported(
+                Format::Toml,
+                path,
+                "toml does not support null values",
+            ))
+        }
+        Value::Bool(b) => (*b).into(),
+        Value::Integer(i) => match i64::try_from(*i) {
+            Ok(i) => i.into(),
+            Err(_) => {
+                return Err(X2YError::unsupported(
+                    Format::Toml,
+                    path,
+                    format!("{} is out of range for a toml integer", i),
+                ))
+            }
+        },
+        Value::Float(f) => (*f).into(),
+        Value::String(s) => s.as_str().into(),
+        Value::Datetime(s) => match s.parse::<toml_edit::Datetime>() {
+            Ok(d) => d.into(),
+            Err(_) => s.as_str().into(),
+        },
+        Value::Sequence(items) => {
+            let mut array = toml_edit::Array::new();
+            for (i, item) in items.iter().enumerate() {
+                array.push_formatted(to_toml(item, &index_path(path, i))?);
+            }
+            TomlValue::Array(array)
+        }
+        Value::Mapping(map) => {
+            let mut table = toml_edit::InlineTable::new();
+            for (k, v) in map {
+                table.insert(k.as_str(), to_toml(v, &key_path(path, k))?);
+            }
+            TomlValue::InlineTable(table)
+        }
+    })
+}
+
+// Mirrors the formatting `toml::to_string_pretty` applies, with the table
+// layout and array indentation taken from the output options.
+struct TomlFormatter {
+    multiline_array: bool,
+    indent: String,
+    table_style: TableStyle,
+    is_value: bool,
+}
+
+impl VisitMut for TomlFormatter {
+    fn visit_item_mut(&mut self, node: &mut TomlItem) {
+        let is_parent_value = self.is_value;
+        if !is_parent_value && self.table_style != TableStyle::Inline {
+            let other = std::mem::take(node);
+            let other = match other.into_table() {
+                Ok(mut table) => {
+                    table.set_dotted(self.table_style == TableStyle::Dotted);
+                    TomlItem::Table(table)
+                }
+                Err(other) => other,
+            };
+            let other = match other.into_array_of_tables() {
+                Ok(array) => TomlItem::ArrayOfTables(array),
+                Err(other) => other,
+            };
+            *node = other;
+        }
+        self.is_value = node.is_value();
+
+        visit_mut::visit_item_mut(self, node);
+        self.is_value = is_parent_value;
+    }
+
+    fn visit_table_mut(&mut self, node: &mut toml_edit::Table) {
+        node.decor_mut().clear();
+
+        // Empty tables could be semantically meaningful, so make sure they are not implicit
+        if !node.is_empty() {
+            node.set_implicit(true);
+        }
+
+        visit_mut::visit_table_mut(self, node);
+    }
+
+    fn visit_value_mut(&mut self, node: &mut TomlValue) {
+        node.decor_mut().clear();
+
+        visit_mut::visit_value_mut(self, node);
+    }
+
+    fn visit_array_mut(&mut self, node: &mut toml_edit::Array) {
+        visit_mut::visit_array_mut(self, node);
+
+        if !self.multiline_array || (0..=1).contains(&node.len()) {
+            node.set_trailing("");
+            node.set_trailing_comma(false);
+        } else {
+            for item in node.iter_mut() {
+                item.decor_mut().set_prefix(format!("\n{}", self.indent));
+            }
+            node.set_trailing("\n");
+            node.set_trailing_comma(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::LineEnding;
+    use crate::transcoder::decode;
+
+    fn json(contents: &str) -> Value {
+        decode(contents, Format::Json).unwrap()
+    }
+
+    #[test]
+    fn toml_refuses_integers_outside_64_bits() {
+        let value = json(r#"{"big": 18446744073709551615}"#);
+
+        let error = to_toml_string(&value, &OutputOptions::default()).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "unsupported toml value at .big: 18446744073709551615 is out of range for a toml integer"
+        );
+    }
+
+    #[test]
+    fn yaml_strings_are_only_quoted_when_ambiguous() {
+        let value = json(r#"{"plain": "text", "number": "123", "bool": "yes", "pair": "a: b"}"#);
+
+        let output = to_yaml_string(&value, &OutputOptions::default()).unwrap();
+
+        assert_eq!(
+            output,
+            "plain: text\nnumber: '123'\nbool: 'yes'\npair: 'a: b'\n"
+        );
+    }
+
+    #[test]
+    fn yaml_merge_key_strings_round_trip() {
+        let value = json(r#"{"<<": {"a": 1}, "b": "<<", "c": [{"<<": 2}]}"#);
+        let compact = OutputOptions {
+            compact: true,
+            ..Default::default()
+        };
+
+        for options in [OutputOptions::default(), compact] {
+            let output = to_yaml_string(&value, &options).unwrap();
+
+            assert!(output.starts_with("'<<':") || output.starts_with("{'<<':"));
+            assert_eq!(
+                crate::transcoder::decode(&output, Format::Yaml).unwrap(),
+                value,
+                "read back from {:?}",
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn yaml_multi_line_strings_are_literal_blocks() {
+        let value = json(r#"{"script": "one\ntwo\n"}"#);
+
+        let output = to_yaml_string(&value, &OutputOptions::default()).unwrap();
+
+        assert_eq!(output, "script: |\n  one\n  two\n");
+    }
+
+    #[test]
+    fn yaml_strings_of_only_line_breaks_and_spaces_round_trip() {
+        for s in ["\n", "\n\n", "  \n", "\n  \n", "a\n\n"] {
+            let value = Value::Mapping(Mapping::from_iter([(
+                "a".to_string(),
+                Value::String(s.to_string()),
+            )]));
+
+            let output = to_yaml_string(&value, &OutputOptions::default()).unwrap();
+
+            assert_eq!(
+                crate::transcoder::decode(&output, Format::Yaml).unwrap(),
+                value,
+                "{:?} was written as {:?}",
+                s,
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn yaml_flow_sequences_and_indentation() {
+        let value = json(r#"{"outer": {"items": [1, "a,b", {"k": null}]}}"#);
+        let options = OutputOptions {
+            indent: Some(4),
+            sequence_style: SequenceStyle::Flow,
+            ..Default::default()
+        };
+
+        let output = to_yaml_string(&value, &options).unwrap();
+
+        assert_eq!(output, "outer:\n    items: [1, 'a,b', {k: null}]\n");
+    }
+
+    #[test]
+    fn repeated_yaml_collections_become_aliases() {
+        let value = json(
+            r#"{"defaults": {"image": "rust", "tags": ["a"]}, "build": {"image": "rust", "tags": ["a"]},
+                "jobs": [{"tags": ["a"]}, {"image": "rust", "tags": ["a"]}]}"#,
+        );
+        let options = OutputOptions {
+            yaml_anchors: true,
+            ..Default::default()
+        };
+
+        let output = to_yaml_string(&value, &options).unwrap();
+
+        assert_eq!(
+            output,
+            "defaults: &defaults\n  image: rust\n  tags: &tags\n  - a\nbuild: *defaults\n\
+             jobs:\n- tags: *tags\n- *defaults\n"
+        );
+        assert_eq!(decode(&output, Format::Yaml).unwrap(), value);
+    }
+
+    #[test]
+    fn wrapped_values_are_written_with_their_yaml_tag() {
+        let value = json(
+            r#"{"name": {"!Sub": "${Env}-data"}, "arn": {"!GetAtt": ["Bucket", "Arn"]},
+                "refs": [{"!Ref": "A"}, {"!If": {"a": 1}}]}"#,
+        );
+
+        let output = to_yaml_string(&value, &OutputOptions::default()).unwrap();
+
+        assert_eq!(
+            output,
+            "name: !Sub ${Env}-data\narn: !GetAtt\n- Bucket\n- Arn\n\
+             refs:\n- !Ref A\n- !If\n  a: 1\n"
+        );
+        assert_eq!(decode(&output, Format::Yaml).unwrap(), value);
+    }
+
+    #[test]
+    fn yaml_double_quote_style() {
+        let value = json(r#"{"name": "it's"}"#);
+        let options = OutputOptions {
+            quote_style: QuoteStyle::Double,
+            ..Default::default()
+        };
+
+        let output = to_yaml_string(&value, &options).unwrap();
+
+        assert_eq!(output, "name: \"it's\"\n");
+    }
+
+    #[test]
+    fn compact_json_without_a_trailing_newline() {
+        let value = json(r#"{"a": [1, 2]}"#);
+        let options = OutputOptions {
+            compact: true,
+            trailing_newline: false,
+            ..Default::default()
+        };
+
+        let output = to_json_string(&value, &options).unwrap();
+
+        assert_eq!(output, r#"{"a":[1,2]}"#);
+    }
+
+    #[test]
+    fn toml_table_styles() {
+        let value = json(r#"{"a": 1, "server": {"host": "x", "port": 80}}"#);
+        let inline = OutputOptions {
+            table_style: TableStyle::Inline,
+            ..Default::default()
+        };
+        let dotted = OutputOptions {
+            table_style: TableStyle::Dotted,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            to_toml_string(&value, &OutputOptions::default()).unwrap(),
+            "a = 1\n\n[server]\nhost = \"x\"\nport = 80\n"
+        );
+        assert_eq!(
+            to_toml_string(&value, &inline).unwrap(),
+            "a = 1\nserver = { host = \"x\", port = 80 }\n"
+        );
+        assert_eq!(
+            to_toml_string(&value, &dotted).unwrap(),
+            "a = 1\nserver.host = \"x\"\nserver.port = 80\n"
+        );
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let value = json(r#"{"a": 1, "b": 2}"#);
+        let options = OutputOptions {
+            line_ending: LineEnding::Crlf,
+            ..Default::default()
+        };
+
+        let output = to_yaml_string(&value, &options).unwrap();
+
+        assert_eq!(output, "a: 1\r\nb: 2\r\n");
+    }
+
+    #[test]
+    fn canonical_json_follows_rfc_8785() {
+        let value = json(
+            r#"{"numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001, -0],
+                "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+                "literals": [null, true, false]}"#,
+        );
+        let options = OutputOptions {
+            canonical: true,
+            indent: Some(4),
+            ..Default::default()
+        };
+
+        let output = to_json_string(&value, &options).unwrap();
+
+        assert_eq!(
+            output,
+            r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27,0],"string":"€$\u000f\nA'B\"\\\\\"/"}"#
+        );
+    }
+
+    #[test]
+    fn canonical_json_sorts_keys_by_utf16_code_units() {
+        let value = json(r#"{"\u20ac": 1, "\r": 2, "\ud83d\ude00": 3, "1": 4, "\u00f6": 5}"#);
+        let options = OutputOptions {
+            canonical: true,
+            ..Default::default()
+        };
+
+        let output = to_json_string(&value, &options).unwrap();
+
+        assert_eq!(output, "{\"\\r\":2,\"1\":4,\"ö\":5,\"€\":1,\"😀\":3}");
+    }
+}
@@ -1,13 +1,357 @@
-use std::error;
+use std::fmt;
 use std::io;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+use crate::format::Format;
+use crate::limits::Limit;
+
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum X2YError {
     #[error("the input was incorrect: `{0}`")]
     InvalidInput(String),
-    #[error("file system issue")]
-    IO(#[from] io::Error),
-    #[error("could not transcode: `{0}`")]
-    Transcode(#[from] Box<dyn error::Error>),
+    #[error("file system issue{}: {source}", in_path(.path))]
+    IO {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    #[error("{0}")]
+    Parse(Box<ParseError>),
+    #[error("{0}")]
+    Unsupported(Box<UnsupportedValue>),
+    #[error("invalid query `{expression}`: {message}")]
+    Query { expression: String, message: String },
+    /// A valid query that can't be run on the document, such as `.[0]` on a
+    /// mapping.
+    #[error("query `{expression}` failed: {message}")]
+    QueryFailed { expression: String, message: String },
+    #[error("{0}")]
+    Patch(Box<PatchError>),
+    #[error("{0}")]
+    Validation(Box<ValidationError>),
+    #[error("{0}")]
+    Verification(Box<VerificationError>),
+    #[error("{0}")]
+    Limit(Box<LimitError>),
+}
+
+fn in_path(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|p| format!(" with {}", p.display()))
+        .unwrap_or_default()
+}
+
+impl From<io::Error> for X2YError {
+    fn from(source: io::Error) -> Self {
+        X2YError::IO { path: None, source }
+    }
+}
+
+impl X2YError {
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        X2YError::IO {
+            path: Some(path.to_path_buf()),
+            source,
+        }
+    }
+
+    pub fn unsupported(format: Format, value_path: &str, message: impl Into<String>) -> Self {
+        X2YError::Unsupported(Box::new(UnsupportedValue {
+            format,
+            message: message.into(),
+            value_path: crate::value::display_path(value_path).to_string(),
+            path: None,
+        }))
+    }
+
+    /// A stable identifier for the kind of error, see the README for the full list.
+    pub fn code(&self) -> &'static str {
+        match self {
+            X2YError::InvalidInput(_) => "E001",
+            X2YError::IO { .. } => "E002",
+            X2YError::Parse(e) => match e.format {
+                Format::Yaml => "E101",
+                Format::Json => "E102",
+                Format::Toml => "E103",
+            },
+            X2YError::Unsupported(_) => "E200",
+            X2YError::Query { .. } => "E300",
+            X2YError::QueryFailed { .. } => "E301",
+            X2YError::Patch(_) => "E400",
+            X2YError::Validation(_) => "E500",
+            X2YError::Verification(_) => "E600",
+            X2YError::Limit(_) => "E700",
+        }
+    }
+
+    /// The class of error as used in JSON error output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            X2YError::InvalidInput(_) => "invalid_input",
+            X2YError::IO { .. } => "io",
+            X2YError::Parse(_) => "parse",
+            X2YError::Unsupported(_) => "unsupported",
+            X2YError::Query { .. } => "query",
+            X2YError::QueryFailed { .. } => "query_failed",
+            X2YError::Patch(_) => "patch",
+            X2YError::Validation(_) => "validation",
+            X2YError::Verification(_) => "verification",
+            X2YError::Limit(_) => "limit",
+        }
+    }
+
+    /// The process exit code for the error, see the README for the full list.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            X2YError::InvalidInput(_) => 2,
+            X2YError::IO { .. } => 3,
+            X2YError::Parse(_) => 4,
+            X2YError::Unsupported(_) => 5,
+            X2YError::Query { .. } => 2,
+            X2YError::QueryFailed { .. } => 10,
+            X2YError::Patch(_) => 6,
+            X2YError::Validation(_) => 7,
+            X2YError::Verification(_) => 8,
+            X2YError::Limit(_) => 9,
+        }
+    }
+
+    /// The file the error relates to, if any.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            X2YError::InvalidInput(_) | X2YError::Query { .. } | X2YError::QueryFailed { .. } => {
+                None
+            }
+            X2YError::IO { path, .. } => path.as_deref(),
+            X2YError::Parse(e) => e.path.as_deref(),
+            X2YError::Unsupported(e) => e.path.as_deref(),
+            X2YError::Patch(e) => e.path.as_deref(),
+            X2YError::Validation(e) => e.path.as_deref(),
+            X2YError::Verification(e) => e.path.as_deref(),
+            X2YError::Limit(e) => e.path.as_deref(),
+        }
+    }
+
+    /// Records the file the error relates to, if it doesn't already have one.
+    pub fn with_path(mut self, file: &Path) -> Self {
+        let path = match &mut self {
+            X2YError::InvalidInput(_) | X2YError::Query { .. } | X2YError::QueryFailed { .. } => {
+                return self
+            }
+            X2YError::IO { path, .. } => path,
+            X2YError::Parse(e) => &mut e.path,
+            X2YError::Unsupported(e) => &mut e.path,
+            X2YError::Patch(e) => &mut e.path,
+            X2YError::Validation(e) => &mut e.path,
+            X2YError::Verification(e) => &mut e.path,
+            X2YError::Limit(e) => &mut e.path,
+        };
+        if path.is_none() {
+            *path = Some(file.to_path_buf());
+        }
+        self
+    }
+}
+
+/// A 1-based position in the source text.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    pub fn from_offset(contents: &str, offset: usize) -> Self {
+        let before = &contents[..offset.min(contents.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+/// The input couldn't be read as the given format.
+#[derive(Debug)]
+pub struct ParseError {
+    pub format: Format,
+    pub message: String,
+    pub path: Option<PathBuf>,
+    pub location: Option<Location>,
+    /// The source line the location points at.
+    pub source_line: Option<String>,
+}
+
+impl ParseError {
+    pub fn new(
+        format: Format,
+        message: String,
+        contents: &str,
+        location: Option<Location>,
+    ) -> Self {
+        let source_line = location.and_then(|l| contents.lines().nth(l.line - 1).map(String::from));
+        Self::with_source_line(format, message, location, source_line)
+    }
+
+    /// A parse error where the source line was kept as the input was read,
+    /// rather than the whole input.
+    pub fn with_source_line(
+        format: Format,
+        message: String,
+        location: Option<Location>,
+        source_line: Option<String>,
+    ) -> Self {
+        Self {
+            format,
+            message,
+            path: None,
+            location,
+            source_line,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid {}", self.format)?;
+        if let Some(path) = &self.path {
+            write!(f, " in {}", path.display())?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(location) = self.location {
+            write!(f, " at line {} column {}", location.line, location.column)?;
+        }
+        Ok(())
+    }
+}
+
+/// A value that can't be represented in a format.
+#[derive(Debug)]
+pub struct UnsupportedValue {
+    pub format: Format,
+    pub message: String,
+    /// Where the value is in the document, e.g. `.servers[0].port`.
+    pub value_path: String,
+    pub path: Option<PathBuf>,
+}
+
+impl fmt::Display for UnsupportedValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unsupported {} value at {}",
+            self.format, self.value_path
+        )?;
+        if let Some(path) = &self.path {
+            write!(f, " in {}", path.display())?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// A patch couldn't be applied to a document.
+#[derive(Debug)]
+pub struct PatchError {
+    /// The position of the failed operation in a JSON Patch, starting at 0.
+    pub operation: Option<usize>,
+    pub message: String,
+    pub path: Option<PathBuf>,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to apply the patch")?;
+        if let Some(path) = &self.path {
+            write!(f, " to {}", path.display())?;
+        }
+        if let Some(operation) = self.operation {
+            write!(f, ", operation {}", operation)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// A document doesn't match a JSON Schema.
+#[derive(Debug)]
+pub struct ValidationError {
+    pub violations: Vec<Violation>,
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    /// Where the value is in the document, e.g. `.servers[0].port`.
+    pub value_path: String,
+    pub message: String,
+    /// Where the value is in the source, when it can be found.
+    pub location: Option<Location>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{} doesn't match the schema", path.display())?,
+            None => write!(f, "the document doesn't match the schema")?,
+        }
+        for violation in &self.violations {
+            write!(f, "\n  {}: {}", violation.value_path, violation.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// A conversion would lose or change values.
+#[derive(Debug)]
+pub struct VerificationError {
+    /// The format the document was converted to.
+    pub format: Format,
+    pub losses: Vec<Violation>,
+    pub path: Option<PathBuf>,
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(
+                f,
+                "{} can't be converted to {} without loss",
+                path.display(),
+                self.format
+            )?,
+            None => write!(
+                f,
+                "the document can't be converted to {} without loss",
+                self.format
+            )?,
+        }
+        for loss in &self.losses {
+            write!(f, "\n  {}: {}", loss.value_path, loss.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// A document is over one of the limits on what is read.
+#[derive(Debug)]
+pub struct LimitError {
+    pub limit: Limit,
+    pub maximum: usize,
+    /// Where the limit was reached in the document, when that's known.
+    pub value_path: Option<String>,
+    pub path: Option<PathBuf>,
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}", path.display())?,
+            None => write!(f, "the document")?,
+        }
+        write!(f, " is over the limit of {} {}", self.maximum, self.limit)?;
+        if let Some(value_path) = &self.value_path {
+            write!(f, " at {}", value_path)?;
+        }
+        Ok(())
+    }
 }
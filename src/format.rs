@@ -1,30 +1,52 @@
 use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
 
+use clap::ValueEnum;
+
+use crate::detect::{self, Detection};
 use crate::error::X2YError;
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, ValueEnum)]
 pub enum Format {
+    #[value(alias = "yml")]
     Yaml,
     Json,
     Toml,
 }
 
+impl Format {
+    /// Guesses the format from the content rather than a file extension.
+    ///
+    /// ```
+    /// use x2y::{Content, Format};
+    ///
+    /// let detection = Format::detect(br#"{"name": "x2y"}"#);
+    /// assert_eq!(detection.best().unwrap().content, Content::Format(Format::Json));
+    /// ```
+    pub fn detect(bytes: &[u8]) -> Detection {
+        detect::detect(bytes)
+    }
+}
+
+/// Parses a format name or one of its aliases, such as `yml`.
+impl FromStr for Format {
+    type Err = X2YError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        <Format as ValueEnum>::from_str(s, true)
+            .map_err(|_| X2YError::InvalidInput(format!("{} is not a supported file format.", s)))
+    }
+}
+
 impl TryFrom<&Path> for Format {
     type Error = X2YError;
 
     fn try_from(s: &Path) -> Result<Self, Self::Error> {
         if let Some(extension) = s.extension() {
             if let Some(format) = extension.to_str() {
-                match format.trim() {
-                    "yaml" => Ok(Self::Yaml),
-                    "yml" => Ok(Self::Yaml),
-                    "json" => Ok(Self::Json),
-                    "toml" => Ok(Self::Toml),
-                    other => Err(X2YError::InvalidInput(format!(
-                        "{} is not a supported file format.",
-                        other
-                    ))),
-                }
+                format.parse()
             } else {
                 Err(X2YError::InvalidInput(format!(
                     "{} contains invalid unicode.",
@@ -32,16 +54,7 @@ impl TryFrom<&Path> for Format {
                 )))
             }
         } else if let Some(s) = s.to_str() {
-            match s.trim() {
-                "yaml" => Ok(Self::Yaml),
-                "yml" => Ok(Self::Yaml),
-                "json" => Ok(Self::Json),
-                "toml" => Ok(Self::Toml),
-                other => Err(X2YError::InvalidInput(format!(
-                    "{} is not a supported file format.",
-                    other
-                ))),
-            }
+            s.parse()
         } else {
             Err(X2YError::InvalidInput(format!(
                 "{:?} has no retrievable extension.",
@@ -98,4 +111,12 @@ mod tests {
         let format = Format::try_from(path).unwrap();
         assert_eq!(Format::Yaml, format);
     }
+
+    #[test]
+    fn a_format_is_parsed_from_its_name_or_an_alias() {
+        assert_eq!("json".parse::<Format>().unwrap(), Format::Json);
+        assert_eq!("yml".parse::<Format>().unwrap(), Format::Yaml);
+        assert_eq!(" TOML".parse::<Format>().unwrap(), Format::Toml);
+        assert_err!("jsn".parse::<Format>());
+    }
 }
@@ -1,78 +1,309 @@
-use crate::error::X2YError;
+use crate::cache::{self, Cache};
+use crate::error::{VerificationError, Violation, X2YError};
 use crate::format::Format;
+use crate::limits;
+use crate::options::{InputOptions, OutputOptions};
+use crate::schema::Schema;
 use crate::transcoder;
 use crate::traversal::walk_dir;
+use crate::verify;
 use std::fs::{self, DirEntry, File};
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
 
+/// What is checked before a file is replaced by its conversion.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Checks<'a> {
+    /// Validate each document against a JSON Schema.
+    pub schema: Option<&'a Schema>,
+    /// Read the output back and fail if any value was lost or changed type.
+    pub verify: bool,
+    /// Fail instead of warning when a value is changed to fit the output format.
+    pub strict: bool,
+}
+
+/// How a file found in a directory is converted.
+#[derive(Clone, Debug)]
+pub struct Plan {
+    pub input_format: Format,
+    pub output_format: Format,
+    pub options: OutputOptions,
+}
+
+impl Plan {
+    /// Whether `file` is a source when sources are kept: it has the extension
+    /// of the input format, so it isn't the output of another conversion.
+    pub fn derives_from(&self, file: &Path) -> bool {
+        self.input_format != self.output_format
+            && Format::try_from(file).is_ok_and(|format| format == self.input_format)
+    }
+}
+
+/// Converts every file in `directory` that `plan` has a conversion for,
+/// returning the warnings for each file that had values changed to fit the
+/// output format. With a `cache` the sources are kept, those unchanged since
+/// the last run are skipped and outputs left behind are removed.
 pub fn process_directory(
     directory: &Path,
-    input_format: &Path,
-    output_format: &Path,
-) -> Result<(), X2YError> {
+    input_options: &InputOptions,
+    checks: &Checks,
+    plan: impl Fn(&Path) -> Result<Option<Plan>, X2YError>,
+    cache: Option<&mut Cache>,
+) -> Result<Vec<(PathBuf, Vec<Violation>)>, X2YError> {
     let mut files: Vec<DirEntry> = Vec::new();
     walk_dir(directory, &mut files);
-    if files.is_empty() {
+    let mut plans = Vec::new();
+    for f in files {
+        let file_path = f.path();
+        if let Some(plan) = plan(&file_path)? {
+            if let Some(cache) = &cache {
+                if cache.is_cache(&file_path) || !plan.derives_from(&file_path) {
+                    continue;
+                }
+            }
+            plans.push((file_path, plan));
+        }
+    }
+    if plans.is_empty() {
         return Err(X2YError::InvalidInput(format!(
-            "Directory: {:?} contains no files with this: {:?} format",
-            directory, input_format
+            "Directory: {:?} contains no files to convert",
+            directory
         )));
     }
-    for f in files {
-        let input_format = input_format.try_into()?;
-        let output_format = output_format.try_into()?;
-        log::debug!("File formats: {}\n{}", input_format, output_format);
-        let file_path = f.path();
-        let contents = fs::read_to_string(&file_path)?;
-        let output_contents = transcoder::transcode(&contents, input_format, output_format)?;
-
-        fs::remove_file(&file_path)?;
-
-        // The file could be invalid unicode when part of a directory.
-        // Ideally we would continue processing files and log the error.
-        // As of right now, a non-unicode file name stops the program.
-        let new_path = new_path(&file_path, input_format)?;
-        let mut file = File::create(format!("{}{}", new_path, output_format))?;
-        file.write_all(output_contents.as_bytes())?;
+    if let Some(cache) = cache {
+        // What was converted is kept even when a later file fails.
+        let result = process_changed(&plans, input_options, checks, cache);
+        cache.save()?;
+        return result;
     }
-    Ok(())
+    let mut warnings = Vec::new();
+    for (file_path, plan) in plans {
+        let file_warnings = process_file(
+            &file_path,
+            plan.input_format,
+            plan.output_format,
+            input_options,
+            &plan.options,
+            checks,
+        )?;
+        if !file_warnings.is_empty() {
+            warnings.push((file_path, file_warnings));
+        }
+    }
+    Ok(warnings)
 }
 
-fn new_path(file_path: &Path, input_format: Format) -> Result<&str, X2YError> {
-    match file_path.to_str() {
-        Some(str) => match str.strip_suffix(&input_format.to_string()) {
-            Some(str) => Ok(str),
-            None => Err(X2YError::InvalidInput(format!(
-                "unable to strip suffix: {:?}",
-                file_path
-            ))),
-        },
-        None => Err(X2YError::InvalidInput(format!(
-            "unable to convert path to str: {:?}",
-            file_path
-        ))),
+fn process_changed(
+    plans: &[(PathBuf, Plan)],
+    input_options: &InputOptions,
+    checks: &Checks,
+    cache: &mut Cache,
+) -> Result<Vec<(PathBuf, Vec<Violation>)>, X2YError> {
+    let mut warnings = Vec::new();
+    let mut skipped = 0;
+    for (file_path, plan) in plans {
+        let source = cache::hash_file(file_path)?;
+        // The checks, with the whole schema, count as options so a file
+        // converted without them is checked when they're added.
+        let options =
+            cache::hash(format!("{:?} {:?} {:?}", plan, input_options, checks).as_bytes());
+        let output = file_path.with_extension(plan.output_format.to_string());
+        if cache.is_fresh(file_path, source, &output, options) {
+            log::info!("Skipping unchanged file: {:?}", file_path);
+            skipped += 1;
+            continue;
+        }
+        let file_warnings = derive_file(file_path, plan, input_options, checks)?;
+        cache.record(file_path, source, &output, options)?;
+        if !file_warnings.is_empty() {
+            warnings.push((file_path.clone(), file_warnings));
+        }
     }
+    let sources: Vec<PathBuf> = plans.iter().map(|(file, _)| file.clone()).collect();
+    let removed = cache.prune(&sources);
+    log::info!(
+        "Converted {} files, skipped {} unchanged and removed {} stale outputs",
+        plans.len() - skipped,
+        skipped,
+        removed.len()
+    );
+    Ok(warnings)
+}
+
+/// Converts a single file, returning the values that were changed to fit the
+/// output format. With `checks.strict` any such change is an error instead.
+pub fn process_file(
+    file: &Path,
+    input_format: Format,
+    output_format: Format,
+    input_options: &InputOptions,
+    options: &OutputOptions,
+    checks: &Checks,
+) -> Result<Vec<Violation>, X2YError> {
+    convert_file(
+        file,
+        input_format,
+        output_format,
+        input_options,
+        options,
+        checks,
+        false,
+    )
 }
 
-pub fn process_file(file: &Path, output_format: &Path) -> Result<(), X2YError> {
-    let input_format: Format = file.try_into()?;
-    let output_format = output_format.try_into()?;
+/// Converts a single file like [`process_file`] but keeps the source, writing
+/// the output alongside it.
+pub fn derive_file(
+    file: &Path,
+    plan: &Plan,
+    input_options: &InputOptions,
+    checks: &Checks,
+) -> Result<Vec<Violation>, X2YError> {
+    convert_file(
+        file,
+        plan.input_format,
+        plan.output_format,
+        input_options,
+        &plan.options,
+        checks,
+        true,
+    )
+}
+
+fn convert_file(
+    file: &Path,
+    input_format: Format,
+    output_format: Format,
+    input_options: &InputOptions,
+    options: &OutputOptions,
+    checks: &Checks,
+    keep_source: bool,
+) -> Result<Vec<Violation>, X2YError> {
     log::debug!(
         "File formats:\n Input Format: {}\n Output Format: {}",
         input_format,
         output_format
     );
-    let contents = fs::read_to_string(file).unwrap();
-    let output_contents = transcoder::transcode(&contents, input_format, output_format)?;
+    let new_path = file.with_extension(output_format.to_string());
+    if new_path == file {
+        return Err(X2YError::InvalidInput(format!(
+            "{} would be overwritten by its own conversion, rename it first",
+            file.display()
+        )));
+    }
+    // Schemas and verification need the whole document.
+    if checks.schema.is_none()
+        && !checks.verify
+        && transcoder::streams(input_format, output_format, options)
+    {
+        stream_file(
+            file,
+            &new_path,
+            input_format,
+            output_format,
+            input_options,
+            options,
+        )?;
+        if !keep_source {
+            fs::remove_file(file).map_err(|e| X2YError::io(file, e))?;
+        }
+        return Ok(Vec::new());
+    }
+    let contents = limits::read_to_string(file, &input_options.limits)?;
+    if let Some(schema) = checks.schema {
+        schema
+            .check(&contents, input_format, input_options)
+            .map_err(|e| e.with_path(file))?;
+    }
+    let (output_contents, warnings) = transcoder::transcode_with_warnings(
+        &contents,
+        input_format,
+        output_format,
+        input_options,
+        options,
+    )
+    .map_err(|e| e.with_path(file))?;
+    if checks.strict && !warnings.is_empty() {
+        return Err(X2YError::Verification(Box::new(VerificationError {
+            format: output_format,
+            losses: warnings,
+            path: Some(file.to_path_buf()),
+        })));
+    }
+    if checks.verify {
+        verify::verify(
+            &contents,
+            input_format,
+            &output_contents,
+            output_format,
+            input_options,
+        )
+        .map_err(|e| e.with_path(file))?;
+    }
 
-    fs::remove_file(file)?;
+    write_atomically(&new_path, &output_contents)?;
+    if !keep_source {
+        fs::remove_file(file).map_err(|e| X2YError::io(file, e))?;
+    }
+    Ok(warnings)
+}
+
+/// Writes `contents` to `path` like a conversion's output, so a failure leaves
+/// an existing file as it was. A symlink is followed, and the file it replaces
+/// keeps its permissions.
+pub(crate) fn write_atomically(path: &Path, contents: &str) -> Result<(), X2YError> {
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut output = temporary_output(&target)?;
+    if let Ok(metadata) = fs::metadata(&target) {
+        output
+            .as_file()
+            .set_permissions(metadata.permissions())
+            .map_err(|e| X2YError::io(path, e))?;
+    }
+    output
+        .write_all(contents.as_bytes())
+        .map_err(|e| X2YError::io(path, e))?;
+    output
+        .persist(&target)
+        .map_err(|e| X2YError::io(path, e.error))?;
+    Ok(())
+}
+
+// Outputs are written to a temporary file next to them and only moved into
+// place once they're complete, so a failure never leaves a partial output or
+// touches an existing file.
+fn temporary_output(new_path: &Path) -> Result<NamedTempFile, X2YError> {
+    let directory = match new_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    NamedTempFile::new_in(directory).map_err(|e| X2YError::io(new_path, e))
+}
 
-    // The file could be invalid unicode when part of a directory.
-    // Ideally we would continue processing files and log the error.
-    // As of right now, a non-unicode file name stops the program.
-    let new_path = new_path(file, input_format)?;
-    let mut file = File::create(format!("{}{}", new_path, output_format))?;
-    file.write_all(output_contents.as_bytes())?;
+// The output is written as the source is read, so the source is only removed
+// once it has all been converted.
+fn stream_file(
+    file: &Path,
+    new_path: &Path,
+    input_format: Format,
+    output_format: Format,
+    input_options: &InputOptions,
+    options: &OutputOptions,
+) -> Result<(), X2YError> {
+    let reader = File::open(file).map_err(|e| X2YError::io(file, e))?;
+    let mut output = temporary_output(new_path)?;
+    transcoder::transcode_stream(
+        reader,
+        output.as_file_mut(),
+        input_format,
+        output_format,
+        input_options,
+        options,
+    )
+    .map_err(|e| e.with_path(file))?;
+    output
+        .persist(new_path)
+        .map_err(|e| X2YError::io(new_path, e.error))?;
     Ok(())
 }
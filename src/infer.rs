@@ -0,0 +1,222 @@
+use std::collections::BTreeSet;
+
+use indexmap::IndexMap;
+
+use crate::value::{Mapping, Value};
+
+// Infers a JSON Schema (draft 2020-12) that every one of a set of sample
+// documents matches. Each position in the documents is summarised as a
+// `Shape`: the types seen there, the strings seen there while there are few of
+// them, and the shapes of mapping values and sequence items.
+
+pub const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+// Strings become an enum when there are at most this many distinct values and
+// at least one of them was seen more than once.
+const ENUM_LIMIT: usize = 5;
+
+#[derive(Clone, Debug, Default)]
+pub struct Inference {
+    root: Shape,
+}
+
+impl Inference {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Widens the schema so that it also describes `document`.
+    pub fn add(&mut self, document: &Value) {
+        self.root.add(document);
+    }
+
+    pub fn schema(&self) -> Value {
+        let mut schema = Mapping::new();
+        schema.insert("$schema".to_string(), Value::String(DRAFT.to_string()));
+        if let Value::Mapping(root) = self.root.schema() {
+            schema.extend(root);
+        }
+        Value::Mapping(schema)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Shape {
+    types: BTreeSet<&'static str>,
+    strings: Vec<String>,
+    string_count: usize,
+    mappings: usize,
+    properties: IndexMap<String, (usize, Shape)>,
+    items: Option<Box<Shape>>,
+}
+
+impl Shape {
+    fn add(&mut self, value: &Value) {
+        self.types.insert(type_name(value));
+        match value {
+            Value::String(s) | Value::Datetime(s) => {
+                self.string_count += 1;
+                if self.strings.len() <= ENUM_LIMIT && !self.strings.contains(s) {
+                    self.strings.push(s.clone());
+                }
+            }
+            Value::Sequence(items) => {
+                let shape = self.items.get_or_insert_with(Default::default);
+                items.iter().for_each(|item| shape.add(item));
+            }
+            Value::Mapping(map) => {
+                self.mappings += 1;
+                for (key, value) in map {
+                    let (count, shape) = self.properties.entry(key.clone()).or_default();
+                    *count += 1;
+                    shape.add(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn schema(&self) -> Value {
+        let mut schema = Mapping::new();
+        // Integers are numbers too, so only the wider type is kept.
+        let types: Vec<&str> = self
+            .types
+            .iter()
+            .copied()
+            .filter(|t| *t != "integer" || !self.types.contains("number"))
+            .collect();
+        match types.as_slice() {
+            [] => {}
+            [single] => {
+                schema.insert("type".to_string(), Value::String(single.to_string()));
+            }
+            several => {
+                schema.insert(
+                    "type".to_string(),
+                    Value::Sequence(
+                        several
+                            .iter()
+                            .map(|t| Value::String(t.to_string()))
+                            .collect(),
+                    ),
+                );
+            }
+        }
+        if types == ["string"]
+            && self.strings.len() <= ENUM_LIMIT
+            && self.strings.len() < self.string_count
+        {
+            schema.insert(
+                "enum".to_string(),
+                Value::Sequence(self.strings.iter().cloned().map(Value::String).collect()),
+            );
+        }
+        if self.mappings > 0 {
+            let properties = self
+                .properties
+                .iter()
+                .map(|(key, (_, shape))| (key.clone(), shape.schema()))
+                .collect();
+            schema.insert("properties".to_string(), Value::Mapping(properties));
+            let required: Vec<Value> = self
+                .properties
+                .iter()
+                .filter(|(_, (count, _))| *count == self.mappings)
+                .map(|(key, _)| Value::String(key.clone()))
+                .collect();
+            if !required.is_empty() {
+                schema.insert("required".to_string(), Value::Sequence(required));
+            }
+        }
+        if let Some(items) = &self.items {
+            if !items.types.is_empty() {
+                schema.insert("items".to_string(), items.schema());
+            }
+        }
+        Value::Mapping(schema)
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Integer(_) => "integer",
+        Value::Float(_) => "number",
+        Value::String(_) | Value::Datetime(_) => "string",
+        Value::Sequence(_) => "array",
+        Value::Mapping(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+    use crate::schema::Schema;
+    use crate::transcoder::decode;
+
+    fn infer(documents: &[&str]) -> Value {
+        let mut inference = Inference::new();
+        for document in documents {
+            inference.add(&decode(document, Format::Yaml).unwrap());
+        }
+        inference.schema()
+    }
+
+    #[test]
+    fn the_union_of_the_samples_is_described() {
+        let schema = infer(&[
+            "name: a\nmode: dev\nport: 80\ntags: [x]",
+            "name: b\nmode: prod\nport: 1.5\n",
+            "name: c\nmode: dev\nport: 8080\ntags: [y, 1]",
+        ]);
+
+        let expected = decode(
+            r#"
+"$schema": https://json-schema.org/draft/2020-12/schema
+type: object
+properties:
+  name: {type: string}
+  mode: {type: string, enum: [dev, prod]}
+  port: {type: number}
+  tags: {type: array, items: {type: [integer, string]}}
+required: [name, mode, port]
+"#,
+            Format::Yaml,
+        )
+        .unwrap();
+        assert_eq!(schema, expected);
+    }
+
+    #[test]
+    fn the_samples_match_the_inferred_schema() {
+        let documents = ["a: [{b: 1}, {b: 2, c: null}]", "a: []\nd: true"];
+
+        let schema = Schema::new(infer(&documents)).unwrap();
+
+        for document in documents {
+            assert_eq!(
+                schema.validate(&decode(document, Format::Yaml).unwrap()),
+                vec![]
+            );
+        }
+    }
+
+    #[test]
+    fn keys_named_like_schema_keywords_are_properties() {
+        let documents = [
+            "name: a\ndependencies: {b: ^1}\nadditionalItems: []",
+            "name: c\ndependencies: {}\n",
+        ];
+
+        let schema = Schema::new(infer(&documents)).unwrap();
+
+        for document in documents {
+            assert_eq!(
+                schema.validate(&decode(document, Format::Yaml).unwrap()),
+                vec![]
+            );
+        }
+    }
+}
@@ -1,7 +1,79 @@
-pub mod app;
-pub mod error;
-pub mod format;
-pub mod fs;
-pub mod test_utils;
-pub mod transcoder;
-pub mod traversal;
+//! Converts data-serialisation documents between YAML, JSON and TOML.
+//!
+//! A [`Transcoder`] converts from one format to another with the given
+//! options:
+//!
+//! ```
+//! use x2y::{Format, OutputOptions, Transcoder};
+//!
+//! let transcoder = Transcoder::new(Format::Json, Format::Yaml).output_options(OutputOptions {
+//!     sort_keys: true,
+//!     ..Default::default()
+//! });
+//! let yaml = transcoder.transcode_str(r#"{"name": "x2y", "formats": ["yaml", "json"]}"#)?;
+//! assert_eq!(yaml, "formats:\n- yaml\n- json\nname: x2y\n");
+//! # Ok::<(), x2y::X2YError>(())
+//! ```
+//!
+//! Documents can also be decoded to a [`Value`] with [`decode`] or
+//! [`decode_with`], changed, and encoded again with [`encode`].
+
+mod app;
+mod cache;
+mod coercion;
+mod completions;
+mod config;
+mod detect;
+mod diagnostic;
+mod diff;
+mod edit;
+mod emitter;
+mod error;
+mod format;
+mod fs;
+mod infer;
+mod limits;
+mod merge;
+mod options;
+mod patch;
+mod query;
+mod schema;
+mod stream;
+#[cfg(test)]
+mod test_utils;
+mod transcoder;
+mod traversal;
+mod value;
+mod verify;
+mod watch;
+
+use std::process::ExitCode;
+
+use clap::Parser;
+
+pub use detect::{Candidate, Content, Detection};
+pub use error::{
+    LimitError, Location, ParseError, PatchError, UnsupportedValue, ValidationError,
+    VerificationError, Violation, X2YError,
+};
+pub use format::Format;
+pub use limits::Limit;
+pub use options::{
+    InputOptions, Limits, LineEnding, OutputOptions, QuoteStyle, SequenceStyle, TableStyle,
+    YamlTags,
+};
+pub use transcoder::{decode, decode_with, encode, transcode, Transcoder};
+pub use value::{Mapping, Value};
+
+/// Runs the x2y command line with the arguments of the process, as the `x2y`
+/// binary does, returning its exit code. Errors are written to stderr.
+pub fn run_cli() -> ExitCode {
+    let args = app::Args::parse();
+    match args.run() {
+        Ok(code) => code,
+        Err(e) => {
+            args.report(&e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
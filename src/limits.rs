@@ -0,0 +1,239 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::error::{LimitError, X2YError};
+use crate::options::Limits;
+use crate::value::{display_path, index_path, key_path, Value};
+
+// Keeps a hostile document from exhausting memory or time. The input size is
+// checked as it's read, YAML aliases as they're expanded, and the shape of the
+// document once it's decoded, before anything is converted or written.
+
+/// A bound a document went over.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Limit {
+    InputSize,
+    Depth,
+    Nodes,
+    AliasExpansion,
+    StringLength,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Limit::InputSize => "bytes of input",
+            Limit::Depth => "levels of nesting",
+            Limit::Nodes => "keys and values",
+            Limit::AliasExpansion => "values added by aliases",
+            Limit::StringLength => "bytes in a string",
+        })
+    }
+}
+
+/// Reads a file, stopping as soon as it's over the size limit.
+pub fn read_file(path: &Path, limits: &Limits) -> Result<Vec<u8>, X2YError> {
+    let file = File::open(path).map_err(|e| X2YError::io(path, e))?;
+    read(file, limits).map_err(|e| e.with_path(path))
+}
+
+/// Reads a file as UTF-8, stopping as soon as it's over the size limit.
+pub fn read_to_string(path: &Path, limits: &Limits) -> Result<String, X2YError> {
+    String::from_utf8(read_file(path, limits)?)
+        .map_err(|e| X2YError::io(path, io::Error::new(io::ErrorKind::InvalidData, e)))
+}
+
+/// Reads `reader` to the end, stopping as soon as it's over the size limit.
+pub fn read(mut reader: impl Read, limits: &Limits) -> Result<Vec<u8>, X2YError> {
+    let mut contents = Vec::new();
+    match limits.max_input_size {
+        // One byte more than allowed is enough to know the input is too big.
+        Some(max) => reader
+            .take((max as u64).saturating_add(1))
+            .read_to_end(&mut contents)?,
+        None => reader.read_to_end(&mut contents)?,
+    };
+    check_size(contents.len(), limits)?;
+    Ok(contents)
+}
+
+pub(crate) fn check_size(len: usize, limits: &Limits) -> Result<(), X2YError> {
+    match limits.max_input_size {
+        Some(max) if len > max => Err(exceeded(Limit::InputSize, max, None)),
+        _ => Ok(()),
+    }
+}
+
+/// Fails if a decoded document is nested too deeply, has too many keys and
+/// values, or has too long a string.
+pub fn check(value: &Value, limits: &Limits) -> Result<(), X2YError> {
+    check_within(value, limits, "", 0, &mut 0)
+}
+
+/// Checks a value found at `path` and `depth` in a document that already has
+/// `nodes` keys and values, adding the value's own to them.
+pub(crate) fn check_within(
+    value: &Value,
+    limits: &Limits,
+    path: &str,
+    depth: usize,
+    nodes: &mut usize,
+) -> Result<(), X2YError> {
+    let mut walk = Walk {
+        limits,
+        nodes: *nodes,
+    };
+    walk.value(value, path, depth)?;
+    *nodes = walk.nodes;
+    Ok(())
+}
+
+pub(crate) fn exceeded(limit: Limit, maximum: usize, value_path: Option<&str>) -> X2YError {
+    X2YError::Limit(Box::new(LimitError {
+        limit,
+        maximum,
+        value_path: value_path.map(|p| display_path(p).to_string()),
+        path: None,
+    }))
+}
+
+struct Walk<'a> {
+    limits: &'a Limits,
+    nodes: usize,
+}
+
+impl Walk<'_> {
+    fn value(&mut self, value: &Value, path: &str, depth: usize) -> Result<(), X2YError> {
+        self.count(path)?;
+        match value {
+            Value::String(s) => self.string(s, path),
+            Value::Sequence(items) => {
+                self.nest(path, depth)?;
+                for (i, item) in items.iter().enumerate() {
+                    self.value(item, &index_path(path, i), depth + 1)?;
+                }
+                Ok(())
+            }
+            Value::Mapping(map) => {
+                self.nest(path, depth)?;
+                for (key, value) in map {
+                    let path = key_path(path, key);
+                    self.count(&path)?;
+                    self.string(key, &path)?;
+                    self.value(value, &path, depth + 1)?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn count(&mut self, path: &str) -> Result<(), X2YError> {
+        self.nodes += 1;
+        match self.limits.max_nodes {
+            Some(max) if self.nodes > max => Err(exceeded(Limit::Nodes, max, Some(path))),
+            _ => Ok(()),
+        }
+    }
+
+    // The root collection is the first level.
+    fn nest(&self, path: &str, depth: usize) -> Result<(), X2YError> {
+        if depth >= self.limits.max_depth {
+            return Err(exceeded(Limit::Depth, self.limits.max_depth, Some(path)));
+        }
+        Ok(())
+    }
+
+    fn string(&self, s: &str, path: &str) -> Result<(), X2YError> {
+        match self.limits.max_string_length {
+            Some(max) if s.len() > max => Err(exceeded(Limit::StringLength, max, Some(path))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+    use crate::options::InputOptions;
+    use crate::transcoder::decode_with;
+
+    fn exceeded_limit(contents: &str, format: Format, limits: Limits) -> (Limit, Option<String>) {
+        let options = InputOptions {
+            limits,
+            ..Default::default()
+        };
+        match decode_with(contents, format, &options) {
+            Err(X2YError::Limit(e)) => (e.limit, e.value_path),
+            other => panic!("expected a limit error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn each_limit_is_enforced() {
+        let limits = |f: fn(&mut Limits)| {
+            let mut limits = Limits::default();
+            f(&mut limits);
+            limits
+        };
+
+        assert_eq!(
+            exceeded_limit(
+                "{\"a\": 1}",
+                Format::Json,
+                limits(|l| l.max_input_size = Some(4))
+            ),
+            (Limit::InputSize, None)
+        );
+        assert_eq!(
+            exceeded_limit("a: {b: {c: 1}}", Format::Yaml, limits(|l| l.max_depth = 2)),
+            (Limit::Depth, Some(".a.b".to_string()))
+        );
+        assert_eq!(
+            exceeded_limit(
+                "a = [1, 2, 3]",
+                Format::Toml,
+                limits(|l| l.max_nodes = Some(4))
+            ),
+            (Limit::Nodes, Some(".a[1]".to_string()))
+        );
+        let aliases = format!(
+            "a: &a [{}]\nb: [{}]\n",
+            ["x"; 10].join(", "),
+            ["*a"; 10].join(", ")
+        );
+        assert_eq!(
+            exceeded_limit(
+                &aliases,
+                Format::Yaml,
+                limits(|l| l.max_alias_expansion = 0)
+            ),
+            (Limit::AliasExpansion, None)
+        );
+        assert_eq!(
+            exceeded_limit(
+                "{\"name\": \"a long string\"}",
+                Format::Json,
+                limits(|l| l.max_string_length = Some(8))
+            ),
+            (Limit::StringLength, Some(".name".to_string()))
+        );
+    }
+
+    #[test]
+    fn reading_stops_at_the_size_limit() {
+        let limits = Limits {
+            max_input_size: Some(3),
+            ..Default::default()
+        };
+
+        assert_eq!(read(&b"abc"[..], &limits).unwrap(), b"abc");
+        assert!(matches!(
+            read(io::repeat(b'a'), &limits),
+            Err(X2YError::Limit(_))
+        ));
+    }
+}
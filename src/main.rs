@@ -1,10 +1,6 @@
-use clap::Parser;
-use x2y::app::Args;
-use x2y::error::X2YError;
+use std::process::ExitCode;
 
-fn main() -> Result<(), X2YError> {
+fn main() -> ExitCode {
     env_logger::init();
-    let args = Args::parse();
-    args.run()?;
-    Ok(())
+    x2y::run_cli()
 }
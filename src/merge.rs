@@ -0,0 +1,134 @@
+use clap::ValueEnum;
+
+use crate::value::Value;
+
+// Deep-merges layered documents, each one overriding the ones before it.
+// Mappings are merged key by key, keeping the order keys were first seen in,
+// and any other value replaces what was there. Sequences follow `arrays`.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MergeOptions {
+    pub arrays: ArrayMerge,
+    /// The key identifying items when sequences are merged by key.
+    pub merge_key: String,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        Self {
+            arrays: ArrayMerge::Replace,
+            merge_key: "name".to_string(),
+        }
+    }
+}
+
+/// How a sequence is merged with the sequence it overrides.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub enum ArrayMerge {
+    /// The later sequence replaces the earlier one.
+    Replace,
+    /// The later items are added after the earlier ones.
+    Append,
+    /// Mappings with the same merge key are merged, other items are appended.
+    MergeByKey,
+}
+
+/// Merges `overlay` into `base`.
+pub fn merge(base: &mut Value, overlay: Value, options: &MergeOptions) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value, options),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Sequence(base), Value::Sequence(overlay)) => match options.arrays {
+            ArrayMerge::Replace => *base = overlay,
+            ArrayMerge::Append => base.extend(overlay),
+            ArrayMerge::MergeByKey => {
+                for item in overlay {
+                    let existing = merge_key(&item, &options.merge_key).and_then(|key| {
+                        base.iter()
+                            .position(|b| merge_key(b, &options.merge_key) == Some(key))
+                    });
+                    match existing {
+                        Some(i) => merge(&mut base[i], item, options),
+                        None => base.push(item),
+                    }
+                }
+            }
+        },
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn merge_key<'a>(item: &'a Value, key: &str) -> Option<&'a Value> {
+    match item {
+        Value::Mapping(map) => map.get(key),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+    use crate::transcoder::decode;
+
+    fn merged(base: &str, overlay: &str, arrays: ArrayMerge) -> Value {
+        let mut value = decode(base, Format::Yaml).unwrap();
+        let options = MergeOptions {
+            arrays,
+            ..Default::default()
+        };
+        merge(&mut value, decode(overlay, Format::Yaml).unwrap(), &options);
+        value
+    }
+
+    fn yaml(document: &str) -> Value {
+        decode(document, Format::Yaml).unwrap()
+    }
+
+    #[test]
+    fn mappings_are_merged_deeply() {
+        assert_eq!(
+            merged(
+                "server: {host: a, port: 80}\nname: x",
+                "server: {port: 8080, tls: true}",
+                ArrayMerge::Replace
+            ),
+            yaml("server: {host: a, port: 8080, tls: true}\nname: x")
+        );
+    }
+
+    #[test]
+    fn sequences_follow_the_strategy() {
+        let base = "items: [1, 2]";
+        let overlay = "items: [3]";
+
+        assert_eq!(
+            merged(base, overlay, ArrayMerge::Replace),
+            yaml("items: [3]")
+        );
+        assert_eq!(
+            merged(base, overlay, ArrayMerge::Append),
+            yaml("items: [1, 2, 3]")
+        );
+    }
+
+    #[test]
+    fn sequences_can_be_merged_by_key() {
+        assert_eq!(
+            merged(
+                "users: [{name: a, admin: false}, {name: b}]",
+                "users: [{name: a, admin: true}, {name: c}, 1]",
+                ArrayMerge::MergeByKey
+            ),
+            yaml("users: [{name: a, admin: true}, {name: b}, {name: c}, 1]")
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use clap::ValueEnum;
+
+// Controls how a decoded document is written back out. Settings that don't
+// apply to the output format are ignored.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputOptions {
+    /// Sort mapping keys instead of keeping the source order.
+    pub sort_keys: bool,
+    /// Indentation width, `None` uses the default for the output format.
+    pub indent: Option<usize>,
+    /// Write JSON on a single line and TOML arrays inline.
+    pub compact: bool,
+    /// Write JSON in the RFC 8785 canonical form. This takes precedence over
+    /// every other setting so the output is byte-for-byte reproducible.
+    pub canonical: bool,
+    pub sequence_style: SequenceStyle,
+    /// Write repeated YAML mappings and sequences once with an `&anchor` and
+    /// refer to them with `*alias`es after that.
+    pub yaml_anchors: bool,
+    pub quote_style: QuoteStyle,
+    pub table_style: TableStyle,
+    pub trailing_newline: bool,
+    pub line_ending: LineEnding,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self {
+            sort_keys: false,
+            indent: None,
+            compact: false,
+            canonical: false,
+            sequence_style: SequenceStyle::Block,
+            yaml_anchors: false,
+            quote_style: QuoteStyle::Auto,
+            table_style: TableStyle::Tables,
+            trailing_newline: true,
+            line_ending: LineEnding::Lf,
+        }
+    }
+}
+
+// Controls how input documents are read.
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputOptions {
+    pub yaml_tags: YamlTags,
+    pub limits: Limits,
+}
+
+/// Bounds on the documents that are read, so a hostile input fails with an
+/// error instead of exhausting memory or time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Limits {
+    /// The largest input in bytes, `None` for no limit.
+    pub max_input_size: Option<usize>,
+    /// How deeply mappings and sequences may be nested. The parsers refuse
+    /// anything nested more than 128 levels whatever this is.
+    pub max_depth: usize,
+    /// The most keys and values in a document, `None` for no limit.
+    pub max_nodes: Option<usize>,
+    /// How many values YAML aliases may add beyond the size of the input in
+    /// bytes.
+    pub max_alias_expansion: usize,
+    /// The longest string or key in bytes, `None` for no limit.
+    pub max_string_length: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_input_size: None,
+            max_depth: 128,
+            max_nodes: None,
+            max_alias_expansion: 1_000_000,
+            max_string_length: None,
+        }
+    }
+}
+
+/// How YAML values with a custom tag, such as `!Ref bucket`, are read.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum YamlTags {
+    /// As a mapping from the tag to the value, `{"!Ref": "bucket"}`, which is
+    /// written back to YAML as the tagged value.
+    #[default]
+    Wrap,
+    /// As the value alone.
+    Drop,
+    /// As an error.
+    Error,
+}
+
+/// How YAML sequences are written.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub enum SequenceStyle {
+    /// One `- item` per line.
+    Block,
+    /// Inline `[a, b]`.
+    Flow,
+}
+
+/// How YAML string values are quoted.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub enum QuoteStyle {
+    /// Only quote strings that would otherwise be read back as something else.
+    Auto,
+    /// Single quote every string that can be single quoted.
+    Single,
+    /// Double quote every string.
+    Double,
+}
+
+/// How nested TOML tables are written.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub enum TableStyle {
+    /// `[table]` headers.
+    Tables,
+    /// `key = { inline = "table" }`.
+    Inline,
+    /// `key.dotted = "keys"`.
+    Dotted,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
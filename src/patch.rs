@@ -0,0 +1,284 @@
+use crate::diff;
+use crate::emitter;
+use crate::error::{PatchError, X2YError};
+use crate::value::{Mapping, Segment, Value};
+
+// Applies RFC 6902 JSON Patches and RFC 7386 JSON Merge Patches to decoded
+// documents, so they work the same whatever format the document is in.
+
+/// Applies a JSON Patch if `patch` is a sequence and a JSON Merge Patch
+/// otherwise. A JSON Patch is applied entirely or not at all.
+pub fn apply(document: &mut Value, patch: Value) -> Result<(), X2YError> {
+    match patch {
+        Value::Sequence(operations) => apply_json_patch(document, &operations),
+        patch => {
+            apply_merge_patch(document, patch);
+            Ok(())
+        }
+    }
+}
+
+pub fn apply_json_patch(document: &mut Value, operations: &[Value]) -> Result<(), X2YError> {
+    let mut patched = document.clone();
+    for (i, operation) in operations.iter().enumerate() {
+        apply_operation(&mut patched, operation).map_err(|message| {
+            X2YError::Patch(Box::new(PatchError {
+                operation: Some(i),
+                message,
+                path: None,
+            }))
+        })?;
+    }
+    *document = patched;
+    Ok(())
+}
+
+pub fn apply_merge_patch(document: &mut Value, patch: Value) {
+    let Value::Mapping(patch) = patch else {
+        *document = patch;
+        return;
+    };
+    if !matches!(document, Value::Mapping(_)) {
+        *document = Value::Mapping(Mapping::new());
+    }
+    let Value::Mapping(target) = document else {
+        unreachable!("replaced with a mapping above");
+    };
+    for (key, value) in patch {
+        if value == Value::Null {
+            target.shift_remove(&key);
+        } else {
+            apply_merge_patch(target.entry(key).or_insert(Value::Null), value);
+        }
+    }
+}
+
+fn apply_operation(document: &mut Value, operation: &Value) -> Result<(), String> {
+    let Value::Mapping(operation) = operation else {
+        return Err("an operation must be a mapping".to_string());
+    };
+    let member = |name: &str| {
+        operation
+            .get(name)
+            .ok_or_else(|| format!("the operation has no `{}`", name))
+    };
+    let string_member = |name: &str| match member(name)? {
+        Value::String(s) => Ok(s.as_str()),
+        _ => Err(format!("`{}` must be a string", name)),
+    };
+    let op = string_member("op")?;
+    let path = string_member("path")?;
+    match op {
+        "add" => add(document, path, member("value")?.clone()),
+        "remove" => remove(document, path).map(|_| ()),
+        "replace" => {
+            let target = resolve(document, path, false)?;
+            *document.get_mut(&target).expect("resolved") = member("value")?.clone();
+            Ok(())
+        }
+        "move" => {
+            let from = string_member("from")?;
+            if path.starts_with(from) && path[from.len()..].starts_with('/') {
+                return Err(format!("{} can't be moved into itself", from));
+            }
+            let value = remove(document, from)?;
+            add(document, path, value)
+        }
+        "copy" => {
+            let from = resolve(document, string_member("from")?, false)?;
+            let value = document.get(&from).expect("resolved").clone();
+            add(document, path, value)
+        }
+        "test" => {
+            let target = resolve(document, path, false)?;
+            let actual = document.get(&target).expect("resolved");
+            let expected = member("value")?;
+            if diff::diff(actual, expected).is_empty() {
+                Ok(())
+            } else {
+                Err(format!(
+                    "test failed, {} is {} but {} was expected",
+                    display(path),
+                    emitter::inline_json(actual),
+                    emitter::inline_json(expected)
+                ))
+            }
+        }
+        other => Err(format!("unknown operation `{}`", other)),
+    }
+}
+
+fn add(document: &mut Value, pointer: &str, value: Value) -> Result<(), String> {
+    let target = resolve(document, pointer, true)?;
+    let Some((last, parents)) = target.split_last() else {
+        *document = value;
+        return Ok(());
+    };
+    match (document.get_mut(parents).expect("resolved"), last) {
+        (Value::Mapping(map), Segment::Key(key)) => match map.get_mut(key) {
+            Some(existing) => *existing = value,
+            None => {
+                map.insert(key.clone(), value);
+            }
+        },
+        (Value::Sequence(items), Segment::Index(i)) => items.insert(*i as usize, value),
+        _ => unreachable!("resolve matches segments to their parent"),
+    }
+    Ok(())
+}
+
+fn remove(document: &mut Value, pointer: &str) -> Result<Value, String> {
+    let target = resolve(document, pointer, false)?;
+    if target.is_empty() {
+        return Err("the whole document can't be removed".to_string());
+    }
+    Ok(document.remove(&target).expect("resolved"))
+}
+
+// Turns an RFC 6901 JSON Pointer into a path, checking that it points at a
+// value. When adding, the last part may also be a new key, `-` for the end of
+// a sequence or an index one past the end.
+fn resolve(document: &Value, pointer: &str, adding: bool) -> Result<Vec<Segment>, String> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(tokens) = pointer.strip_prefix('/') else {
+        return Err(format!("`{}` is not a JSON Pointer", pointer));
+    };
+    let tokens: Vec<String> = tokens
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect();
+    let not_found = || format!("there is no value at {}", display(pointer));
+    let mut path = Vec::with_capacity(tokens.len());
+    let mut value = document;
+    for (i, token) in tokens.iter().enumerate() {
+        let last = i == tokens.len() - 1;
+        let new_allowed = last && adding;
+        let segment = match value {
+            Value::Mapping(map) => {
+                if !new_allowed && !map.contains_key(token) {
+                    return Err(not_found());
+                }
+                Segment::Key(token.clone())
+            }
+            Value::Sequence(items) => {
+                let index = match token.as_str() {
+                    "-" if new_allowed => Some(items.len()),
+                    token if is_array_index(token) => token.parse().ok(),
+                    _ => None,
+                };
+                let allowed = if new_allowed {
+                    items.len() + 1
+                } else {
+                    items.len()
+                };
+                match index {
+                    Some(index) if index < allowed => Segment::Index(index as i128),
+                    _ => return Err(not_found()),
+                }
+            }
+            _ => return Err(not_found()),
+        };
+        path.push(segment);
+        if !last {
+            value = document.get(&path).expect("checked above");
+        }
+    }
+    Ok(path)
+}
+
+// Leading zeros aren't allowed.
+fn is_array_index(token: &str) -> bool {
+    token == "0"
+        || (!token.is_empty()
+            && !token.starts_with('0')
+            && token.bytes().all(|b| b.is_ascii_digit()))
+}
+
+fn display(pointer: &str) -> &str {
+    if pointer.is_empty() {
+        "the document root"
+    } else {
+        pointer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+    use crate::transcoder::decode;
+
+    fn json_value(contents: &str) -> Value {
+        decode(contents, Format::Json).unwrap()
+    }
+
+    fn patched(document: &str, patch: &str) -> Result<Value, X2YError> {
+        let mut document = json_value(document);
+        apply(&mut document, json_value(patch))?;
+        Ok(document)
+    }
+
+    #[test]
+    fn json_patch_operations() {
+        let document = r#"{"a": {"b": 1}, "c": [1, 2], "d/e": 3}"#;
+        let patch = r#"[
+            {"op": "add", "path": "/c/1", "value": 5},
+            {"op": "add", "path": "/c/-", "value": 6},
+            {"op": "remove", "path": "/d~1e"},
+            {"op": "replace", "path": "/a/b", "value": 2},
+            {"op": "copy", "from": "/a", "path": "/f"},
+            {"op": "move", "from": "/f/b", "path": "/g"},
+            {"op": "test", "path": "/g", "value": 2.0}
+        ]"#;
+
+        assert_eq!(
+            patched(document, patch).unwrap(),
+            json_value(r#"{"a": {"b": 2}, "c": [1, 5, 2, 6], "f": {}, "g": 2}"#)
+        );
+    }
+
+    #[test]
+    fn a_failed_test_leaves_the_document_unchanged() {
+        let mut document = json_value(r#"{"a": 1}"#);
+        let patch = json_value(
+            r#"[{"op": "replace", "path": "/a", "value": 2}, {"op": "test", "path": "/a", "value": 3}]"#,
+        );
+
+        let error = apply(&mut document, patch).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "failed to apply the patch, operation 1: test failed, /a is 2 but 3 was expected"
+        );
+        assert_eq!(document, json_value(r#"{"a": 1}"#));
+    }
+
+    #[test]
+    fn missing_paths_are_errors() {
+        assert!(patched(r#"{"a": []}"#, r#"[{"op": "remove", "path": "/a/0"}]"#).is_err());
+        assert!(patched(
+            r#"{"a": [1]}"#,
+            r#"[{"op": "add", "path": "/a/2", "value": 1}]"#
+        )
+        .is_err());
+        assert!(patched(
+            r#"{"a": 1}"#,
+            r#"[{"op": "add", "path": "/b/c", "value": 1}]"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn merge_patch() {
+        assert_eq!(
+            patched(
+                r#"{"a": "b", "c": {"d": "e", "f": "g"}}"#,
+                r#"{"a": "z", "c": {"f": null}, "h": [1]}"#
+            )
+            .unwrap(),
+            json_value(r#"{"a": "z", "c": {"d": "e"}, "h": [1]}"#)
+        );
+    }
+}
@@ -0,0 +1,789 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::error::X2YError;
+use crate::value::{resolve_index, Segment, Value};
+
+// A subset of the jq language for selecting parts of a document:
+//
+//   .  .foo  ."foo bar"  .[0]  .[-1]  .[1:3]  .[]  ..  a | b  a, b
+//   keys  length  select(.port > 80)  ==  !=  <  <=  >  >=  and  or  not
+//
+// A `?` after any of the path expressions suppresses its errors, keeping the
+// values produced before one. Every expression produces zero or more values,
+// like jq. There's no construction of sequences or mappings (`[...]`,
+// `{...}`), arithmetic, variables or other functions such as `map`. Queries that don't parse are `X2YError::Query`,
+// those that fail on a document are `X2YError::QueryFailed`.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    expression: String,
+    ast: Expr,
+}
+
+impl Query {
+    pub fn parse(expression: &str) -> Result<Self, X2YError> {
+        let tokens = tokenize(expression).map_err(|m| query_error(expression, m))?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let ast = parser.pipe().map_err(|m| query_error(expression, m))?;
+        if let Some(token) = parser.peek() {
+            return Err(query_error(expression, format!("unexpected {}", token)));
+        }
+        Ok(Self {
+            expression: expression.to_string(),
+            ast,
+        })
+    }
+
+    /// Runs the query, returning every value it produces.
+    pub fn run(&self, input: &Value) -> Result<Vec<Value>, X2YError> {
+        let mut output = Vec::new();
+        eval(&self.ast, input, &mut output).map_err(|message| X2YError::QueryFailed {
+            expression: self.expression.clone(),
+            message,
+        })?;
+        Ok(output)
+    }
+
+    /// Runs the query for a single document: one result is returned as is and
+    /// several are collected into a sequence.
+    pub fn apply(&self, input: &Value) -> Result<Value, X2YError> {
+        let mut results = self.run(input)?;
+        Ok(match results.len() {
+            1 => results.remove(0),
+            _ => Value::Sequence(results),
+        })
+    }
+
+    /// The query as a list of keys and indexes if it's a plain path such as
+    /// `.servers[0].host`.
+    pub fn path(&self) -> Option<Vec<Segment>> {
+        fn segments(expr: &Expr, path: &mut Vec<Segment>) -> Option<()> {
+            match expr {
+                Expr::Identity => {}
+                Expr::Field(base, name) => {
+                    segments(base, path)?;
+                    path.push(Segment::Key(name.clone()));
+                }
+                Expr::Index(base, index) => {
+                    segments(base, path)?;
+                    path.push(match index.as_ref() {
+                        Expr::Literal(Value::Integer(i)) => Segment::Index(*i),
+                        Expr::Literal(Value::String(key)) => Segment::Key(key.clone()),
+                        _ => return None,
+                    });
+                }
+                _ => return None,
+            }
+            Some(())
+        }
+        let mut path = Vec::new();
+        segments(&self.ast, &mut path).map(|_| path)
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+fn query_error(expression: &str, message: String) -> X2YError {
+    X2YError::Query {
+        expression: expression.to_string(),
+        message,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Dot,
+    DotDot,
+    Ident(String),
+    Str(String),
+    Number(Value),
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    Colon,
+    Pipe,
+    Comma,
+    Question,
+    Op(CompareOp),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Dot => write!(f, "`.`"),
+            Token::DotDot => write!(f, "`..`"),
+            Token::Ident(s) => write!(f, "`{}`", s),
+            Token::Str(s) => write!(f, "{:?}", s),
+            Token::Number(n) => write!(f, "`{:?}`", n),
+            Token::LBracket => write!(f, "`[`"),
+            Token::RBracket => write!(f, "`]`"),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+            Token::Colon => write!(f, "`:`"),
+            Token::Pipe => write!(f, "`|`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::Question => write!(f, "`?`"),
+            Token::Op(op) => write!(f, "`{}`", op.as_str()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    fn as_str(&self) -> &'static str {
+        match self {
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '.' if next == Some('.') => {
+                tokens.push(Token::DotDot);
+                i += 2;
+            }
+            '.' => {
+                tokens.push(Token::Dot);
+                i += 1;
+            }
+            '[' | ']' | '(' | ')' | ':' | '|' | ',' | '?' => {
+                tokens.push(match c {
+                    '[' => Token::LBracket,
+                    ']' => Token::RBracket,
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    ':' => Token::Colon,
+                    '|' => Token::Pipe,
+                    ',' => Token::Comma,
+                    _ => Token::Question,
+                });
+                i += 1;
+            }
+            '=' | '!' | '<' | '>' => {
+                let (op, width) = match (c, next) {
+                    ('=', Some('=')) => (CompareOp::Eq, 2),
+                    ('!', Some('=')) => (CompareOp::Ne, 2),
+                    ('<', Some('=')) => (CompareOp::Le, 2),
+                    ('>', Some('=')) => (CompareOp::Ge, 2),
+                    ('<', _) => (CompareOp::Lt, 1),
+                    ('>', _) => (CompareOp::Gt, 1),
+                    _ => return Err(format!("unexpected `{}` at column {}", c, i + 1)),
+                };
+                tokens.push(Token::Op(op));
+                i += width;
+            }
+            '"' => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    i += if chars[i] == '\\' { 2 } else { 1 };
+                }
+                if i >= chars.len() {
+                    return Err(format!("unterminated string at column {}", start + 1));
+                }
+                let literal: String = chars[start..=i].iter().collect();
+                let s = serde_json::from_str(&literal)
+                    .map_err(|e| format!("invalid string at column {}: {}", start + 1, e))?;
+                tokens.push(Token::Str(s));
+                i += 1;
+            }
+            c if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                    // `.[1:2]` and `.[0].a` mustn't swallow the following dot.
+                    if chars[i] == '.' && !chars.get(i + 1).is_some_and(char::is_ascii_digit) {
+                        break;
+                    }
+                    i += 1;
+                }
+                let literal: String = chars[start..i].iter().collect();
+                let number = if let Ok(n) = literal.parse::<i128>() {
+                    Value::Integer(n)
+                } else if let Ok(n) = literal.parse::<f64>() {
+                    Value::Float(n)
+                } else {
+                    return Err(format!("invalid number `{}`", literal));
+                };
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            c => return Err(format!("unexpected `{}` at column {}", c, i + 1)),
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Identity,
+    Recurse,
+    Literal(Value),
+    Field(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Slice(Box<Expr>, Option<i128>, Option<i128>),
+    Iterate(Box<Expr>),
+    Optional(Box<Expr>),
+    Pipe(Box<Expr>, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+    Compare(Box<Expr>, CompareOp, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not,
+    Keys,
+    Length,
+    Select(Box<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        match self.next() {
+            Some(t) if t == token => Ok(()),
+            Some(t) => Err(format!("expected {} but found {}", token, t)),
+            None => Err(format!("expected {} at the end of the query", token)),
+        }
+    }
+
+    fn pipe(&mut self) -> Result<Expr, String> {
+        let mut left = self.comma()?;
+        while self.eat(&Token::Pipe) {
+            left = Expr::Pipe(Box::new(left), Box::new(self.comma()?));
+        }
+        Ok(left)
+    }
+
+    fn comma(&mut self) -> Result<Expr, String> {
+        let mut left = self.or()?;
+        while self.eat(&Token::Comma) {
+            left = Expr::Comma(Box::new(left), Box::new(self.or()?));
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.eat(&Token::Ident("or".to_string())) {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.comparison()?;
+        while self.eat(&Token::Ident("and".to_string())) {
+            left = Expr::And(Box::new(left), Box::new(self.comparison()?));
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.postfix()?;
+        if let Some(Token::Op(op)) = self.peek().cloned() {
+            self.position += 1;
+            let right = self.postfix()?;
+            return Ok(Expr::Compare(Box::new(left), op, Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+        loop {
+            match self.peek() {
+                Some(Token::Dot) => {
+                    self.position += 1;
+                    expr = match self.next() {
+                        Some(Token::Ident(name) | Token::Str(name)) => {
+                            Expr::Field(Box::new(expr), name)
+                        }
+                        Some(Token::LBracket) => self.brackets(expr)?,
+                        Some(t) => return Err(format!("unexpected {} after `.`", t)),
+                        None => return Err("expected a key after `.`".to_string()),
+                    };
+                }
+                Some(Token::LBracket) => {
+                    self.position += 1;
+                    expr = self.brackets(expr)?;
+                }
+                Some(Token::Question) => {
+                    self.position += 1;
+                    expr = Expr::Optional(Box::new(expr));
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    // Parses what follows a `[`: `]`, `n]`, `a:b]` or `"key"]`.
+    fn brackets(&mut self, base: Expr) -> Result<Expr, String> {
+        if self.eat(&Token::RBracket) {
+            return Ok(Expr::Iterate(Box::new(base)));
+        }
+        let start = self.slice_bound()?;
+        if self.eat(&Token::Colon) {
+            let end = self.slice_bound()?;
+            self.expect(Token::RBracket)?;
+            return Ok(Expr::Slice(Box::new(base), start, end));
+        }
+        let index = match start {
+            Some(n) => Expr::Literal(Value::Integer(n)),
+            None => self.pipe()?,
+        };
+        self.expect(Token::RBracket)?;
+        Ok(Expr::Index(Box::new(base), Box::new(index)))
+    }
+
+    fn slice_bound(&mut self) -> Result<Option<i128>, String> {
+        match self.peek() {
+            Some(Token::Number(Value::Integer(n))) => {
+                let n = *n;
+                self.position += 1;
+                Ok(Some(n))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Dot) => match self.peek().cloned() {
+                Some(Token::Ident(name) | Token::Str(name)) => {
+                    self.position += 1;
+                    Ok(Expr::Field(Box::new(Expr::Identity), name))
+                }
+                Some(Token::LBracket) => {
+                    self.position += 1;
+                    self.brackets(Expr::Identity)
+                }
+                _ => Ok(Expr::Identity),
+            },
+            Some(Token::DotDot) => Ok(Expr::Recurse),
+            Some(Token::Number(n)) => Ok(Expr::Literal(n)),
+            Some(Token::Str(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(Token::LParen) => {
+                let expr = self.pipe()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "null" => Ok(Expr::Literal(Value::Null)),
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "not" => Ok(Expr::Not),
+                "keys" => Ok(Expr::Keys),
+                "length" => Ok(Expr::Length),
+                "select" => {
+                    self.expect(Token::LParen)?;
+                    let condition = self.pipe()?;
+                    self.expect(Token::RParen)?;
+                    Ok(Expr::Select(Box::new(condition)))
+                }
+                other => Err(format!("unknown function `{}`", other)),
+            },
+            Some(t) => Err(format!("unexpected {}", t)),
+            None => Err("the query is empty".to_string()),
+        }
+    }
+}
+
+// Adds the values `expr` produces to `output` as they're produced, so those
+// before an error are kept for `?`, like jq.
+fn eval(expr: &Expr, input: &Value, output: &mut Vec<Value>) -> Result<(), String> {
+    match expr {
+        Expr::Identity => output.push(input.clone()),
+        Expr::Recurse => recurse(input, output),
+        Expr::Literal(value) => output.push(value.clone()),
+        Expr::Field(base, name) => each(base, input, output, |value, output| {
+            output.push(field(&value, name)?);
+            Ok(())
+        })?,
+        Expr::Index(base, index) => each(base, input, output, |value, output| {
+            each(index, input, output, |index, output| {
+                output.push(match index {
+                    Value::String(name) => field(&value, &name)?,
+                    Value::Integer(i) => element(&value, i)?,
+                    other => {
+                        return Err(format!(
+                            "cannot index {} with {}",
+                            type_name(&value),
+                            type_name(&other)
+                        ))
+                    }
+                });
+                Ok(())
+            })
+        })?,
+        Expr::Slice(base, start, end) => each(base, input, output, |value, output| {
+            output.push(slice(&value, *start, *end)?);
+            Ok(())
+        })?,
+        Expr::Iterate(base) => each(base, input, output, |value, output| {
+            match value {
+                Value::Sequence(items) => output.extend(items),
+                Value::Mapping(map) => output.extend(map.into_values()),
+                other => return Err(format!("cannot iterate over {}", type_name(&other))),
+            }
+            Ok(())
+        })?,
+        Expr::Optional(inner) => {
+            let _ = eval(inner, input, output);
+        }
+        Expr::Pipe(left, right) => each(left, input, output, |value, output| {
+            eval(right, &value, output)
+        })?,
+        Expr::Comma(left, right) => {
+            eval(left, input, output)?;
+            eval(right, input, output)?;
+        }
+        Expr::Compare(left, op, right) => each(right, input, output, |r, output| {
+            each(left, input, output, |l, output| {
+                let ordering = compare(&l, &r);
+                output.push(Value::Bool(match op {
+                    CompareOp::Eq => ordering == Ordering::Equal,
+                    CompareOp::Ne => ordering != Ordering::Equal,
+                    CompareOp::Lt => ordering == Ordering::Less,
+                    CompareOp::Le => ordering != Ordering::Greater,
+                    CompareOp::Gt => ordering == Ordering::Greater,
+                    CompareOp::Ge => ordering != Ordering::Less,
+                }));
+                Ok(())
+            })
+        })?,
+        Expr::And(left, right) => each(left, input, output, |l, output| {
+            if !truthy(&l) {
+                output.push(Value::Bool(false));
+                return Ok(());
+            }
+            each(right, input, output, |r, output| {
+                output.push(Value::Bool(truthy(&r)));
+                Ok(())
+            })
+        })?,
+        Expr::Or(left, right) => each(left, input, output, |l, output| {
+            if truthy(&l) {
+                output.push(Value::Bool(true));
+                return Ok(());
+            }
+            each(right, input, output, |r, output| {
+                output.push(Value::Bool(truthy(&r)));
+                Ok(())
+            })
+        })?,
+        Expr::Not => output.push(Value::Bool(!truthy(input))),
+        Expr::Keys => output.push(match input {
+            Value::Mapping(map) => {
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                Value::Sequence(keys.into_iter().cloned().map(Value::String).collect())
+            }
+            Value::Sequence(items) => {
+                Value::Sequence((0..items.len() as i128).map(Value::Integer).collect())
+            }
+            other => return Err(format!("{} has no keys", type_name(other))),
+        }),
+        Expr::Length => output.push(match input {
+            Value::Null => Value::Integer(0),
+            Value::Bool(_) => return Err("boolean has no length".to_string()),
+            Value::Integer(i) => Value::Integer(i.abs()),
+            Value::Float(f) => Value::Float(f.abs()),
+            Value::String(s) | Value::Datetime(s) => Value::Integer(s.chars().count() as i128),
+            Value::Sequence(items) => Value::Integer(items.len() as i128),
+            Value::Mapping(map) => Value::Integer(map.len() as i128),
+        }),
+        Expr::Select(condition) => each(condition, input, output, |result, output| {
+            if truthy(&result) {
+                output.push(input.clone());
+            }
+            Ok(())
+        })?,
+    }
+    Ok(())
+}
+
+// Calls `f` with each value `expr` produces, in order. The values produced
+// before an error in `expr` are still passed on, then the error is returned.
+fn each(
+    expr: &Expr,
+    input: &Value,
+    output: &mut Vec<Value>,
+    mut f: impl FnMut(Value, &mut Vec<Value>) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut values = Vec::new();
+    let result = eval(expr, input, &mut values);
+    for value in values {
+        f(value, output)?;
+    }
+    result
+}
+
+fn recurse(value: &Value, output: &mut Vec<Value>) {
+    output.push(value.clone());
+    match value {
+        Value::Sequence(items) => items.iter().for_each(|item| recurse(item, output)),
+        Value::Mapping(map) => map.values().for_each(|item| recurse(item, output)),
+        _ => {}
+    }
+}
+
+fn field(value: &Value, name: &str) -> Result<Value, String> {
+    match value {
+        Value::Mapping(map) => Ok(map.get(name).cloned().unwrap_or(Value::Null)),
+        Value::Null => Ok(Value::Null),
+        other => Err(format!("cannot index {} with {:?}", type_name(other), name)),
+    }
+}
+
+fn element(value: &Value, index: i128) -> Result<Value, String> {
+    match value {
+        Value::Sequence(items) => {
+            Ok(resolve_index(index, items.len()).map_or(Value::Null, |i| items[i].clone()))
+        }
+        Value::Null => Ok(Value::Null),
+        other => Err(format!("cannot index {} with a number", type_name(other))),
+    }
+}
+
+fn slice(value: &Value, start: Option<i128>, end: Option<i128>) -> Result<Value, String> {
+    let bounds = |len: usize| {
+        let clamp = |i: i128| {
+            let i = if i < 0 { len as i128 + i } else { i };
+            i.clamp(0, len as i128) as usize
+        };
+        let start = start.map_or(0, clamp);
+        let end = end.map_or(len, clamp);
+        (start, end.max(start))
+    };
+    match value {
+        Value::Sequence(items) => {
+            let (start, end) = bounds(items.len());
+            Ok(Value::Sequence(items[start..end].to_vec()))
+        }
+        Value::String(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            let (start, end) = bounds(chars.len());
+            Ok(Value::String(chars[start..end].iter().collect()))
+        }
+        Value::Null => Ok(Value::Null),
+        other => Err(format!("cannot slice {}", type_name(other))),
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Bool(false))
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Integer(_) | Value::Float(_) => "number",
+        Value::String(_) | Value::Datetime(_) => "string",
+        Value::Sequence(_) => "array",
+        Value::Mapping(_) => "object",
+    }
+}
+
+// jq's ordering: null < false < true < numbers < strings < arrays < objects.
+fn compare(a: &Value, b: &Value) -> Ordering {
+    fn rank(value: &Value) -> u8 {
+        match value {
+            Value::Null => 0,
+            Value::Bool(false) => 1,
+            Value::Bool(true) => 2,
+            Value::Integer(_) | Value::Float(_) => 3,
+            Value::String(_) | Value::Datetime(_) => 4,
+            Value::Sequence(_) => 5,
+            Value::Mapping(_) => 6,
+        }
+    }
+    match (a, b) {
+        (Value::Integer(x), Value::Integer(y)) => x.cmp(y),
+        (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => {
+            as_f64(a).total_cmp(&as_f64(b))
+        }
+        (Value::String(x) | Value::Datetime(x), Value::String(y) | Value::Datetime(y)) => x.cmp(y),
+        (Value::Sequence(x), Value::Sequence(y)) => x
+            .iter()
+            .zip(y)
+            .map(|(x, y)| compare(x, y))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| x.len().cmp(&y.len())),
+        // By their sorted keys, then by the values in the order of those keys.
+        (Value::Mapping(x), Value::Mapping(y)) => {
+            let mut keys: Vec<_> = x.keys().collect();
+            let mut other_keys: Vec<_> = y.keys().collect();
+            keys.sort();
+            other_keys.sort();
+            keys.cmp(&other_keys).then_with(|| {
+                keys.iter()
+                    .map(|key| compare(&x[*key], &y[*key]))
+                    .find(|o| o.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            })
+        }
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn as_f64(value: &Value) -> f64 {
+    match value {
+        Value::Integer(i) => *i as f64,
+        Value::Float(f) => *f,
+        _ => f64::NAN,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+    use crate::transcoder::decode;
+
+    fn run(expression: &str, document: &str) -> Vec<Value> {
+        let input = decode(document, Format::Yaml).unwrap();
+        Query::parse(expression).unwrap().run(&input).unwrap()
+    }
+
+    fn yaml(document: &str) -> Value {
+        decode(document, Format::Yaml).unwrap()
+    }
+
+    const COMPOSE: &str = "services:\n  web:\n    image: nginx\n    ports: [80, 443]\n  db:\n    image: postgres\n    ports: [5432]\n";
+
+    #[test]
+    fn paths_select_a_subtree() {
+        assert_eq!(
+            run(".services.web", COMPOSE),
+            vec![yaml("image: nginx\nports: [80, 443]")]
+        );
+        assert_eq!(
+            run(".services.web.ports[-1]", COMPOSE),
+            vec![Value::Integer(443)]
+        );
+        assert_eq!(run(".services.\"db\".missing", COMPOSE), vec![Value::Null]);
+    }
+
+    #[test]
+    fn iteration_pipes_and_select() {
+        assert_eq!(
+            run(".services[] | select(.ports[0] > 100) | .image", COMPOSE),
+            vec![Value::String("postgres".to_string())]
+        );
+        assert_eq!(run(".services | keys", COMPOSE), vec![yaml("[db, web]")]);
+        assert_eq!(
+            run(".services.web.ports | length, .[1:]", COMPOSE),
+            vec![Value::Integer(2), yaml("[443]")]
+        );
+    }
+
+    #[test]
+    fn optional_paths_suppress_errors() {
+        let query = Query::parse(".services.web.image[0]").unwrap();
+        assert_eq!(
+            query.run(&yaml(COMPOSE)).unwrap_err().code(),
+            "E301",
+            "a query that fails on the document isn't an invalid query"
+        );
+        assert_eq!(run(".services.web.image[0]?", COMPOSE), vec![]);
+    }
+
+    #[test]
+    fn optional_keeps_the_values_before_an_error() {
+        assert_eq!(
+            run(
+                "(.services.web.image, .services.web.image[0], .services.db.image)?",
+                COMPOSE
+            ),
+            vec![Value::String("nginx".to_string())]
+        );
+        assert_eq!(
+            run("(.items[] | keys)?", "items: [{x: 1}, 5, {y: 2}]"),
+            vec![yaml("[x]")]
+        );
+    }
+
+    #[test]
+    fn mappings_are_ordered_by_keys_then_values() {
+        let document = "a: {x: 1}\nb: {x: 2}\nc: {y: 0}\nd: {x: 1, y: 0}\ne: {y: 0, x: 1}";
+
+        assert_eq!(
+            run(".a < .b, .b < .a, .b < .c, .c < .b", document),
+            [true, false, true, false].map(Value::Bool)
+        );
+        assert_eq!(
+            run(".d == .e, .d < .e, .e < .d, .a < .d", document),
+            [true, false, false, true].map(Value::Bool)
+        );
+    }
+
+    #[test]
+    fn invalid_queries_are_rejected() {
+        assert!(Query::parse(".a |").is_err());
+        assert!(Query::parse(".a[").is_err());
+        assert!(Query::parse("frobnicate").is_err());
+        assert_eq!(Query::parse("[.a]").unwrap_err().code(), "E300");
+        assert_eq!(Query::parse("map(.a)").unwrap_err().code(), "E300");
+        assert_eq!(Query::parse(".a + 1").unwrap_err().code(), "E300");
+    }
+}
@@ -0,0 +1,821 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use regex::Regex;
+
+use crate::diff;
+use crate::emitter::inline_json;
+use crate::error::{Location, ValidationError, Violation, X2YError};
+use crate::format::Format;
+use crate::options::InputOptions;
+use crate::transcoder;
+use crate::value::{display_path, segments_path, Mapping, Segment, Value};
+
+// Validates decoded documents against a JSON Schema (draft 2020-12). `format`
+// is only an annotation. Schemas using a keyword that isn't supported, a
+// `$ref` outside the schema or a pattern the regex crate can't compile are
+// refused when they're loaded, rather than passing documents they'd reject.
+// Patterns match anywhere in the string unless they're anchored, as in
+// ECMA-262, but lookarounds and backreferences aren't supported.
+
+// Guards against `$ref` cycles that never descend into the document.
+const MAX_DEPTH: usize = 128;
+
+// Keywords that change what's valid but aren't implemented.
+const UNSUPPORTED: [&str; 7] = [
+    "$dynamicRef",
+    "$recursiveRef",
+    "additionalItems",
+    "dependencies",
+    "dependentSchemas",
+    "unevaluatedItems",
+    "unevaluatedProperties",
+];
+
+// Keywords whose values are data rather than schemas.
+const DATA: [&str; 4] = ["const", "enum", "default", "examples"];
+
+// Keywords whose values map names to schemas.
+const SCHEMA_MAPS: [&str; 4] = ["properties", "patternProperties", "$defs", "definitions"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+    root: Value,
+    patterns: Patterns,
+}
+
+// The compiled `pattern`s and `patternProperties` keys, by their source.
+#[derive(Clone, Default)]
+struct Patterns(HashMap<String, Regex>);
+
+// Sorted, so a schema is written the same way on every run for the cache.
+impl fmt::Debug for Patterns {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut patterns: Vec<&String> = self.0.keys().collect();
+        patterns.sort();
+        f.debug_tuple("Patterns").field(&patterns).finish()
+    }
+}
+
+impl PartialEq for Patterns {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.keys().all(|k| other.0.contains_key(k))
+    }
+}
+
+impl Schema {
+    pub fn new(root: Value) -> Result<Self, X2YError> {
+        if !matches!(root, Value::Mapping(_) | Value::Bool(_)) {
+            return Err(X2YError::InvalidInput(
+                "a schema must be a mapping or a boolean".to_string(),
+            ));
+        }
+        let mut patterns = Patterns::default();
+        prepare(&root, &root, "#", &mut patterns)?;
+        Ok(Self { root, patterns })
+    }
+
+    /// Every way the value doesn't match the schema, without source locations.
+    #[cfg(test)]
+    pub fn validate(&self, value: &Value) -> Vec<Violation> {
+        let mut validator = Validator {
+            root: &self.root,
+            patterns: &self.patterns,
+            violations: Vec::new(),
+            contents: None,
+        };
+        validator.validate(&self.root, value, &mut Vec::new(), 0);
+        validator.violations
+    }
+
+    /// Decodes a document with `options`, as it would be converted, and
+    /// validates it, pointing violations at the source.
+    pub fn check(
+        &self,
+        contents: &str,
+        format: Format,
+        options: &InputOptions,
+    ) -> Result<(), X2YError> {
+        let value = transcoder::decode_with(contents, format, options)?;
+        let mut validator = Validator {
+            root: &self.root,
+            patterns: &self.patterns,
+            violations: Vec::new(),
+            contents: Some(contents),
+        };
+        validator.validate(&self.root, &value, &mut Vec::new(), 0);
+        if validator.violations.is_empty() {
+            Ok(())
+        } else {
+            Err(X2YError::Validation(Box::new(ValidationError {
+                violations: validator.violations,
+                path: None,
+            })))
+        }
+    }
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+    patterns: &'a Patterns,
+    violations: Vec<Violation>,
+    contents: Option<&'a str>,
+}
+
+impl<'a> Validator<'a> {
+    fn report(&mut self, path: &[Segment], message: String) {
+        self.violations.push(Violation {
+            value_path: display_path(&segments_path(path)).to_string(),
+            message,
+            location: self.contents.and_then(|contents| locate(contents, path)),
+        });
+    }
+
+    // Whether the value matches, for the combinators, without reporting.
+    fn matches(
+        &self,
+        schema: &Value,
+        value: &Value,
+        path: &mut Vec<Segment>,
+        depth: usize,
+    ) -> bool {
+        let mut validator = Validator {
+            root: self.root,
+            patterns: self.patterns,
+            violations: Vec::new(),
+            contents: None,
+        };
+        validator.validate(schema, value, path, depth);
+        validator.violations.is_empty()
+    }
+
+    fn validate(&mut self, schema: &Value, value: &Value, path: &mut Vec<Segment>, depth: usize) {
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => return self.report(path, "no value is allowed here".to_string()),
+            Value::Mapping(schema) => schema,
+            _ => return,
+        };
+        if depth > MAX_DEPTH {
+            return self.report(path, "the schema refers to itself without end".to_string());
+        }
+
+        if let Some(Value::String(reference)) = schema.get("$ref") {
+            // References were resolved when the schema was loaded.
+            if let Some(target) = resolve_ref(self.root, reference) {
+                self.validate(target, value, path, depth + 1);
+            }
+        }
+        if let Some(types) = schema.get("type") {
+            let types: Vec<&str> = match types {
+                Value::String(t) => vec![t.as_str()],
+                Value::Sequence(ts) => ts
+                    .iter()
+                    .filter_map(|t| match t {
+                        Value::String(t) => Some(t.as_str()),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+            if !types.iter().any(|t| has_type(value, t)) {
+                self.report(
+                    path,
+                    format!(
+                        "expected {} but found {}",
+                        types.join(" or "),
+                        type_name(value)
+                    ),
+                );
+            }
+        }
+        if let Some(Value::Sequence(options)) = schema.get("enum") {
+            if !options.iter().any(|option| equal(option, value)) {
+                self.report(
+                    path,
+                    format!(
+                        "{} is not one of {}",
+                        inline_json(value),
+                        inline_json(&Value::Sequence(options.clone()))
+                    ),
+                );
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if !equal(expected, value) {
+                self.report(
+                    path,
+                    format!("{} is not {}", inline_json(value), inline_json(expected)),
+                );
+            }
+        }
+
+        match value {
+            Value::Integer(_) | Value::Float(_) => self.number(schema, value, path),
+            Value::String(s) | Value::Datetime(s) => self.string(schema, s, path),
+            Value::Sequence(items) => self.sequence(schema, items, path, depth),
+            Value::Mapping(map) => self.mapping(schema, map, path, depth),
+            _ => {}
+        }
+
+        if let Some(Value::Sequence(schemas)) = schema.get("allOf") {
+            for subschema in schemas {
+                self.validate(subschema, value, path, depth + 1);
+            }
+        }
+        if let Some(Value::Sequence(schemas)) = schema.get("anyOf") {
+            if !schemas
+                .iter()
+                .any(|s| self.matches(s, value, path, depth + 1))
+            {
+                self.report(
+                    path,
+                    "the value doesn't match any of the anyOf schemas".to_string(),
+                );
+            }
+        }
+        if let Some(Value::Sequence(schemas)) = schema.get("oneOf") {
+            let matching = schemas
+                .iter()
+                .filter(|s| self.matches(s, value, path, depth + 1))
+                .count();
+            if matching != 1 {
+                self.report(
+                    path,
+                    format!(
+                        "the value must match exactly one of the oneOf schemas but matches {}",
+                        matching
+                    ),
+                );
+            }
+        }
+        if let Some(not) = schema.get("not") {
+            if self.matches(not, value, path, depth + 1) {
+                self.report(path, "the value matches the schema in not".to_string());
+            }
+        }
+        if let Some(condition) = schema.get("if") {
+            let branch = if self.matches(condition, value, path, depth + 1) {
+                schema.get("then")
+            } else {
+                schema.get("else")
+            };
+            if let Some(branch) = branch {
+                self.validate(branch, value, path, depth + 1);
+            }
+        }
+    }
+
+    fn number(&mut self, schema: &Mapping, value: &Value, path: &[Segment]) {
+        let n = as_f64(value).unwrap_or(f64::NAN);
+        let limit = |keyword: &str| schema.get(keyword).and_then(as_f64);
+        if let Some(minimum) = limit("minimum").filter(|m| n < *m) {
+            self.report(
+                path,
+                format!(
+                    "{} is less than the minimum of {}",
+                    inline_json(value),
+                    minimum
+                ),
+            );
+        }
+        if let Some(maximum) = limit("maximum").filter(|m| n > *m) {
+            self.report(
+                path,
+                format!(
+                    "{} is greater than the maximum of {}",
+                    inline_json(value),
+                    maximum
+                ),
+            );
+        }
+        if let Some(minimum) = limit("exclusiveMinimum").filter(|m| n <= *m) {
+            self.report(
+                path,
+                format!("{} must be greater than {}", inline_json(value), minimum),
+            );
+        }
+        if let Some(maximum) = limit("exclusiveMaximum").filter(|m| n >= *m) {
+            self.report(
+                path,
+                format!("{} must be less than {}", inline_json(value), maximum),
+            );
+        }
+        if let Some(divisor) = limit("multipleOf").filter(|d| *d > 0.0) {
+            let quotient = n / divisor;
+            if (quotient - quotient.round()).abs() > 1e-9 {
+                self.report(
+                    path,
+                    format!("{} is not a multiple of {}", inline_json(value), divisor),
+                );
+            }
+        }
+    }
+
+    fn string(&mut self, schema: &Mapping, s: &str, path: &[Segment]) {
+        let length = s.chars().count();
+        if let Some(minimum) = count(schema, "minLength").filter(|m| length < *m) {
+            self.report(
+                path,
+                format!("the string is shorter than {} characters", minimum),
+            );
+        }
+        if let Some(maximum) = count(schema, "maxLength").filter(|m| length > *m) {
+            self.report(
+                path,
+                format!("the string is longer than {} characters", maximum),
+            );
+        }
+        if let Some(Value::String(pattern)) = schema.get("pattern") {
+            if !self.patterns.matches(pattern, s) {
+                self.report(
+                    path,
+                    format!("{:?} doesn't match the pattern {:?}", s, pattern),
+                );
+            }
+        }
+    }
+
+    fn sequence(
+        &mut self,
+        schema: &Mapping,
+        items: &[Value],
+        path: &mut Vec<Segment>,
+        depth: usize,
+    ) {
+        if let Some(minimum) = count(schema, "minItems").filter(|m| items.len() < *m) {
+            self.report(
+                path,
+                format!("the sequence has fewer than {} items", minimum),
+            );
+        }
+        if let Some(maximum) = count(schema, "maxItems").filter(|m| items.len() > *m) {
+            self.report(
+                path,
+                format!("the sequence has more than {} items", maximum),
+            );
+        }
+        if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+            let duplicate =
+                (1..items.len()).find(|i| items[..*i].iter().any(|other| equal(other, &items[*i])));
+            if let Some(i) = duplicate {
+                self.report(
+                    path,
+                    format!(
+                        "the items must be unique but {} is repeated",
+                        inline_json(&items[i])
+                    ),
+                );
+            }
+        }
+        let prefix = match schema.get("prefixItems") {
+            Some(Value::Sequence(prefix)) => prefix.as_slice(),
+            _ => &[],
+        };
+        for (i, item) in items.iter().enumerate() {
+            let item_schema = match prefix.get(i) {
+                Some(item_schema) => item_schema,
+                None => match schema.get("items") {
+                    Some(item_schema) => item_schema,
+                    None => continue,
+                },
+            };
+            path.push(Segment::Index(i as i128));
+            self.validate(item_schema, item, path, depth + 1);
+            path.pop();
+        }
+        if let Some(contains) = schema.get("contains") {
+            let found = items
+                .iter()
+                .enumerate()
+                .filter(|(i, item)| {
+                    path.push(Segment::Index(*i as i128));
+                    let matches = self.matches(contains, item, path, depth + 1);
+                    path.pop();
+                    matches
+                })
+                .count();
+            let minimum = count(schema, "minContains").unwrap_or(1);
+            if found < minimum {
+                self.report(
+                    path,
+                    format!("fewer than {} items match the contains schema", minimum),
+                );
+            }
+            if let Some(maximum) = count(schema, "maxContains").filter(|m| found > *m) {
+                self.report(
+                    path,
+                    format!("more than {} items match the contains schema", maximum),
+                );
+            }
+        }
+    }
+
+    fn mapping(&mut self, schema: &Mapping, map: &Mapping, path: &mut Vec<Segment>, depth: usize) {
+        if let Some(minimum) = count(schema, "minProperties").filter(|m| map.len() < *m) {
+            self.report(path, format!("the mapping has fewer than {} keys", minimum));
+        }
+        if let Some(maximum) = count(schema, "maxProperties").filter(|m| map.len() > *m) {
+            self.report(path, format!("the mapping has more than {} keys", maximum));
+        }
+        if let Some(Value::Sequence(required)) = schema.get("required") {
+            for key in required {
+                if let Value::String(key) = key {
+                    if !map.contains_key(key) {
+                        self.report(path, format!("{:?} is required", key));
+                    }
+                }
+            }
+        }
+        if let Some(Value::Mapping(dependencies)) = schema.get("dependentRequired") {
+            for (key, required) in dependencies {
+                let (true, Value::Sequence(required)) = (map.contains_key(key), required) else {
+                    continue;
+                };
+                for other in required {
+                    if let Value::String(other) = other {
+                        if !map.contains_key(other) {
+                            self.report(
+                                path,
+                                format!("{:?} is required when {:?} is present", other, key),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        let properties = match schema.get("properties") {
+            Some(Value::Mapping(properties)) => Some(properties),
+            _ => None,
+        };
+        let pattern_properties = match schema.get("patternProperties") {
+            Some(Value::Mapping(pattern_properties)) => Some(pattern_properties),
+            _ => None,
+        };
+        for (key, value) in map {
+            path.push(Segment::Key(key.clone()));
+            if let Some(names) = schema.get("propertyNames") {
+                if !self.matches(names, &Value::String(key.clone()), path, depth + 1) {
+                    self.report(
+                        path,
+                        format!("the key {:?} doesn't match propertyNames", key),
+                    );
+                }
+            }
+            let property = properties.and_then(|p| p.get(key));
+            if let Some(property) = property {
+                self.validate(property, value, path, depth + 1);
+            }
+            let mut matched = false;
+            for (pattern, property) in pattern_properties.into_iter().flatten() {
+                if self.patterns.matches(pattern, key) {
+                    matched = true;
+                    self.validate(property, value, path, depth + 1);
+                }
+            }
+            match property {
+                Some(_) => {}
+                None if matched => {}
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => {
+                        self.report(path, format!("the key {:?} is not allowed", key))
+                    }
+                    Some(additional) => self.validate(additional, value, path, depth + 1),
+                    None => {}
+                },
+            }
+            path.pop();
+        }
+    }
+}
+
+impl Patterns {
+    fn matches(&self, pattern: &str, s: &str) -> bool {
+        self.0.get(pattern).is_some_and(|regex| regex.is_match(s))
+    }
+}
+
+// Compiles the patterns in `schema`, at `pointer` in the root, and checks it
+// only uses what's supported.
+fn prepare(
+    root: &Value,
+    schema: &Value,
+    pointer: &str,
+    patterns: &mut Patterns,
+) -> Result<(), X2YError> {
+    let unsupported = |what: String| {
+        X2YError::InvalidInput(format!(
+            "the schema uses {} at {}, which isn't supported",
+            what, pointer
+        ))
+    };
+    let Value::Mapping(map) = schema else {
+        return Ok(());
+    };
+    if let Some(keyword) = UNSUPPORTED.iter().find(|k| map.contains_key(**k)) {
+        return Err(unsupported(keyword.to_string()));
+    }
+    if let Some(Value::String(reference)) = map.get("$ref") {
+        if resolve_ref(root, reference).is_none() {
+            return Err(unsupported(format!("the $ref {:?}", reference)));
+        }
+    }
+    if let Some(Value::Sequence(_)) = map.get("items") {
+        return Err(unsupported("items as an array".to_string()));
+    }
+    let mut compile = |pattern: &str| -> Result<(), X2YError> {
+        if !patterns.0.contains_key(pattern) {
+            let regex = Regex::new(pattern)
+                .map_err(|_| unsupported(format!("the pattern {:?}", pattern)))?;
+            patterns.0.insert(pattern.to_string(), regex);
+        }
+        Ok(())
+    };
+    if let Some(Value::String(pattern)) = map.get("pattern") {
+        compile(pattern)?;
+    }
+    if let Some(Value::Mapping(properties)) = map.get("patternProperties") {
+        for pattern in properties.keys() {
+            compile(pattern)?;
+        }
+    }
+    for (keyword, value) in map {
+        if DATA.contains(&keyword.as_str()) {
+            continue;
+        }
+        let pointer = format!("{}/{}", pointer, escape(keyword));
+        match value {
+            Value::Mapping(schemas) if SCHEMA_MAPS.contains(&keyword.as_str()) => {
+                for (name, schema) in schemas {
+                    prepare(
+                        root,
+                        schema,
+                        &format!("{}/{}", pointer, escape(name)),
+                        patterns,
+                    )?;
+                }
+            }
+            Value::Mapping(_) => prepare(root, value, &pointer, patterns)?,
+            Value::Sequence(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    prepare(root, item, &format!("{}/{}", pointer, i), patterns)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn escape(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+// Only references within the schema, `#` and `#/$defs/name`, are supported.
+fn resolve_ref<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    let pointer = reference.strip_prefix('#')?;
+    if pointer.is_empty() {
+        return Some(root);
+    }
+    pointer
+        .strip_prefix('/')?
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .try_fold(root, |value, token| match value {
+            Value::Mapping(map) => map.get(&token),
+            Value::Sequence(items) => items.get(token.parse::<usize>().ok()?),
+            _ => None,
+        })
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => match value {
+            Value::Integer(_) => true,
+            Value::Float(f) => f.fract() == 0.0,
+            _ => false,
+        },
+        "number" => matches!(value, Value::Integer(_) | Value::Float(_)),
+        other => type_name(value) == other,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Integer(_) => "integer",
+        Value::Float(_) => "number",
+        Value::String(_) | Value::Datetime(_) => "string",
+        Value::Sequence(_) => "array",
+        Value::Mapping(_) => "object",
+    }
+}
+
+fn equal(a: &Value, b: &Value) -> bool {
+    diff::diff(a, b).is_empty()
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(i) => Some(*i as f64),
+        Value::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+fn count(schema: &Mapping, keyword: &str) -> Option<usize> {
+    match schema.get(keyword) {
+        Some(Value::Integer(n)) => usize::try_from(*n).ok(),
+        _ => None,
+    }
+}
+
+// The decoders don't keep positions, so values are found by looking for each
+// key in turn in the source. This works for the usual layouts of all three
+// formats and gives up rather than guess when a key can't be found.
+fn locate(contents: &str, path: &[Segment]) -> Option<Location> {
+    let mut offset = 0;
+    let mut found = false;
+    for segment in path {
+        let Segment::Key(key) = segment else {
+            continue;
+        };
+        offset += find_key(&contents[offset..], key)?;
+        found = true;
+    }
+    found.then(|| Location::from_offset(contents, offset))
+}
+
+fn find_key(contents: &str, key: &str) -> Option<usize> {
+    let quoted = serde_json::to_string(key).expect("strings always serialize");
+    let single_quoted = format!("'{}'", key);
+    [quoted.as_str(), single_quoted.as_str(), key]
+        .iter()
+        .filter_map(|candidate| {
+            contents.match_indices(candidate).map(|(i, _)| i).find(|i| {
+                let before = contents[..*i].chars().next_back();
+                let after = contents[i + candidate.len()..].trim_start_matches([' ', '\t']);
+                !before.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '-')
+                    && (after.starts_with(':')
+                        || after.starts_with('=')
+                        || after.starts_with(']')
+                        || after.starts_with('.'))
+            })
+        })
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(contents: &str) -> Schema {
+        Schema::new(transcoder::decode(contents, Format::Yaml).unwrap()).unwrap()
+    }
+
+    fn messages(schema: &Schema, document: &str) -> Vec<String> {
+        schema
+            .validate(&transcoder::decode(document, Format::Yaml).unwrap())
+            .into_iter()
+            .map(|v| format!("{}: {}", v.value_path, v.message))
+            .collect()
+    }
+
+    const SERVER: &str = r##"
+type: object
+required: [host, port]
+additionalProperties: false
+properties:
+  host: {type: string, minLength: 1}
+  port: {$ref: "#/$defs/port"}
+  mode: {enum: [dev, prod]}
+  tags: {type: array, items: {type: string}, uniqueItems: true}
+$defs:
+  port: {type: integer, minimum: 1, maximum: 65535}
+"##;
+
+    #[test]
+    fn a_valid_document_has_no_violations() {
+        let schema = schema(SERVER);
+        assert_eq!(
+            messages(&schema, "host: a\nport: 80\nmode: dev\ntags: [x, y]"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn violations_name_the_value_path() {
+        let schema = schema(SERVER);
+        assert_eq!(
+            messages(
+                &schema,
+                "port: 99999\nmode: test\ntags: [x, 1, x]\nextra: true"
+            ),
+            vec![
+                ".: \"host\" is required",
+                ".port: 99999 is greater than the maximum of 65535",
+                ".mode: \"test\" is not one of [\"dev\",\"prod\"]",
+                ".tags: the items must be unique but \"x\" is repeated",
+                ".tags[1]: expected string but found integer",
+                ".extra: the key \"extra\" is not allowed",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn combinators() {
+        let schema = schema("oneOf: [{type: integer}, {type: number}]\n");
+        assert_eq!(messages(&schema, "1.5"), Vec::<String>::new());
+        assert_eq!(
+            messages(&schema, "1"),
+            vec![".: the value must match exactly one of the oneOf schemas but matches 2"]
+        );
+    }
+
+    #[test]
+    fn patterns() {
+        let schema = schema(
+            "properties: {name: {pattern: '^[a-z]+$'}}\n\
+             patternProperties: {'^x-': {type: string}}\n\
+             additionalProperties: false\n",
+        );
+        assert_eq!(
+            messages(&schema, "name: lower\nx-a: b"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            messages(&schema, "name: UPPER\nx-a: 1\ny: 2"),
+            vec![
+                ".name: \"UPPER\" doesn't match the pattern \"^[a-z]+$\"",
+                ".\"x-a\": expected string but found integer",
+                ".y: the key \"y\" is not allowed",
+            ]
+        );
+    }
+
+    #[test]
+    fn schemas_using_what_is_not_supported_are_refused() {
+        let refused = [
+            ("unevaluatedProperties: false", "unevaluatedProperties at #"),
+            ("items: [{type: string}]", "items as an array at #"),
+            (
+                "properties: {dependencies: {dependencies: {}}}",
+                "dependencies at #/properties/dependencies",
+            ),
+            (
+                "properties: {a: {$ref: 'other.json'}}",
+                "the $ref \"other.json\" at #/properties/a",
+            ),
+            (
+                "patternProperties: {'(?=a)': true}",
+                "the pattern \"(?=a)\" at #",
+            ),
+        ];
+
+        for (contents, what) in refused {
+            let root = transcoder::decode(contents, Format::Yaml).unwrap();
+            assert_eq!(
+                Schema::new(root).unwrap_err().to_string(),
+                X2YError::InvalidInput(format!("the schema uses {}, which isn't supported", what))
+                    .to_string()
+            );
+        }
+        // Data is not a schema, and neither are the names of properties.
+        schema("const: {items: [1], $ref: nowhere}");
+        let named = schema(
+            "type: object\nproperties:\n  dependencies: {type: object}\n  name: {type: string}\n\
+             $defs: {additionalItems: {type: string}}",
+        );
+        assert!(named
+            .check(
+                "dependencies: {a: '1'}\nname: b\n",
+                Format::Yaml,
+                &InputOptions::default()
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn violations_point_at_the_source() {
+        let schema = schema(SERVER);
+        let documents = [
+            ("host: a\nport: 0\n", Format::Yaml),
+            ("{\"host\": \"a\",\n  \"port\": 0}", Format::Json),
+            ("host = \"a\"\nport = 0\n", Format::Toml),
+        ];
+
+        for (document, format) in documents {
+            let X2YError::Validation(error) = schema
+                .check(document, format, &InputOptions::default())
+                .unwrap_err()
+            else {
+                panic!("expected a validation error");
+            };
+            assert_eq!(error.violations[0].location.map(|l| l.line), Some(2));
+        }
+    }
+}
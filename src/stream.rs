@@ -0,0 +1,839 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use serde::ser::{self, Impossible, Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_json::Value as JsonValue;
+
+use crate::emitter::{self, YamlEmitter};
+use crate::error::X2YError;
+use crate::limits::{self, Limit};
+use crate::options::{InputOptions, Limits, OutputOptions};
+use crate::transcoder;
+use crate::value::{index_path, key_path, Mapping, Value};
+
+// Converts JSON to YAML as it's read, so memory use doesn't grow with the size
+// of the document. serde-transcode hands each value from the JSON parser to a
+// serializer that writes the same layout as the YAML emitter. Only scalars,
+// the keys of the mappings being written, and the first entry of a mapping
+// whose key could be a YAML tag, are held while they're written. A stream of JSON values, such as NDJSON, becomes a
+// stream of YAML documents.
+
+pub(crate) fn json_to_yaml(
+    reader: impl Read,
+    writer: impl Write,
+    input_options: &InputOptions,
+    options: &OutputOptions,
+) -> Result<(), X2YError> {
+    let limits = &input_options.limits;
+    let mut reader = Input::new(BufReader::new(reader), limits.max_input_size);
+    let mut deserializer = serde_json::Deserializer::from_reader(&mut reader);
+    let mut stream = YamlStream {
+        output: Lines::new(BufWriter::new(writer), options),
+        emitter: YamlEmitter::new(options),
+        limits,
+        frames: Vec::new(),
+        position: Position::Document,
+        nodes: 0,
+        error: None,
+    };
+    let result = stream.documents(&mut deserializer);
+    drop(deserializer);
+    match result {
+        Ok(()) => stream.output.finish().map_err(X2YError::from),
+        Err(_) if reader.exceeded => Err(limits::exceeded(
+            Limit::InputSize,
+            limits.max_input_size.unwrap_or_default(),
+            None,
+        )),
+        Err(e) => Err(stream
+            .error
+            .take()
+            .unwrap_or_else(|| json_error(e.0, &mut reader))),
+    }
+}
+
+// Errors pass through the JSON parser as messages, so parse errors are rebuilt
+// from theirs, the same way as when the JSON is read whole.
+fn json_error<R: Read>(message: String, input: &mut Input<R>) -> X2YError {
+    let (line, column) = match message.rfind(" at line ") {
+        Some(i) => {
+            let mut numbers = message[i..]
+                .split(|c: char| !c.is_ascii_digit())
+                .filter_map(|n| n.parse().ok());
+            (
+                numbers.next().unwrap_or_default(),
+                numbers.next().unwrap_or_default(),
+            )
+        }
+        None => (0, 0),
+    };
+    transcoder::json_error(message, line, column, |line| input.source_line(line))
+}
+
+// Where the next value is written.
+#[derive(Copy, Clone, Debug)]
+enum Position {
+    /// At the start of a document.
+    Document,
+    /// After the `key:` of an entry in a mapping at the level.
+    AfterKey(usize),
+    /// After the `-` of an item in a sequence at the level.
+    AfterDash(usize),
+}
+
+// A mapping or sequence that is being written. Nothing is written for it
+// until its first entry, which is when it's known not to be empty.
+struct Frame {
+    mapping: bool,
+    opened_at: Position,
+    started: bool,
+    level: usize,
+    /// The first entry continues the line the collection was opened on.
+    inline: bool,
+    entries: usize,
+    key: String,
+    /// The keys written so far, a key appearing twice can't be taken back.
+    keys: HashSet<String>,
+    /// The first key looks like a YAML tag, so the entry is held until it's
+    /// known whether it's the only one.
+    tag: Option<String>,
+    tagged: Option<(String, Value)>,
+}
+
+struct YamlStream<'a, W: Write> {
+    output: Lines<W>,
+    emitter: YamlEmitter<'a>,
+    limits: &'a Limits,
+    frames: Vec<Frame>,
+    position: Position,
+    nodes: usize,
+    /// The error behind a failure, which reaches the caller as a message.
+    error: Option<X2YError>,
+}
+
+impl<W: Write> YamlStream<'_, W> {
+    fn documents<R: Read>(
+        &mut self,
+        deserializer: &mut serde_json::Deserializer<serde_json::de::IoRead<R>>,
+    ) -> Result<(), StreamError> {
+        loop {
+            serde_transcode::transcode(&mut *deserializer, &mut *self)?;
+            match deserializer.end() {
+                Ok(()) => return Ok(()),
+                Err(e) if e.is_syntax() && e.to_string().starts_with("trailing characters") => {
+                    self.write("---\n")?;
+                    self.position = Position::Document;
+                }
+                Err(e) => return Err(StreamError(e.to_string())),
+            }
+        }
+    }
+
+    fn fail(&mut self, error: X2YError) -> StreamError {
+        let message = error.to_string();
+        self.error = Some(error);
+        StreamError(message)
+    }
+
+    fn write(&mut self, s: &str) -> Result<(), StreamError> {
+        self.output.write(s).map_err(|e| self.fail(e.into()))
+    }
+
+    fn write_emitted(&mut self) -> Result<(), StreamError> {
+        let output = self.emitter.take_output();
+        self.write(&output)
+    }
+
+    // Where the value being written is in the document, e.g. `.items[2]`.
+    fn path(&self) -> String {
+        self.frames
+            .iter()
+            .filter(|frame| frame.entries > 0)
+            .fold(String::new(), |path, frame| {
+                if frame.mapping {
+                    key_path(&path, &frame.key)
+                } else {
+                    index_path(&path, frame.entries - 1)
+                }
+            })
+    }
+
+    fn count(&mut self) -> Result<(), StreamError> {
+        self.nodes += 1;
+        match self.limits.max_nodes {
+            Some(max) if self.nodes > max => {
+                let error = limits::exceeded(Limit::Nodes, max, Some(&self.path()));
+                Err(self.fail(error))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_string(&mut self, s: &str) -> Result<(), StreamError> {
+        match self.limits.max_string_length {
+            Some(max) if s.len() > max => {
+                let error = limits::exceeded(Limit::StringLength, max, Some(&self.path()));
+                Err(self.fail(error))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn render(&mut self, value: &Value, at: Position) -> Result<(), StreamError> {
+        match at {
+            Position::Document => self.emitter.document(value),
+            Position::AfterKey(level) => self.emitter.after_key("", value, level),
+            Position::AfterDash(level) => self.emitter.after_dash(value, level),
+        }
+        self.write_emitted()
+    }
+
+    fn scalar(&mut self, value: Value) -> Result<(), StreamError> {
+        self.count()?;
+        if let Value::String(s) = &value {
+            self.check_string(s)?;
+        }
+        self.render(&value, self.position)
+    }
+
+    fn open(&mut self, mapping: bool) -> Result<(), StreamError> {
+        self.count()?;
+        if self.frames.len() >= self.limits.max_depth {
+            let error = limits::exceeded(Limit::Depth, self.limits.max_depth, Some(&self.path()));
+            return Err(self.fail(error));
+        }
+        self.frames.push(Frame {
+            mapping,
+            opened_at: self.position,
+            started: false,
+            level: 0,
+            inline: false,
+            entries: 0,
+            key: String::new(),
+            keys: HashSet::new(),
+            tag: None,
+            tagged: None,
+        });
+        Ok(())
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("a collection is open")
+    }
+
+    // Writes what comes before the first entry of the open collection, with
+    // the same layout as the emitter.
+    fn start(&mut self) -> Result<(), StreamError> {
+        let indent = self.emitter.indent();
+        let item_level = |level| self.emitter.item_level(level);
+        let frame = self.frames.last().expect("a collection is open");
+        let (prefix, level, inline) = match frame.opened_at {
+            Position::Document => (String::new(), 0, false),
+            Position::AfterKey(level) if frame.mapping => ("\n".to_string(), level + indent, false),
+            Position::AfterKey(level) => ("\n".to_string(), level, false),
+            Position::AfterDash(level) => {
+                let child = item_level(level);
+                (" ".repeat(child - level - 1), child, true)
+            }
+        };
+        let frame = self.frame();
+        frame.started = true;
+        frame.level = level;
+        frame.inline = inline;
+        self.write(&prefix)
+    }
+
+    // Writes the start of a line for an entry or item of the open collection.
+    fn line(&mut self, start: &str) -> Result<(), StreamError> {
+        let frame = self.frame();
+        let pad = if frame.inline { 0 } else { frame.level };
+        frame.inline = false;
+        let line = format!("{}{}", " ".repeat(pad), start);
+        self.write(&line)
+    }
+
+    fn key(&mut self, key: String) -> Result<(), StreamError> {
+        let frame = self.frame();
+        if !frame.keys.insert(key.clone()) {
+            return Err(StreamError(format!(
+                "the key {:?} appears twice, which can't be streamed",
+                key
+            )));
+        }
+        let first = frame.entries == 0;
+        frame.entries += 1;
+        frame.key.clone_from(&key);
+        self.count()?;
+        self.check_string(&key)?;
+        if first && emitter::is_tag(&key) {
+            self.frame().tag = Some(key);
+            return Ok(());
+        }
+        if let Some((tag, value)) = self.frame().tagged.take() {
+            self.start()?;
+            self.entry(&tag)?;
+            let level = self.frame().level;
+            self.emitter.after_key(&tag, &value, level);
+            self.write_emitted()?;
+        } else if !self.frame().started {
+            self.start()?;
+        }
+        self.entry(&key)
+    }
+
+    fn entry(&mut self, key: &str) -> Result<(), StreamError> {
+        self.line(&format!("{}:", emitter::yaml_key(key)))?;
+        self.position = Position::AfterKey(self.frame().level);
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), StreamError> {
+        let frame = self.frames.pop().expect("a collection is open");
+        if let Some((tag, value)) = frame.tagged {
+            let wrapper = Value::Mapping(Mapping::from([(tag, value)]));
+            self.render(&wrapper, frame.opened_at)
+        } else if !frame.started {
+            let empty = if frame.mapping {
+                Value::Mapping(Mapping::new())
+            } else {
+                Value::Sequence(Vec::new())
+            };
+            self.render(&empty, frame.opened_at)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<W: Write> Serializer for &mut YamlStream<'_, W> {
+    type Ok = ();
+    type Error = StreamError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Impossible<(), StreamError>;
+    type SerializeTupleStruct = Impossible<(), StreamError>;
+    type SerializeTupleVariant = Impossible<(), StreamError>;
+    type SerializeMap = Self;
+    type SerializeStruct = Impossible<(), StreamError>;
+    type SerializeStructVariant = Impossible<(), StreamError>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), StreamError> {
+        self.scalar(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), StreamError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), StreamError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), StreamError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), StreamError> {
+        self.scalar(Value::Integer(v.into()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), StreamError> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), StreamError> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), StreamError> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), StreamError> {
+        self.scalar(Value::Integer(v.into()))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), StreamError> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), StreamError> {
+        self.scalar(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), StreamError> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), StreamError> {
+        self.scalar(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<(), StreamError> {
+        Err(unsupported("bytes"))
+    }
+
+    fn serialize_none(self) -> Result<(), StreamError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), StreamError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), StreamError> {
+        self.scalar(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), StreamError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<(), StreamError> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), StreamError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), StreamError> {
+        Err(unsupported("enums"))
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self, StreamError> {
+        self.open(false)?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, StreamError> {
+        Err(unsupported("tuples"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, StreamError> {
+        Err(unsupported("tuples"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, StreamError> {
+        Err(unsupported("enums"))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self, StreamError> {
+        self.open(true)?;
+        Ok(self)
+    }
+
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, StreamError> {
+        Err(unsupported("structs"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, StreamError> {
+        Err(unsupported("enums"))
+    }
+}
+
+impl<W: Write> SerializeSeq for &mut YamlStream<'_, W> {
+    type Ok = ();
+    type Error = StreamError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), StreamError> {
+        self.frame().entries += 1;
+        if !self.frame().started {
+            self.start()?;
+        }
+        self.line("-")?;
+        self.position = Position::AfterDash(self.frame().level);
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), StreamError> {
+        self.close()
+    }
+}
+
+impl<W: Write> SerializeMap for &mut YamlStream<'_, W> {
+    type Ok = ();
+    type Error = StreamError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), StreamError> {
+        match serde_json::to_value(key) {
+            Ok(JsonValue::String(key)) => self.key(key),
+            Ok(_) => Err(unsupported("keys that aren't strings")),
+            Err(e) => Err(StreamError(e.to_string())),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), StreamError> {
+        let Some(tag) = self.frame().tag.take() else {
+            return value.serialize(&mut **self);
+        };
+        let json = serde_json::to_value(value).map_err(|e| StreamError(e.to_string()))?;
+        let value = transcoder::from_json(json);
+        let (path, depth) = (self.path(), self.frames.len());
+        let mut nodes = self.nodes;
+        limits::check_within(&value, self.limits, &path, depth, &mut nodes)
+            .map_err(|e| self.fail(e))?;
+        self.nodes = nodes;
+        self.frame().tagged = Some((tag, value));
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), StreamError> {
+        self.close()
+    }
+}
+
+fn unsupported(what: &str) -> StreamError {
+    StreamError(format!("{} can't be streamed", what))
+}
+
+#[derive(Debug)]
+pub(crate) struct StreamError(String);
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+impl ser::Error for StreamError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        StreamError(msg.to_string())
+    }
+}
+
+// Applies the line ending and trailing newline options as the output is
+// written, like `emitter::finish` does for a whole document. A final newline
+// is held back until it's known whether anything follows it.
+struct Lines<W: Write> {
+    inner: W,
+    ending: &'static str,
+    trailing_newline: bool,
+    held: bool,
+}
+
+impl<W: Write> Lines<W> {
+    fn new(inner: W, options: &OutputOptions) -> Self {
+        Self {
+            inner,
+            ending: options.line_ending.as_str(),
+            trailing_newline: options.trailing_newline,
+            held: false,
+        }
+    }
+
+    fn write(&mut self, s: &str) -> io::Result<()> {
+        if s.is_empty() {
+            return Ok(());
+        }
+        if std::mem::take(&mut self.held) {
+            self.inner.write_all(self.ending.as_bytes())?;
+        }
+        let (s, held) = match s.strip_suffix('\n') {
+            Some(s) => (s, true),
+            None => (s, false),
+        };
+        self.held = held;
+        if self.ending == "\n" {
+            self.inner.write_all(s.as_bytes())
+        } else {
+            self.inner
+                .write_all(s.replace('\n', self.ending).as_bytes())
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.held && self.trailing_newline {
+            self.inner.write_all(self.ending.as_bytes())?;
+        }
+        self.inner.flush()
+    }
+}
+
+// Longer source lines aren't kept for errors, minified JSON can be a single
+// line of any size.
+const MAX_SOURCE_LINE: usize = 1024;
+
+// Stops reading with an error once more than the size limit has been read,
+// and keeps the line being read, and the one before it as the parser may have
+// looked one byte ahead, to show in a parse error.
+struct Input<R> {
+    inner: R,
+    remaining: Option<usize>,
+    exceeded: bool,
+    /// The number of the line being read, from 1.
+    line: usize,
+    current: Vec<u8>,
+    previous: Vec<u8>,
+}
+
+impl<R: Read> Input<R> {
+    fn new(inner: R, max_input_size: Option<usize>) -> Self {
+        Self {
+            inner,
+            remaining: max_input_size,
+            exceeded: false,
+            line: 1,
+            current: Vec::new(),
+            previous: Vec::new(),
+        }
+    }
+
+    // Reads the rest of the line if it's the one being read.
+    fn source_line(&mut self, line: usize) -> Option<String> {
+        let mut byte = [0];
+        while self.line == line && self.current.len() <= MAX_SOURCE_LINE {
+            if !matches!(self.read(&mut byte), Ok(1)) {
+                break;
+            }
+        }
+        // Like `str::lines`, there's no line after a final newline.
+        let source = if line == self.line && !self.current.is_empty() {
+            &self.current
+        } else if line + 1 == self.line {
+            &self.previous
+        } else {
+            return None;
+        };
+        if source.len() > MAX_SOURCE_LINE {
+            return None;
+        }
+        let source = String::from_utf8_lossy(source);
+        Some(source.strip_suffix('\r').unwrap_or(&source).to_string())
+    }
+}
+
+impl<R: Read> Read for Input<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if let Some(remaining) = &mut self.remaining {
+            match remaining.checked_sub(read) {
+                Some(left) => *remaining = left,
+                None => {
+                    self.exceeded = true;
+                    return Err(io::Error::other("the input is over the size limit"));
+                }
+            }
+        }
+        for &byte in &buf[..read] {
+            if byte == b'\n' {
+                self.previous = std::mem::take(&mut self.current);
+                self.line += 1;
+            } else if self.current.len() <= MAX_SOURCE_LINE {
+                self.current.push(byte);
+            }
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+    use crate::options::{LineEnding, QuoteStyle};
+    use crate::transcoder::transcode;
+
+    fn streamed(input: &str, input_options: &InputOptions, options: &OutputOptions) -> String {
+        let mut output = Vec::new();
+        json_to_yaml(input.as_bytes(), &mut output, input_options, options).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn streamed_output_matches_the_emitter() {
+        let documents = [
+            r#"{"a": 1, "b": [1, 2.5, true, null, "yes", "", "multi\nline", "0x1F"], "c": {}, "d": []}"#,
+            r#"{"e": {"f": [[1, 2], [3], []], "g": [{"h": 1, "i": {"j": [{}]}}]}}"#,
+            r#"[1, {"a": []}, [[{"b": "x"}]], -9223372036854775808, 18446744073709551615, 1e300]"#,
+            r#""scalar""#,
+            r#"{}"#,
+            r#"{"Bucket": {"!Ref": "Name"}, "List": [{"!GetAtt": ["a", "b"]}], "!Sub": 1, "x": 2}"#,
+            r#"{"!Tag": {"a": [1, {"!Ref": "b"}]}}"#,
+        ];
+        let styles = [
+            OutputOptions::default(),
+            OutputOptions {
+                indent: Some(4),
+                quote_style: QuoteStyle::Double,
+                line_ending: LineEnding::Crlf,
+                trailing_newline: false,
+                ..Default::default()
+            },
+        ];
+        for options in &styles {
+            for document in documents {
+                assert_eq!(
+                    streamed(document, &InputOptions::default(), options),
+                    transcode(document, Format::Json, Format::Yaml, options).unwrap(),
+                    "{}",
+                    document
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn a_stream_of_values_becomes_yaml_documents() {
+        let input = "{\"id\": 1}\n{\"id\": 2}\n[]\n";
+
+        assert_eq!(
+            streamed(input, &InputOptions::default(), &OutputOptions::default()),
+            "id: 1\n---\nid: 2\n---\n[]\n"
+        );
+        let options = OutputOptions {
+            line_ending: LineEnding::Crlf,
+            trailing_newline: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            streamed(input, &InputOptions::default(), &options),
+            transcode(input, Format::Json, Format::Yaml, &options).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_errors_match_those_of_the_whole_document() {
+        let inputs = [
+            "{\"a\": 1,\n  \"b\": tru}\n",
+            "{\"a\": [1,",
+            "{\"a\": [1,\n",
+            "[1,\r\n 2 x]",
+            "{\"a\": 1}\n{\"b\"}\n",
+            "",
+        ];
+        for input in inputs {
+            let streamed = json_to_yaml(
+                input.as_bytes(),
+                io::sink(),
+                &InputOptions::default(),
+                &OutputOptions::default(),
+            )
+            .unwrap_err();
+            let whole = transcode(input, Format::Json, Format::Yaml, &OutputOptions::default())
+                .unwrap_err();
+            let (X2YError::Parse(streamed), X2YError::Parse(whole)) = (streamed, whole) else {
+                panic!("{:?} should be a parse error", input);
+            };
+            assert_eq!(streamed.to_string(), whole.to_string(), "{:?}", input);
+            assert_eq!(streamed.source_line, whole.source_line, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn limits_and_parse_errors_are_reported() {
+        let run = |input: &str, limits: Limits| {
+            let options = InputOptions {
+                limits,
+                ..Default::default()
+            };
+            json_to_yaml(
+                input.as_bytes(),
+                io::sink(),
+                &options,
+                &OutputOptions::default(),
+            )
+            .unwrap_err()
+        };
+
+        let error = run(
+            r#"{"a": [1, {"b": 2}]}"#,
+            Limits {
+                max_depth: 2,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            error.to_string(),
+            "the document is over the limit of 2 levels of nesting at .a[1]"
+        );
+
+        let error = run(
+            r#"{"a": "x", "b": "too long"}"#,
+            Limits {
+                max_string_length: Some(4),
+                ..Default::default()
+            },
+        );
+        assert!(matches!(error, X2YError::Limit(e) if e.value_path.as_deref() == Some(".b")));
+
+        let error = run(
+            &format!("[{}]", ["1"; 1000].join(", ")),
+            Limits {
+                max_input_size: Some(100),
+                ..Default::default()
+            },
+        );
+        assert!(matches!(error, X2YError::Limit(e) if e.limit == Limit::InputSize));
+
+        let error = run("{\n  \"a\": 1,\n  \"b\" 2\n}", Limits::default());
+        assert_eq!(error.code(), "E102");
+        assert_eq!(
+            error.to_string(),
+            "invalid json: expected `:` at line 3 column 7"
+        );
+    }
+
+    #[test]
+    fn a_key_appearing_twice_is_refused() {
+        let mut output = Vec::new();
+        let error = json_to_yaml(
+            r#"{"a": {"b": 1, "c": {"b": 2}, "b": 3}}"#.as_bytes(),
+            &mut output,
+            &InputOptions::default(),
+            &OutputOptions::default(),
+        )
+        .unwrap_err();
+
+        assert_eq!(error.code(), "E102");
+        assert_eq!(
+            error.to_string(),
+            "invalid json: the key \"b\" appears twice, which can't be streamed at line 1 column 34"
+        );
+        assert_eq!(
+            streamed(
+                r#"{"a": 1}{"a": 2}"#,
+                &InputOptions::default(),
+                &OutputOptions::default()
+            ),
+            "a: 1\n---\na: 2\n"
+        );
+    }
+}
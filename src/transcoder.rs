@@ -1,99 +1,787 @@
-use crate::error::X2YError;
+use crate::coercion::{self, YamlFindings};
+use crate::emitter;
+use crate::error::{Location, ParseError, Violation, X2YError};
 use crate::format::Format;
+use crate::limits::{self, Limit};
+use crate::options::{InputOptions, Limits, OutputOptions, SequenceStyle, YamlTags};
+use crate::stream;
+use crate::value::{index_path, key_path, Mapping, Value};
 
+use serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, IgnoredAny, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
 use serde_json::Value as JsonValue;
+use serde_yaml::value::Tag;
 use serde_yaml::Value as YamlValue;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Read, Write};
 use toml::Value as TomlValue;
+use yaml_rust2::parser::{Event, Parser};
+use yaml_rust2::scanner::TScalarStyle;
 
+/// Converts documents from one format to another.
+///
+/// ```
+/// use x2y::{Format, Transcoder};
+///
+/// let toml = Transcoder::new(Format::Yaml, Format::Toml).transcode_str("port: 8080\n")?;
+/// assert_eq!(toml, "port = 8080\n");
+/// # Ok::<(), x2y::X2YError>(())
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transcoder {
+    input_format: Format,
+    output_format: Format,
+    input_options: InputOptions,
+    output_options: OutputOptions,
+}
+
+impl Transcoder {
+    /// Converts from `input_format` to `output_format` with the default
+    /// options. The formats have to differ.
+    pub fn new(input_format: Format, output_format: Format) -> Self {
+        Self {
+            input_format,
+            output_format,
+            input_options: InputOptions::default(),
+            output_options: OutputOptions::default(),
+        }
+    }
+
+    /// Sets how documents are read, including the limits on their size.
+    pub fn input_options(mut self, options: InputOptions) -> Self {
+        self.input_options = options;
+        self
+    }
+
+    /// Sets how documents are written.
+    pub fn output_options(mut self, options: OutputOptions) -> Self {
+        self.output_options = options;
+        self
+    }
+
+    pub fn transcode_str(&self, contents: &str) -> Result<String, X2YError> {
+        transcode_with_warnings(
+            contents,
+            self.input_format,
+            self.output_format,
+            &self.input_options,
+            &self.output_options,
+        )
+        .map(|(output, _)| output)
+    }
+
+    /// Like `transcode_str`, failing if `contents` isn't UTF-8.
+    pub fn transcode_bytes(&self, contents: &[u8]) -> Result<Vec<u8>, X2YError> {
+        let contents = std::str::from_utf8(contents)
+            .map_err(|_| X2YError::InvalidInput("the input is not valid utf-8".to_string()))?;
+        self.transcode_str(contents).map(String::into_bytes)
+    }
+
+    /// Converts everything read from `reader`, writing the output to
+    /// `writer`. JSON to YAML is converted as it's read so memory use stays
+    /// flat, see `streams`, and `writer` may hold partial output on error.
+    pub fn transcode_reader_to_writer(
+        &self,
+        reader: impl Read,
+        writer: impl Write,
+    ) -> Result<(), X2YError> {
+        transcode_stream(
+            reader,
+            writer,
+            self.input_format,
+            self.output_format,
+            &self.input_options,
+            &self.output_options,
+        )
+        .map(|_| ())
+    }
+}
+
+/// Converts `contents` from one format to another with the default input
+/// options.
 pub fn transcode(
     contents: &str,
     input_format: Format,
     output_format: Format,
+    options: &OutputOptions,
 ) -> Result<String, X2YError> {
-    match (input_format, output_format) {
-        (Format::Yaml, Format::Json) => yaml_to_json(contents),
-        (Format::Yaml, Format::Toml) => yaml_to_toml(contents),
-        (Format::Json, Format::Toml) => json_to_toml(contents),
-        (Format::Json, Format::Yaml) => json_to_yaml(contents),
-        (Format::Toml, Format::Json) => toml_to_json(contents),
-        (Format::Toml, Format::Yaml) => toml_to_yaml(contents),
-        _ => Err(X2YError::InvalidInput(format!(
+    transcode_with_warnings(
+        contents,
+        input_format,
+        output_format,
+        &InputOptions::default(),
+        options,
+    )
+    .map(|(output, _)| output)
+}
+
+/// Converts `contents` along with a warning for every value that had to be
+/// changed to fit the output format.
+pub fn transcode_with_warnings(
+    contents: &str,
+    input_format: Format,
+    output_format: Format,
+    input_options: &InputOptions,
+    options: &OutputOptions,
+) -> Result<(String, Vec<Violation>), X2YError> {
+    if input_format == output_format {
+        return Err(X2YError::InvalidInput(format!(
             "Incompatible input and output formats: {} -> {}",
             input_format, output_format
-        ))),
+        )));
     }
+    // Like the streamed conversion, several JSON values become several YAML
+    // documents.
+    let (values, yaml) = match (input_format, output_format) {
+        (Format::Json, Format::Yaml) => (decode_json_values(contents, input_options)?, None),
+        _ => {
+            let (value, yaml) = decode_inspected(contents, input_format, input_options)?;
+            (vec![value], yaml)
+        }
+    };
+    let last = values.len() - 1;
+    let mut output = String::new();
+    let mut warnings = Vec::new();
+    for (i, mut value) in values.into_iter().enumerate() {
+        warnings.extend(coercion::coercions(yaml.as_ref(), &value, output_format));
+        if options.sort_keys {
+            value.sort_keys();
+        }
+        if i > 0 {
+            output.push_str("---");
+            output.push_str(options.line_ending.as_str());
+        }
+        let options = OutputOptions {
+            trailing_newline: options.trailing_newline || i < last,
+            ..options.clone()
+        };
+        output.push_str(&encode(&value, output_format, &options)?);
+    }
+    Ok((output, warnings))
 }
 
-fn yaml_to_json(contents: &str) -> Result<String, X2YError> {
-    let json_value: JsonValue = match serde_yaml::from_str(contents) {
-        Ok(v) => v,
-        Err(e) => return Err(X2YError::Transcode(e.into())),
-    };
-    let json_string = match serde_json::to_string_pretty(&json_value) {
-        Ok(s) => s,
-        Err(e) => return Err(X2YError::Transcode(e.into())),
-    };
-    Ok(json_string)
+// Every JSON value in `contents`, such as each line of NDJSON.
+fn decode_json_values(contents: &str, options: &InputOptions) -> Result<Vec<Value>, X2YError> {
+    limits::check_size(contents.len(), &options.limits)?;
+    let mut values = Vec::new();
+    for json_value in serde_json::Deserializer::from_str(contents).into_iter::<JsonValue>() {
+        let value = from_json(json_value.map_err(|e| json_parse_error(&e, contents))?);
+        limits::check(&value, &options.limits)?;
+        values.push(value);
+    }
+    if values.is_empty() {
+        // Reports the missing value.
+        values.push(decode_with(contents, Format::Json, options)?);
+    }
+    Ok(values)
 }
 
-fn yaml_to_toml(contents: &str) -> Result<String, X2YError> {
-    let toml_value: TomlValue = match serde_yaml::from_str(contents) {
-        Ok(v) => v,
-        Err(e) => return Err(X2YError::Transcode(e.into())),
-    };
-    let toml_string = match toml::to_string_pretty(&toml_value) {
-        Ok(s) => s,
-        Err(e) => return Err(X2YError::Transcode(e.into())),
-    };
-    Ok(toml_string)
+/// Whether `transcode_stream` converts between the formats as the input is
+/// read. Only JSON can be read a value at a time, and only YAML written a
+/// value at a time, unless the options need the whole document: sorted keys,
+/// anchors or flow sequences.
+pub fn streams(input_format: Format, output_format: Format, options: &OutputOptions) -> bool {
+    input_format == Format::Json
+        && output_format == Format::Yaml
+        && !options.sort_keys
+        && !options.yaml_anchors
+        && options.sequence_style == SequenceStyle::Block
 }
 
-fn json_to_toml(contents: &str) -> Result<String, X2YError> {
-    let toml_value: TomlValue = match serde_json::from_str(contents) {
-        Ok(v) => v,
-        Err(e) => return Err(X2YError::Transcode(e.into())),
-    };
-    let toml_string = match toml::to_string_pretty(&toml_value) {
-        Ok(s) => s,
-        Err(e) => return Err(X2YError::Transcode(e.into())),
-    };
-    Ok(toml_string)
+/// Converts everything read from `reader`, writing the output to `writer` as
+/// it goes when the formats stream, see `streams`, so memory use stays flat
+/// however large the input. Otherwise the input is read whole first. Returns
+/// the values that had to be changed to fit the output format.
+///
+/// When streaming, an error partway through the input leaves the output
+/// written before it in `writer`, and a key appearing twice in a JSON object
+/// is an error rather than the last value winning.
+pub fn transcode_stream(
+    reader: impl Read,
+    mut writer: impl Write,
+    input_format: Format,
+    output_format: Format,
+    input_options: &InputOptions,
+    options: &OutputOptions,
+) -> Result<Vec<Violation>, X2YError> {
+    if streams(input_format, output_format, options) {
+        stream::json_to_yaml(reader, writer, input_options, options)?;
+        return Ok(Vec::new());
+    }
+    let contents = limits::read(reader, &input_options.limits)?;
+    let contents = String::from_utf8(contents)
+        .map_err(|e| X2YError::from(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+    let (output, warnings) = transcode_with_warnings(
+        &contents,
+        input_format,
+        output_format,
+        input_options,
+        options,
+    )?;
+    writer.write_all(output.as_bytes())?;
+    Ok(warnings)
 }
 
-fn json_to_yaml(contents: &str) -> Result<String, X2YError> {
-    let yaml_value: YamlValue = match serde_json::from_str(contents) {
-        Ok(v) => v,
-        Err(e) => return Err(X2YError::Transcode(e.into())),
-    };
-    let yaml_string = match serde_yaml::to_string(&yaml_value) {
-        Ok(s) => s,
-        Err(e) => return Err(X2YError::Transcode(e.into())),
+/// Decodes `contents` like `decode_with`, along with what only the source
+/// shows when it's YAML, read in the same pass.
+pub(crate) fn decode_inspected(
+    contents: &str,
+    format: Format,
+    options: &InputOptions,
+) -> Result<(Value, Option<YamlFindings>), X2YError> {
+    if format != Format::Yaml {
+        return Ok((decode_with(contents, format, options)?, None));
+    }
+    limits::check_size(contents.len(), &options.limits)?;
+    let (yaml, node) = read_yaml(contents, &options.limits)?;
+    let findings = YamlFindings::new(&yaml, &node, options.yaml_tags);
+    let value = from_yaml(yaml, "", options.yaml_tags)?;
+    limits::check(&value, &options.limits)?;
+    Ok((value, Some(findings)))
+}
+
+pub fn decode(contents: &str, format: Format) -> Result<Value, X2YError> {
+    decode_with(contents, format, &InputOptions::default())
+}
+
+pub fn decode_with(
+    contents: &str,
+    format: Format,
+    options: &InputOptions,
+) -> Result<Value, X2YError> {
+    limits::check_size(contents.len(), &options.limits)?;
+    let value = match format {
+        Format::Yaml => from_yaml(
+            parse_yaml(contents, &options.limits)?,
+            "",
+            options.yaml_tags,
+        )?,
+        Format::Json => {
+            let json_value: JsonValue =
+                serde_json::from_str(contents).map_err(|e| json_parse_error(&e, contents))?;
+            from_json(json_value)
+        }
+        Format::Toml => {
+            let toml_value: TomlValue = toml::from_str(contents).map_err(|e| {
+                let location = e.span().map(|s| Location::from_offset(contents, s.start));
+                parse_error(format, e.message().to_string(), contents, location)
+            })?;
+            from_toml(toml_value)
+        }
     };
-    Ok(yaml_string)
+    limits::check(&value, &options.limits)?;
+    Ok(value)
 }
 
-fn toml_to_yaml(contents: &str) -> Result<String, X2YError> {
-    let yaml_value: YamlValue = match toml::from_str(contents) {
-        Ok(v) => v,
-        Err(e) => return Err(X2YError::Transcode(e.into())),
+/// Reads a YAML document with its aliases expanded and its `<<` merge keys
+/// applied.
+pub(crate) fn parse_yaml(contents: &str, limits: &Limits) -> Result<YamlValue, X2YError> {
+    read_yaml(contents, limits).map(|(value, _)| value)
+}
+
+// Reads a YAML document like `parse_yaml`, along with how its nodes were
+// written.
+fn read_yaml(contents: &str, limits: &Limits) -> Result<(YamlValue, Node), X2YError> {
+    let yaml_error = |e: serde_yaml::Error| {
+        let location = e.location().map(|l| Location {
+            line: l.line(),
+            column: l.column(),
+        });
+        parse_error(Format::Yaml, e.to_string(), contents, location)
     };
-    let yaml_string = match serde_yaml::to_string(&yaml_value) {
-        Ok(s) => s,
-        Err(e) => return Err(X2YError::Transcode(e.into())),
+    // Aliases are expanded as a document is read, so a small file can stand
+    // for an enormous one ("billion laughs"). Counting the values first means
+    // an oversized document is rejected before any of it is built. Aliases may
+    // add values beyond the size of the file in bytes, which bounds the values
+    // in a document without them.
+    let expansion = contents.len().saturating_add(limits.max_alias_expansion);
+    let mut budget = match limits.max_nodes {
+        Some(max) if max < expansion => Budget::new(Limit::Nodes, max, max),
+        _ => Budget::new(Limit::AliasExpansion, limits.max_alias_expansion, expansion),
     };
-    Ok(yaml_string)
+    let counted = NodeCounter {
+        budget: &mut budget,
+    }
+    .deserialize(serde_yaml::Deserializer::from_str(contents));
+    if budget.exhausted {
+        return Err(limits::exceeded(budget.limit, budget.maximum, None));
+    }
+    counted.map_err(yaml_error)?;
+    let mut value: YamlValue = serde_yaml::from_str(contents).map_err(yaml_error)?;
+    let mut node = read_nodes(contents);
+    apply_merge_keys(&mut value, &mut node).map_err(yaml_error)?;
+    Ok((value, node))
+}
+
+// How the nodes of a YAML document were written, which serde_yaml's values
+// don't keep: only a plain `<<` key is a merge key, a quoted one is a string.
+#[derive(Clone)]
+pub(crate) enum Node {
+    Scalar { plain: bool },
+    Sequence(Vec<Node>),
+    Mapping(Vec<(Node, Node)>),
+    // Not known, such as when the parsers disagree about the document.
+    Unknown,
+}
+
+// Stands in for the nodes of values the parsers disagree about.
+const UNKNOWN: &Node = &Node::Unknown;
+
+impl Node {
+    fn is_plain(&self) -> bool {
+        matches!(self, Node::Scalar { plain: true } | Node::Unknown)
+    }
+
+    /// Whether the node is known to be a plain scalar without a tag.
+    pub(crate) fn is_plain_scalar(&self) -> bool {
+        matches!(self, Node::Scalar { plain: true })
+    }
+
+    /// The node of a sequence's item `i`.
+    pub(crate) fn item(&self, i: usize) -> &Node {
+        match self {
+            Node::Sequence(items) => items.get(i).unwrap_or(UNKNOWN),
+            _ => UNKNOWN,
+        }
+    }
+
+    /// The node of the value of a mapping's entry `i`.
+    pub(crate) fn value(&self, i: usize) -> &Node {
+        match self {
+            Node::Mapping(entries) => entries.get(i).map_or(UNKNOWN, |(_, value)| value),
+            _ => UNKNOWN,
+        }
+    }
+
+    // The nodes of a sequence's `n` items, unknown if the node isn't one.
+    fn items(&mut self, n: usize) -> &mut Vec<Node> {
+        if !matches!(self, Node::Sequence(items) if items.len() == n) {
+            *self = Node::Sequence(vec![Node::Unknown; n]);
+        }
+        match self {
+            Node::Sequence(items) => items,
+            _ => unreachable!(),
+        }
+    }
+
+    // The nodes of a mapping's `n` keys and values, unknown if the node isn't
+    // one.
+    fn entries(&mut self, n: usize) -> &mut Vec<(Node, Node)> {
+        if !matches!(self, Node::Mapping(entries) if entries.len() == n) {
+            *self = Node::Mapping(vec![(Node::Unknown, Node::Unknown); n]);
+        }
+        match self {
+            Node::Mapping(entries) => entries,
+            _ => unreachable!(),
+        }
+    }
+}
+
+// The nodes of the first document in `contents`, with aliases expanded like
+// serde_yaml does. The alias expansion limit has already been checked.
+fn read_nodes(contents: &str) -> Node {
+    enum Open {
+        Sequence(Vec<Node>),
+        Mapping(Vec<(Node, Node)>, Option<Node>),
+    }
+    let mut parser = Parser::new_from_str(contents);
+    let mut anchors = HashMap::new();
+    let mut open: Vec<(Open, usize)> = Vec::new();
+    while let Ok((event, _)) = parser.next_token() {
+        let (node, anchor) = match event {
+            Event::Scalar(_, style, anchor, tag) => (
+                Node::Scalar {
+                    plain: style == TScalarStyle::Plain && tag.is_none(),
+                },
+                anchor,
+            ),
+            Event::Alias(anchor) => (anchors.get(&anchor).cloned().unwrap_or(Node::Unknown), 0),
+            Event::SequenceStart(anchor, _) => {
+                open.push((Open::Sequence(Vec::new()), anchor));
+                continue;
+            }
+            Event::MappingStart(anchor, _) => {
+                open.push((Open::Mapping(Vec::new(), None), anchor));
+                continue;
+            }
+            Event::SequenceEnd | Event::MappingEnd => match open.pop() {
+                Some((Open::Sequence(items), anchor)) => (Node::Sequence(items), anchor),
+                Some((Open::Mapping(entries, _), anchor)) => (Node::Mapping(entries), anchor),
+                None => break,
+            },
+            Event::DocumentEnd | Event::StreamEnd => break,
+            _ => continue,
+        };
+        if anchor != 0 {
+            anchors.insert(anchor, node.clone());
+        }
+        match open.last_mut() {
+            None => return node,
+            Some((Open::Sequence(items), _)) => items.push(node),
+            Some((Open::Mapping(entries, key), _)) => match key.take() {
+                Some(key) => entries.push((key, node)),
+                None => *key = Some(node),
+            },
+        }
+    }
+    Node::Unknown
+}
+
+// serde_yaml's `apply_merge` appends merged keys after the mapping's own, here
+// they take the place of the `<<` key so `<<: *defaults` followed by overrides
+// keeps the layout of the defaults. Keys in the mapping itself win over merged
+// ones, and earlier merged mappings win over later ones. `node` is how `value`
+// was written, and is merged along with it.
+fn apply_merge_keys(value: &mut YamlValue, node: &mut Node) -> Result<(), serde_yaml::Error> {
+    match value {
+        YamlValue::Sequence(items) => {
+            let nodes = node.items(items.len());
+            items
+                .iter_mut()
+                .zip(nodes)
+                .try_for_each(|(item, node)| apply_merge_keys(item, node))
+        }
+        YamlValue::Tagged(tagged) => apply_merge_keys(&mut tagged.value, node),
+        YamlValue::Mapping(map) => {
+            let nodes = node.entries(map.len());
+            for (value, (_, node)) in map.values_mut().zip(nodes.iter_mut()) {
+                apply_merge_keys(value, node)?;
+            }
+            let merge_key = YamlValue::String("<<".to_string());
+            let is_merge = |key: &YamlValue, node: &Node| *key == merge_key && node.is_plain();
+            if !map
+                .keys()
+                .zip(nodes.iter())
+                .any(|(k, (n, _))| is_merge(k, n))
+            {
+                return Ok(());
+            }
+            let own: HashSet<YamlValue> = map
+                .keys()
+                .zip(nodes.iter())
+                .filter(|(k, (n, _))| !is_merge(k, n))
+                .map(|(k, _)| k.clone())
+                .collect();
+            let mut merged = serde_yaml::Mapping::with_capacity(map.len());
+            let mut merged_nodes = Vec::with_capacity(map.len());
+            for ((key, value), (key_node, mut value_node)) in
+                std::mem::take(map).into_iter().zip(std::mem::take(nodes))
+            {
+                if !is_merge(&key, &key_node) {
+                    merged.insert(key, value);
+                    merged_nodes.push((key_node, value_node));
+                    continue;
+                }
+                let sources = match value {
+                    YamlValue::Mapping(source) => vec![(YamlValue::Mapping(source), value_node)],
+                    YamlValue::Sequence(items) => {
+                        let nodes = std::mem::take(value_node.items(items.len()));
+                        items.into_iter().zip(nodes).collect()
+                    }
+                    _ => return Err(invalid_merge()),
+                };
+                for (source, mut source_node) in sources {
+                    let YamlValue::Mapping(source) = source else {
+                        return Err(invalid_merge());
+                    };
+                    let nodes = std::mem::take(source_node.entries(source.len()));
+                    for ((key, value), nodes) in source.into_iter().zip(nodes) {
+                        if !merged.contains_key(&key) && !own.contains(&key) {
+                            merged.insert(key, value);
+                            merged_nodes.push(nodes);
+                        }
+                    }
+                }
+            }
+            *map = merged;
+            *node = Node::Mapping(merged_nodes);
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn invalid_merge() -> serde_yaml::Error {
+    de::Error::custom("a << merge key must be a mapping or a sequence of mappings")
+}
+
+// The values left before a YAML document goes over the node or alias
+// expansion limit, whichever is reached first.
+struct Budget {
+    limit: Limit,
+    maximum: usize,
+    remaining: usize,
+    exhausted: bool,
+}
+
+impl Budget {
+    fn new(limit: Limit, maximum: usize, remaining: usize) -> Self {
+        Self {
+            limit,
+            maximum,
+            remaining,
+            exhausted: false,
+        }
+    }
+}
+
+struct NodeCounter<'a> {
+    budget: &'a mut Budget,
+}
+
+impl NodeCounter<'_> {
+    fn count<E: de::Error>(&mut self) -> Result<(), E> {
+        match self.budget.remaining.checked_sub(1) {
+            Some(remaining) => {
+                self.budget.remaining = remaining;
+                Ok(())
+            }
+            None => {
+                self.budget.exhausted = true;
+                Err(E::custom("the document has too many values"))
+            }
+        }
+    }
+
+    fn child(&mut self) -> NodeCounter<'_> {
+        NodeCounter {
+            budget: self.budget,
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for NodeCounter<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for NodeCounter<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a YAML value")
+    }
+
+    fn visit_bool<E: de::Error>(mut self, _: bool) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_i64<E: de::Error>(mut self, _: i64) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_u64<E: de::Error>(mut self, _: u64) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_f64<E: de::Error>(mut self, _: f64) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_str<E: de::Error>(mut self, _: &str) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_unit<E: de::Error>(mut self) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_none<E: de::Error>(mut self) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(mut self, deserializer: D) -> Result<(), D::Error> {
+        self.child().deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        self.count()?;
+        while seq.next_element_seed(self.child())?.is_some() {}
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        self.count()?;
+        while map.next_key_seed(self.child())?.is_some() {
+            map.next_value_seed(self.child())?;
+        }
+        Ok(())
+    }
+
+    // Tagged values are read as enums, the tag being the variant.
+    fn visit_enum<A: EnumAccess<'de>>(mut self, data: A) -> Result<(), A::Error> {
+        let (_, variant) = data.variant::<IgnoredAny>()?;
+        variant.newtype_variant_seed(self.child())
+    }
 }
 
-fn toml_to_json(contents: &str) -> Result<String, X2YError> {
-    let json_value: YamlValue = match toml::from_str(contents) {
-        Ok(v) => v,
-        Err(e) => return Err(X2YError::Transcode(e.into())),
+// serde_json and serde_yaml include the position in their messages, it's
+// dropped here because the diagnostic reports it separately.
+fn json_parse_error(error: &serde_json::Error, contents: &str) -> X2YError {
+    json_error(error.to_string(), error.line(), error.column(), |line| {
+        contents.lines().nth(line - 1).map(String::from)
+    })
+}
+
+/// A JSON parse error at `line` and `column`, as serde_json counts them,
+/// with the source line looked up by `source_line`. Used for JSON that was
+/// read whole and JSON that was streamed, so both report errors alike.
+pub(crate) fn json_error(
+    message: String,
+    line: usize,
+    column: usize,
+    source_line: impl FnOnce(usize) -> Option<String>,
+) -> X2YError {
+    let location = (line > 0).then(|| Location {
+        line,
+        column: column.max(1),
+    });
+    let message = match message.find(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message,
     };
-    let json_string = match serde_json::to_string_pretty(&json_value) {
-        Ok(s) => s,
-        Err(e) => return Err(X2YError::Transcode(e.into())),
+    X2YError::Parse(Box::new(ParseError::with_source_line(
+        Format::Json,
+        message,
+        location,
+        location.and_then(|l| source_line(l.line)),
+    )))
+}
+
+pub(crate) fn parse_error(
+    format: Format,
+    message: String,
+    contents: &str,
+    location: Option<Location>,
+) -> X2YError {
+    let message = match message.find(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message,
     };
-    Ok(json_string)
+    X2YError::Parse(Box::new(ParseError::new(
+        format, message, contents, location,
+    )))
+}
+
+pub fn encode(value: &Value, format: Format, options: &OutputOptions) -> Result<String, X2YError> {
+    match format {
+        Format::Yaml => emitter::to_yaml_string(value, options),
+        Format::Json => emitter::to_json_string(value, options),
+        Format::Toml => emitter::to_toml_string(value, options),
+    }
+}
+
+fn from_yaml(value: YamlValue, path: &str, tags: YamlTags) -> Result<Value, X2YError> {
+    Ok(match value {
+        YamlValue::Null => Value::Null,
+        YamlValue::Bool(b) => Value::Bool(b),
+        YamlValue::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Value::Integer(i.into())
+            } else if let Some(u) = n.as_u64() {
+                Value::Integer(u.into())
+            } else {
+                Value::Float(n.as_f64().unwrap_or(f64::NAN))
+            }
+        }
+        YamlValue::String(s) => Value::String(s),
+        YamlValue::Sequence(items) => Value::Sequence(
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| from_yaml(item, &index_path(path, i), tags))
+                .collect::<Result<_, _>>()?,
+        ),
+        YamlValue::Mapping(map) => {
+            let mut mapping = Mapping::with_capacity(map.len());
+            for (k, v) in map {
+                let key = yaml_key(k, path, tags)?;
+                let value = from_yaml(v, &key_path(path, &key), tags)?;
+                mapping.insert(key, value);
+            }
+            Value::Mapping(mapping)
+        }
+        YamlValue::Tagged(tagged) => match tags {
+            YamlTags::Wrap => {
+                let value = from_yaml(tagged.value, path, tags)?;
+                Value::Mapping(Mapping::from([(tagged.tag.to_string(), value)]))
+            }
+            YamlTags::Drop => from_yaml(tagged.value, path, tags)?,
+            YamlTags::Error => return Err(unsupported_tag(&tagged.tag, path)),
+        },
+    })
+}
+
+fn unsupported_tag(tag: &Tag, path: &str) -> X2YError {
+    X2YError::unsupported(
+        Format::Yaml,
+        path,
+        format!(
+            "the value has the tag {}, choose how tags are read with --yaml-tags",
+            tag
+        ),
+    )
+}
+
+// JSON and TOML only allow string keys, so scalar keys are written out as
+// they appear in the source. A key can't be wrapped, so its tag is dropped.
+fn yaml_key(key: YamlValue, path: &str, tags: YamlTags) -> Result<String, X2YError> {
+    match key {
+        YamlValue::String(s) => Ok(s),
+        YamlValue::Null => Ok("null".to_string()),
+        YamlValue::Bool(b) => Ok(b.to_string()),
+        YamlValue::Number(n) => Ok(n.to_string()),
+        YamlValue::Tagged(tagged) if tags == YamlTags::Error => {
+            Err(unsupported_tag(&tagged.tag, path))
+        }
+        YamlValue::Tagged(tagged) => yaml_key(tagged.value, path, tags),
+        _ => Err(X2YError::unsupported(
+            Format::Yaml,
+            path,
+            "mapping keys must be strings, numbers, booleans or null",
+        )),
+    }
+}
+
+pub(crate) fn from_json(value: JsonValue) -> Value {
+    match value {
+        JsonValue::Null => Value::Null,
+        JsonValue::Bool(b) => Value::Bool(b),
+        JsonValue::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Value::Integer(i.into())
+            } else if let Some(u) = n.as_u64() {
+                Value::Integer(u.into())
+            } else {
+                Value::Float(n.as_f64().unwrap_or(f64::NAN))
+            }
+        }
+        JsonValue::String(s) => Value::String(s),
+        JsonValue::Array(items) => Value::Sequence(items.into_iter().map(from_json).collect()),
+        JsonValue::Object(map) => {
+            Value::Mapping(map.into_iter().map(|(k, v)| (k, from_json(v))).collect())
+        }
+    }
+}
+
+fn from_toml(value: TomlValue) -> Value {
+    match value {
+        TomlValue::String(s) => Value::String(s),
+        TomlValue::Integer(i) => Value::Integer(i.into()),
+        TomlValue::Float(f) => Value::Float(f),
+        TomlValue::Boolean(b) => Value::Bool(b),
+        TomlValue::Datetime(d) => Value::Datetime(d.to_string()),
+        TomlValue::Array(items) => Value::Sequence(items.into_iter().map(from_toml).collect()),
+        TomlValue::Table(table) => {
+            Value::Mapping(table.into_iter().map(|(k, v)| (k, from_toml(v))).collect())
+        }
+    }
 }
 
 #[cfg(test)]
@@ -108,7 +796,13 @@ mod tests {
         let input = Basic::new();
         let input_contents = serde_json::to_string(&input).unwrap();
 
-        let output = transcode(&input_contents, input_format, output_format).unwrap();
+        let output = transcode(
+            &input_contents,
+            input_format,
+            output_format,
+            &OutputOptions::default(),
+        )
+        .unwrap();
 
         let desired_output = serde_yaml::to_string(&input).unwrap();
 
@@ -121,9 +815,156 @@ mod tests {
         let output_format = Format::Yaml;
         let input = Intermediate::new();
         let input_contents = serde_json::to_string(&input).unwrap();
-        let output = transcode(&input_contents, input_format, output_format).unwrap();
+        let output = transcode(
+            &input_contents,
+            input_format,
+            output_format,
+            &OutputOptions::default(),
+        )
+        .unwrap();
 
         let desired_output = serde_yaml::to_string(&input).unwrap();
         assert_eq!(output, desired_output);
     }
+
+    #[test]
+    fn key_order_is_preserved_between_formats() {
+        let input = "zeta: 1\nalpha: 2\nmid: 3\n";
+
+        let json = transcode(input, Format::Yaml, Format::Json, &OutputOptions::default()).unwrap();
+        let toml = transcode(&json, Format::Json, Format::Toml, &OutputOptions::default()).unwrap();
+        let yaml = transcode(&toml, Format::Toml, Format::Yaml, &OutputOptions::default()).unwrap();
+
+        assert_eq!(toml, "zeta = 1\nalpha = 2\nmid = 3\n");
+        assert_eq!(yaml, input);
+    }
+
+    #[test]
+    fn yaml_merge_keys_are_applied_in_place() {
+        let input = "base: &base {a: 1, b: 2}\nextra: &extra {b: 9, c: 3}\n\
+                     child:\n  <<: [*base, *extra]\n  b: 4\n";
+
+        let options = OutputOptions {
+            compact: true,
+            ..Default::default()
+        };
+
+        let output = transcode(input, Format::Yaml, Format::Json, &options).unwrap();
+
+        assert_eq!(
+            output,
+            "{\"base\":{\"a\":1,\"b\":2},\"extra\":{\"b\":9,\"c\":3},\"child\":{\"a\":1,\"c\":3,\"b\":4}}\n"
+        );
+    }
+
+    #[test]
+    fn quoted_yaml_merge_keys_are_strings() {
+        let input = "base: &base {a: 1, '<<': 2}\nchild:\n  <<: *base\n  b: 3\n\
+                     quoted: {\"<<\": *base}\nmerged: {<<: {<<: *base}}\n";
+
+        let output = transcode(input, Format::Yaml, Format::Json, &OutputOptions::default());
+
+        assert_eq!(
+            decode(&output.unwrap(), Format::Json).unwrap(),
+            decode(
+                r#"{"base": {"a": 1, "<<": 2}, "child": {"a": 1, "<<": 2, "b": 3},
+                    "quoted": {"<<": {"a": 1, "<<": 2}}, "merged": {"a": 1, "<<": 2}}"#,
+                Format::Json
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn yaml_tags_are_wrapped_dropped_or_refused() {
+        let input = "bucket: !Ref Bucket\n";
+        let read = |yaml_tags| {
+            let options = InputOptions {
+                yaml_tags,
+                ..Default::default()
+            };
+            decode_with(input, Format::Yaml, &options)
+        };
+
+        assert_eq!(
+            read(YamlTags::Wrap).unwrap(),
+            decode(r#"{"bucket": {"!Ref": "Bucket"}}"#, Format::Json).unwrap()
+        );
+        assert_eq!(
+            read(YamlTags::Drop).unwrap(),
+            decode(r#"{"bucket": "Bucket"}"#, Format::Json).unwrap()
+        );
+        assert_eq!(
+            read(YamlTags::Error).unwrap_err().to_string(),
+            "unsupported yaml value at .bucket: the value has the tag !Ref, \
+             choose how tags are read with --yaml-tags"
+        );
+    }
+
+    #[test]
+    fn alias_expansion_is_limited() {
+        let items = vec!["x"; 1000].join(", ");
+        let aliases = vec!["*a"; 2000].join(", ");
+        let input = format!("a: &a [{}]\nb: [{}]\n", items, aliases);
+
+        let error = decode(&input, Format::Yaml).unwrap_err();
+
+        assert!(matches!(
+            error,
+            X2YError::Limit(e) if e.limit == Limit::AliasExpansion
+        ));
+    }
+
+    #[test]
+    fn the_transcoder_reads_strings_bytes_and_readers() {
+        let transcoder = Transcoder::new(Format::Json, Format::Yaml);
+        let input = r#"{"a": [1, 2]}"#;
+
+        assert_eq!(transcoder.transcode_str(input).unwrap(), "a:\n- 1\n- 2\n");
+        assert_eq!(
+            transcoder.transcode_bytes(input.as_bytes()).unwrap(),
+            b"a:\n- 1\n- 2\n"
+        );
+        let mut output = Vec::new();
+        Transcoder::new(Format::Yaml, Format::Json)
+            .output_options(OutputOptions {
+                compact: true,
+                ..Default::default()
+            })
+            .transcode_reader_to_writer("a: [1, 2]".as_bytes(), &mut output)
+            .unwrap();
+        assert_eq!(output, b"{\"a\":[1,2]}\n");
+        assert!(transcoder.transcode_bytes(b"\xff").is_err());
+    }
+
+    #[test]
+    fn keys_are_sorted_when_requested() {
+        let input = r#"{"zeta": 1, "alpha": {"b": 2, "a": 1}}"#;
+
+        let options = OutputOptions {
+            sort_keys: true,
+            ..Default::default()
+        };
+        let output = transcode(input, Format::Json, Format::Yaml, &options).unwrap();
+
+        assert_eq!(output, "alpha:\n  a: 1\n  b: 2\nzeta: 1\n");
+    }
+
+    #[test]
+    fn several_json_values_become_yaml_documents_only() {
+        let input = "{\"b\": 1, \"a\": 2}\n[3]\n";
+        let options = OutputOptions {
+            sort_keys: true,
+            ..Default::default()
+        };
+
+        let output = transcode(input, Format::Json, Format::Yaml, &options).unwrap();
+        let error = transcode(input, Format::Json, Format::Toml, &options).unwrap_err();
+
+        assert_eq!(output, "a: 2\nb: 1\n---\n- 3\n");
+        assert_eq!(
+            error.to_string(),
+            "invalid json: trailing characters at line 2 column 1"
+        );
+    }
 }
@@ -1,4 +1,3 @@
-use log;
 use std::fs;
 use std::fs::DirEntry;
 use std::path::Path;
@@ -32,6 +31,12 @@ pub fn walk_dir(directory: &Path, files: &mut Vec<DirEntry>) {
 }
 
 #[cfg(test)]
+#[allow(
+    clippy::single_component_path_imports,
+    clippy::needless_range_loop,
+    clippy::needless_late_init,
+    clippy::needless_borrow
+)]
 mod tests {
     use super::*;
     use std::fs::File;
@@ -0,0 +1,244 @@
+use indexmap::IndexMap;
+
+use crate::error::X2YError;
+
+// The intermediate representation every input is decoded into before it is
+// encoded into the output format. Mappings keep their keys in source order.
+
+/// The keys and values of a mapping in source order.
+///
+/// This is an `IndexMap` from the `indexmap` crate, which is a public
+/// dependency: a new major version of indexmap is a new major version of x2y.
+pub type Mapping = IndexMap<String, Value>;
+
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Integer(i128),
+    Float(f64),
+    String(String),
+    Datetime(String),
+    Sequence(Vec<Value>),
+    Mapping(Mapping),
+}
+
+impl Value {
+    /// Recursively sorts the keys of every mapping in the value.
+    pub fn sort_keys(&mut self) {
+        match self {
+            Value::Sequence(items) => items.iter_mut().for_each(Value::sort_keys),
+            Value::Mapping(map) => {
+                map.values_mut().for_each(Value::sort_keys);
+                map.sort_keys();
+            }
+            _ => {}
+        }
+    }
+
+    /// The value at a path, if there is one.
+    pub fn get(&self, path: &[Segment]) -> Option<&Value> {
+        path.iter()
+            .try_fold(self, |value, segment| match (value, segment) {
+                (Value::Mapping(map), Segment::Key(key)) => map.get(key),
+                (Value::Sequence(items), Segment::Index(i)) => {
+                    resolve_index(*i, items.len()).and_then(|i| items.get(i))
+                }
+                _ => None,
+            })
+    }
+
+    pub fn get_mut(&mut self, path: &[Segment]) -> Option<&mut Value> {
+        path.iter()
+            .try_fold(self, |value, segment| match (value, segment) {
+                (Value::Mapping(map), Segment::Key(key)) => map.get_mut(key),
+                (Value::Sequence(items), Segment::Index(i)) => {
+                    resolve_index(*i, items.len()).and_then(|i| items.get_mut(i))
+                }
+                _ => None,
+            })
+    }
+
+    /// Replaces the value at a path. Missing keys along the way are created as
+    /// mappings and an index one past the end of a sequence appends to it.
+    pub fn set(&mut self, path: &[Segment], new: Value) -> Result<(), X2YError> {
+        let mut value = self;
+        for (depth, segment) in path.iter().enumerate() {
+            if matches!((&*value, segment), (Value::Null, Segment::Key(_))) {
+                *value = Value::Mapping(Mapping::new());
+            }
+            let slot = match (value, segment) {
+                (Value::Mapping(map), Segment::Key(key)) => {
+                    map.entry(key.clone()).or_insert(Value::Null)
+                }
+                (Value::Sequence(items), Segment::Index(i)) => {
+                    if *i == items.len() as i128 {
+                        items.push(Value::Null);
+                    }
+                    let len = items.len();
+                    match resolve_index(*i, len) {
+                        Some(i) => &mut items[i],
+                        None => {
+                            return Err(X2YError::InvalidInput(format!(
+                                "{} is out of range, the sequence has {} items",
+                                segments_path(&path[..=depth]),
+                                len
+                            )))
+                        }
+                    }
+                }
+                (other, _) => {
+                    return Err(X2YError::InvalidInput(format!(
+                        "{} can't be set, {} is {}",
+                        segments_path(path),
+                        display_path(&segments_path(&path[..depth])),
+                        other.describe()
+                    )))
+                }
+            };
+            value = slot;
+        }
+        *value = new;
+        Ok(())
+    }
+
+    /// Removes the value at a path, returning it if it was there.
+    pub fn remove(&mut self, path: &[Segment]) -> Option<Value> {
+        let (last, parents) = path.split_last()?;
+        match (self.get_mut(parents)?, last) {
+            (Value::Mapping(map), Segment::Key(key)) => map.shift_remove(key),
+            (Value::Sequence(items), Segment::Index(i)) => {
+                resolve_index(*i, items.len()).map(|i| items.remove(i))
+            }
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "a boolean",
+            Value::Integer(_) | Value::Float(_) => "a number",
+            Value::String(_) | Value::Datetime(_) => "a string",
+            Value::Sequence(_) => "a sequence",
+            Value::Mapping(_) => "a mapping",
+        }
+    }
+}
+
+/// One step of a path into a document, negative indexes count from the end.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Segment {
+    Key(String),
+    Index(i128),
+}
+
+pub(crate) fn resolve_index(index: i128, len: usize) -> Option<usize> {
+    let index = if index < 0 {
+        len as i128 + index
+    } else {
+        index
+    };
+    usize::try_from(index).ok().filter(|i| *i < len)
+}
+
+/// The path as an RFC 6901 JSON Pointer, `/servers/0/host`.
+pub fn json_pointer(path: &[Segment]) -> String {
+    path.iter()
+        .map(|segment| match segment {
+            Segment::Key(key) => format!("/{}", key.replace('~', "~0").replace('/', "~1")),
+            Segment::Index(i) => format!("/{}", i),
+        })
+        .collect()
+}
+
+pub fn segments_path(path: &[Segment]) -> String {
+    path.iter()
+        .fold(String::new(), |parent, segment| match segment {
+            Segment::Key(key) => key_path(&parent, key),
+            Segment::Index(i) => format!("{}[{}]", parent, i),
+        })
+}
+
+// Paths to values inside a document use jq syntax, `.servers[0]."host name"`.
+// The root is the empty string and is displayed as `.`.
+
+pub fn key_path(parent: &str, key: &str) -> String {
+    let is_identifier = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_identifier {
+        format!("{}.{}", parent, key)
+    } else {
+        format!(
+            "{}.{}",
+            parent,
+            serde_json::to_string(key).expect("strings always serialize")
+        )
+    }
+}
+
+pub fn index_path(parent: &str, index: usize) -> String {
+    format!("{}[{}]", parent, index)
+}
+
+pub fn display_path(path: &str) -> &str {
+    if path.is_empty() {
+        "."
+    } else {
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(entries: Vec<(&str, Value)>) -> Value {
+        Value::Mapping(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    fn keys(value: &Value) -> Vec<&str> {
+        match value {
+            Value::Mapping(map) => map.keys().map(String::as_str).collect(),
+            _ => panic!("not a mapping"),
+        }
+    }
+
+    #[test]
+    fn keys_are_sorted_at_every_depth() {
+        let mut value = mapping(vec![
+            ("b", Value::Integer(1)),
+            (
+                "a",
+                Value::Sequence(vec![mapping(vec![("z", Value::Null), ("y", Value::Null)])]),
+            ),
+        ]);
+        value.sort_keys();
+
+        assert_eq!(keys(&value), vec!["a", "b"]);
+        let Value::Mapping(map) = &value else {
+            unreachable!()
+        };
+        let Value::Sequence(items) = &map["a"] else {
+            unreachable!()
+        };
+        assert_eq!(keys(&items[0]), vec!["y", "z"]);
+    }
+
+    #[test]
+    fn value_paths_quote_keys_that_are_not_identifiers() {
+        let path = key_path(&index_path(&key_path("", "servers"), 0), "host name");
+
+        assert_eq!(path, r#".servers[0]."host name""#);
+        assert_eq!(display_path(""), ".");
+    }
+}
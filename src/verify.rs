@@ -0,0 +1,172 @@
+use crate::emitter;
+use crate::error::{VerificationError, Violation, X2YError};
+use crate::format::Format;
+use crate::options::InputOptions;
+use crate::transcoder::{decode, decode_inspected};
+use crate::value::{display_path, index_path, key_path, Value};
+
+// Checks that a conversion is lossless by reading the output back and
+// comparing it with the source. Unlike `diff`, a value that comes back as a
+// different type, such as a datetime as a string or an integer as a float,
+// counts as a change.
+
+/// Fails with every value that didn't survive converting `contents` to
+/// `output`.
+pub fn verify(
+    contents: &str,
+    input_format: Format,
+    output: &str,
+    output_format: Format,
+    options: &InputOptions,
+) -> Result<(), X2YError> {
+    let (original, yaml) = decode_inspected(contents, input_format, options)?;
+    let converted = decode(output, output_format).map_err(|e| {
+        X2YError::InvalidInput(format!(
+            "the {} output can't be read back: {}",
+            output_format, e
+        ))
+    })?;
+    // Decoding already turned YAML's scalar keys and tagged values into plain
+    // strings and values, so they're found in the source.
+    let mut losses = yaml.map(|findings| findings.changes).unwrap_or_default();
+    compare(&original, &converted, "", &mut losses);
+    if losses.is_empty() {
+        Ok(())
+    } else {
+        Err(X2YError::Verification(Box::new(VerificationError {
+            format: output_format,
+            losses,
+            path: None,
+        })))
+    }
+}
+
+fn compare(original: &Value, converted: &Value, path: &str, losses: &mut Vec<Violation>) {
+    match (original, converted) {
+        (Value::Mapping(a), Value::Mapping(b)) => {
+            for (key, a_value) in a {
+                let path = key_path(path, key);
+                match b.get(key) {
+                    Some(b_value) => compare(a_value, b_value, &path, losses),
+                    None => loss(losses, &path, "was dropped".to_string()),
+                }
+            }
+            for key in b.keys().filter(|key| !a.contains_key(*key)) {
+                loss(losses, &key_path(path, key), "was added".to_string());
+            }
+        }
+        (Value::Sequence(a), Value::Sequence(b)) => {
+            for (i, (a, b)) in a.iter().zip(b).enumerate() {
+                compare(a, b, &index_path(path, i), losses);
+            }
+            for i in b.len()..a.len() {
+                loss(losses, &index_path(path, i), "was dropped".to_string());
+            }
+            for i in a.len()..b.len() {
+                loss(losses, &index_path(path, i), "was added".to_string());
+            }
+        }
+        (Value::Float(a), Value::Float(b)) if a.is_nan() && b.is_nan() => {}
+        (a, b) if type_name(a) != type_name(b) => loss(
+            losses,
+            path,
+            format!("was {}, is now {}", type_name(a), type_name(b)),
+        ),
+        (a, b) if a != b => loss(
+            losses,
+            path,
+            format!(
+                "changed from {} to {}",
+                emitter::inline_json(a),
+                emitter::inline_json(b)
+            ),
+        ),
+        _ => {}
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Integer(_) => "an integer",
+        Value::Float(_) => "a float",
+        Value::String(_) => "a string",
+        Value::Datetime(_) => "a datetime",
+        Value::Sequence(_) => "a sequence",
+        Value::Mapping(_) => "a mapping",
+    }
+}
+
+fn loss(losses: &mut Vec<Violation>, path: &str, message: String) {
+    losses.push(Violation {
+        value_path: display_path(path).to_string(),
+        message,
+        location: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::{OutputOptions, YamlTags};
+    use crate::transcoder::transcode_with_warnings;
+
+    fn verified(contents: &str, input_format: Format, output_format: Format) -> Vec<String> {
+        let options = InputOptions {
+            yaml_tags: YamlTags::Drop,
+            ..Default::default()
+        };
+        let (output, _) = transcode_with_warnings(
+            contents,
+            input_format,
+            output_format,
+            &options,
+            &OutputOptions::default(),
+        )
+        .unwrap();
+        match verify(contents, input_format, &output, output_format, &options) {
+            Ok(()) => vec![],
+            Err(X2YError::Verification(e)) => e
+                .losses
+                .iter()
+                .map(|l| format!("{}: {}", l.value_path, l.message))
+                .collect(),
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn a_lossless_conversion_passes() {
+        let contents = "a: 1\nb: [1.5, true, null]\nc: {d: text}\n";
+
+        assert_eq!(
+            verified(contents, Format::Yaml, Format::Json),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn changed_types_are_flagged() {
+        let contents = "date = 1979-05-27\nwhole = 1.0\n";
+
+        assert_eq!(
+            verified(contents, Format::Toml, Format::Json),
+            vec![".date: was a datetime, is now a string"]
+        );
+    }
+
+    #[test]
+    fn yaml_keys_and_tags_are_flagged() {
+        let contents = "1: one\ntrue: yes\nref: !Ref bucket\n";
+
+        assert_eq!(
+            verified(contents, Format::Yaml, Format::Json),
+            vec![
+                ".\"1\": the key was a number, is now a string",
+                ".true: the key was a boolean, is now a string",
+                ".ref: the tag !Ref was dropped",
+            ]
+        );
+    }
+}
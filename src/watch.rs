@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant, SystemTime};
+
+use notify::event::{AccessKind, AccessMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use crate::config::normalize;
+
+// Changed files are reported by the file system through `notify`, so a file
+// rewritten with the same size within the resolution of its modification time
+// is still noticed. Where that isn't available, such as when the limit on
+// watches is reached, the tree is scanned instead and files whose modification
+// time or size changed are noticed. Either way a change is only reported once
+// the file has been left alone for the debounce period, so an editor writing a
+// file in several steps causes a single conversion.
+
+/// How often the watched files are scanned when the file system can't report
+/// changes.
+pub(crate) const INTERVAL: Duration = Duration::from_millis(100);
+
+/// What the file system reported.
+#[derive(Debug)]
+pub(crate) enum Change {
+    /// These paths changed.
+    Paths(Vec<PathBuf>),
+    /// Changes may have been missed, so every file has to be compared.
+    Rescan,
+}
+
+/// Reports changes under `path` to `sender` until the returned watcher is
+/// dropped. For a file its directory is watched, so it's still noticed when an
+/// editor saves by replacing it.
+pub(crate) fn subscribe(path: &Path, sender: Sender<Change>) -> notify::Result<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let change = match event {
+            Ok(event) if event.need_rescan() => Change::Rescan,
+            // Reading a file isn't a change.
+            Ok(Event {
+                kind: EventKind::Access(kind),
+                ..
+            }) if kind != AccessKind::Close(AccessMode::Write) => return,
+            Ok(event) => Change::Paths(event.paths),
+            Err(_) => Change::Rescan,
+        };
+        let _ = sender.send(change);
+    })?;
+    if path.is_dir() {
+        watcher.watch(path, RecursiveMode::Recursive)?;
+    } else {
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+    }
+    Ok(watcher)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl Stamp {
+    fn of(file: &Path) -> Option<Stamp> {
+        let metadata = fs::metadata(file).ok()?;
+        Some(Stamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Watcher {
+    debounce: Duration,
+    // The files as they were last converted, or found.
+    seen: HashMap<PathBuf, Stamp>,
+    // Changed files and when they last changed.
+    pending: HashMap<PathBuf, (Stamp, Instant)>,
+}
+
+impl Watcher {
+    pub(crate) fn new(debounce: Duration) -> Watcher {
+        Watcher {
+            debounce,
+            seen: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Records each source as it is now, returning those whose output is
+    /// missing or older than the source.
+    pub(crate) fn start<'a>(
+        &mut self,
+        files: impl IntoIterator<Item = (&'a Path, PathBuf)>,
+    ) -> Vec<PathBuf> {
+        let mut stale = Vec::new();
+        for (file, output) in files {
+            let Some(stamp) = Stamp::of(file) else {
+                continue;
+            };
+            let output = Stamp::of(&output).and_then(|output| output.modified);
+            if output.is_none() || output < stamp.modified {
+                stale.push(file.to_path_buf());
+            }
+            self.seen.insert(file.to_path_buf(), stamp);
+        }
+        stale
+    }
+
+    /// Records that the files among `files` reported in `touched` changed,
+    /// restarting their debounce period even if their modification time and
+    /// size are as they were.
+    pub(crate) fn notice<'a>(
+        &mut self,
+        files: impl IntoIterator<Item = &'a Path>,
+        touched: &[PathBuf],
+        now: Instant,
+    ) {
+        let touched: Vec<PathBuf> = touched.iter().map(|path| absolute(path)).collect();
+        for file in files {
+            if !touched.contains(&absolute(file)) {
+                continue;
+            }
+            if let Some(stamp) = Stamp::of(file) {
+                self.pending.insert(file.to_path_buf(), (stamp, now));
+            }
+        }
+    }
+
+    /// When the next pending file will have been left alone for the debounce
+    /// period, if there is one.
+    pub(crate) fn next_due(&self) -> Option<Instant> {
+        self.pending
+            .values()
+            .map(|(_, changed)| *changed + self.debounce)
+            .min()
+    }
+
+    /// Returns the changed files that have since been left alone for the
+    /// debounce period. Files that were removed are forgotten.
+    pub(crate) fn ready(&mut self, now: Instant) -> Vec<PathBuf> {
+        self.pending.retain(|file, _| file.exists());
+        let mut ready: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, (_, changed))| now.duration_since(*changed) >= self.debounce)
+            .map(|(file, _)| file.clone())
+            .collect();
+        ready.sort();
+        for file in &ready {
+            if let Some((stamp, _)) = self.pending.remove(file) {
+                self.seen.insert(file.clone(), stamp);
+            }
+        }
+        ready
+    }
+
+    /// Compares the files with how they were last seen, returning those that
+    /// changed and have since been left alone for the debounce period. New
+    /// files count as changed, removed files are forgotten.
+    pub(crate) fn poll<'a>(
+        &mut self,
+        files: impl IntoIterator<Item = &'a Path>,
+        now: Instant,
+    ) -> Vec<PathBuf> {
+        let mut present = HashMap::new();
+        for file in files {
+            if let Some(stamp) = Stamp::of(file) {
+                present.insert(file.to_path_buf(), stamp);
+            }
+        }
+        self.seen.retain(|file, _| present.contains_key(file));
+        self.pending.retain(|file, _| present.contains_key(file));
+        for (file, stamp) in &present {
+            if self.seen.get(file) == Some(stamp) {
+                self.pending.remove(file);
+                continue;
+            }
+            match self.pending.get(file) {
+                Some((pending, _)) if pending == stamp => {}
+                _ => {
+                    self.pending.insert(file.clone(), (*stamp, now));
+                }
+            }
+        }
+        self.ready(now)
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    normalize(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEBOUNCE: Duration = Duration::from_millis(200);
+
+    #[test]
+    fn missing_and_older_outputs_are_converted_at_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.yaml");
+        let b = dir.path().join("b.yaml");
+        fs::write(&a, "a: 1\n").unwrap();
+        fs::write(&b, "b: 1\n").unwrap();
+        fs::write(dir.path().join("b.json"), "{\"b\": 1}\n").unwrap();
+
+        let mut watcher = Watcher::new(DEBOUNCE);
+        let stale = watcher.start([
+            (a.as_path(), a.with_extension("json")),
+            (b.as_path(), b.with_extension("json")),
+        ]);
+
+        assert_eq!(stale, vec![a]);
+    }
+
+    #[test]
+    fn changes_are_reported_once_they_settle() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.yaml");
+        fs::write(&file, "a: 1\n").unwrap();
+        let mut watcher = Watcher::new(DEBOUNCE);
+        watcher.start([(file.as_path(), file.with_extension("json"))]);
+        let now = Instant::now();
+
+        assert!(watcher.poll([file.as_path()], now).is_empty());
+        fs::write(&file, "a: 12\n").unwrap();
+        assert!(watcher.poll([file.as_path()], now).is_empty());
+        // Another change restarts the debounce period.
+        fs::write(&file, "a: 123\n").unwrap();
+        let later = now + DEBOUNCE / 2;
+        assert!(watcher.poll([file.as_path()], later).is_empty());
+        assert!(watcher.poll([file.as_path()], now + DEBOUNCE).is_empty());
+        assert_eq!(
+            watcher.poll([file.as_path()], later + DEBOUNCE),
+            vec![file.clone()]
+        );
+        assert!(watcher
+            .poll([file.as_path()], later + DEBOUNCE * 2)
+            .is_empty());
+    }
+
+    #[test]
+    fn reported_changes_count_even_when_the_size_and_time_are_the_same() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.yaml");
+        let other = dir.path().join("other.yaml");
+        fs::write(&file, "a: 1\n").unwrap();
+        fs::write(&other, "b: 1\n").unwrap();
+        let files = [file.as_path(), other.as_path()];
+        let mut watcher = Watcher::new(DEBOUNCE);
+        watcher.start(files.map(|file| (file, file.with_extension("json"))));
+        let now = Instant::now();
+
+        // As the file system reports it, relative to the watched directory.
+        let touched = [dir.path().join(".").join("config.yaml")];
+        watcher.notice(files, &touched, now);
+        assert_eq!(watcher.next_due(), Some(now + DEBOUNCE));
+        assert!(watcher.ready(now + DEBOUNCE / 2).is_empty());
+        watcher.notice(files, &touched, now + DEBOUNCE / 2);
+        assert!(watcher.ready(now + DEBOUNCE).is_empty());
+        assert_eq!(watcher.ready(now + DEBOUNCE * 2), vec![file.clone()]);
+        assert_eq!(watcher.next_due(), None);
+    }
+
+    #[test]
+    fn the_file_system_reports_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.yaml");
+        fs::write(&file, "a: 1\n").unwrap();
+        let (sender, changes) = std::sync::mpsc::channel();
+        let Ok(_watcher) = subscribe(dir.path(), sender) else {
+            // Not every environment lets files be watched.
+            return;
+        };
+
+        fs::write(&file, "a: 2\n").unwrap();
+
+        let mut reported = false;
+        while let Ok(change) = changes.recv_timeout(Duration::from_secs(5)) {
+            if let Change::Paths(paths) = change {
+                if paths.iter().any(|path| path.ends_with("config.yaml")) {
+                    reported = true;
+                    break;
+                }
+            }
+        }
+        assert!(reported);
+    }
+
+    #[test]
+    fn new_files_are_reported_and_removed_files_forgotten() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("new.yaml");
+        let mut watcher = Watcher::new(DEBOUNCE);
+        watcher.start([]);
+        let now = Instant::now();
+
+        fs::write(&file, "a: 1\n").unwrap();
+        assert!(watcher.poll([file.as_path()], now).is_empty());
+        assert_eq!(
+            watcher.poll([file.as_path()], now + DEBOUNCE),
+            vec![file.clone()]
+        );
+        fs::remove_file(&file).unwrap();
+        assert!(watcher.poll([file.as_path()], now + DEBOUNCE).is_empty());
+        assert!(watcher.seen.is_empty());
+    }
+}
@@ -1,3 +1,9 @@
+#![allow(
+    clippy::single_component_path_imports,
+    clippy::needless_borrows_for_generic_args
+)]
+
+use assert_cmd::cargo::CommandCargoExt;
 use assert_cmd::Command;
 use once_cell::sync::Lazy;
 use serde_json;
@@ -8,8 +14,11 @@ use std::io::Write;
 use tempfile;
 use toml;
 
-extern crate x2y;
-use x2y::test_utils::*;
+// The fixtures are shared with the unit tests and not all of them are used here.
+#[allow(dead_code)]
+#[path = "../src/test_utils.rs"]
+mod test_utils;
+use test_utils::*;
 
 static LOGGER: Lazy<()> = Lazy::new(|| {
     let _ = env_logger::builder().is_test(true).try_init();
@@ -188,3 +197,998 @@ fn supplying_a_symlink_on_windows_returns_an_error() {
     fs::remove_file("link.json").unwrap();
     assert.failure();
 }
+
+#[test]
+fn keys_keep_the_source_order() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("ordered.json");
+    fs::write(&file_path, r#"{"zeta": 1, "alpha": {"b": 2, "a": 3}}"#).unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y toml").arg(&file_path).assert().success();
+
+    let output = fs::read_to_string(dir.path().join("ordered.toml")).unwrap();
+    assert_eq!(output, "zeta = 1\n\n[alpha]\nb = 2\na = 3\n");
+}
+
+#[test]
+fn keys_are_sorted_when_requested() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("sorted.json");
+    fs::write(&file_path, r#"{"zeta": 1, "alpha": 2}"#).unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y yaml")
+        .arg("--sort-keys")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    let output = fs::read_to_string(dir.path().join("sorted.yaml")).unwrap();
+    assert_eq!(output, "alpha: 2\nzeta: 1\n");
+}
+
+#[test]
+fn output_style_options_are_applied() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("styled.yaml");
+    fs::write(&file_path, "name: x\nlist:\n- 1\n- 2\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y json")
+        .arg("--compact")
+        .arg("--no-trailing-newline")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    let output = fs::read_to_string(dir.path().join("styled.json")).unwrap();
+    assert_eq!(output, r#"{"name":"x","list":[1,2]}"#);
+}
+
+#[test]
+fn canonical_json_is_identical_for_every_source_format() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let yaml_path = dir.path().join("canonical_a.yaml");
+    let toml_path = dir.path().join("canonical_b.toml");
+    fs::write(&yaml_path, "b: 1.50\na:\n  - x\n  - y\n").unwrap();
+    fs::write(&toml_path, "a = [\"x\", \"y\"]\nb = 1.5\n").unwrap();
+
+    for path in [&yaml_path, &toml_path] {
+        let mut cmd = Command::cargo_bin("x2y").unwrap();
+        cmd.arg("-y json")
+            .arg("--canonical")
+            .arg(path)
+            .assert()
+            .success();
+    }
+
+    let from_yaml = fs::read_to_string(dir.path().join("canonical_a.json")).unwrap();
+    let from_toml = fs::read_to_string(dir.path().join("canonical_b.json")).unwrap();
+    assert_eq!(from_yaml, r#"{"a":["x","y"],"b":1.5}"#);
+    assert_eq!(from_yaml, from_toml);
+}
+
+#[test]
+fn a_parse_error_names_the_file_and_location() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("broken.yaml"), "a: 1\nb: [1, 2\nc: 3\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-x yaml")
+        .arg("-y json")
+        .arg("--color=never")
+        .arg(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("error[E101]: invalid yaml"))
+        .stderr(predicates::str::contains("broken.yaml:3:2"))
+        .stderr(predicates::str::contains("3 | c: 3\n  |  ^"));
+}
+
+#[test]
+fn errors_can_be_written_as_json() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("nulls.json");
+    fs::write(&file_path, r#"{"server": {"host": null}}"#).unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    let output = cmd
+        .arg("-y toml")
+        .arg("--error-format=json")
+        .arg(&file_path)
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(5));
+    let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(error["code"], "E200");
+    assert_eq!(error["kind"], "unsupported");
+    assert_eq!(error["format"], "toml");
+    assert_eq!(error["value_path"], ".server.host");
+    assert_eq!(error["path"], file_path.display().to_string());
+}
+
+#[test]
+fn the_format_of_a_file_without_an_extension_is_detected() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("Settings");
+    fs::write(&file_path, "name = \"x\"\n\n[server]\nport = 80\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y yaml").arg(&file_path).assert().success();
+
+    let output = fs::read_to_string(dir.path().join("Settings.yaml")).unwrap();
+    assert_eq!(output, "name: x\nserver:\n  port: 80\n");
+}
+
+#[test]
+fn stdin_is_converted_to_stdout() {
+    Lazy::force(&LOGGER);
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y toml")
+        .arg("-")
+        .write_stdin("{\"a\": 1}")
+        .assert()
+        .success()
+        .stdout("a = 1\n");
+}
+
+#[test]
+fn an_explicit_input_format_overrides_detection() {
+    Lazy::force(&LOGGER);
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-x yaml")
+        .arg("-y json")
+        .arg("--compact")
+        .arg("-")
+        .write_stdin("hello")
+        .assert()
+        .success()
+        .stdout("\"hello\"\n")
+        .stderr("");
+}
+
+#[test]
+fn a_query_prints_a_subtree_and_keeps_the_file() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("compose.yaml");
+    let contents =
+        "services:\n  web:\n    image: nginx\n    ports: [80]\n  db:\n    image: postgres\n";
+    fs::write(&file_path, contents).unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y toml")
+        .arg("--query")
+        .arg(".services.web")
+        .arg(&file_path)
+        .assert()
+        .success()
+        .stdout("image = \"nginx\"\nports = [80]\n");
+
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), contents);
+    assert!(!dir.path().join("compose.toml").exists());
+}
+
+#[test]
+fn an_invalid_query_is_a_usage_error() {
+    Lazy::force(&LOGGER);
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y json")
+        .arg("--query")
+        .arg(".a[")
+        .arg("-")
+        .write_stdin("a: 1")
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn a_query_failing_on_the_document_is_not_a_usage_error() {
+    Lazy::force(&LOGGER);
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y json")
+        .arg("--query")
+        .arg(".[0]")
+        .arg("-")
+        .write_stdin("a: 1")
+        .assert()
+        .code(10)
+        .stderr(predicates::str::contains(
+            "error[E301]: query `.[0]` failed: cannot index object with a number",
+        ));
+}
+
+#[test]
+fn an_unknown_format_is_refused_with_a_suggestion() {
+    Lazy::force(&LOGGER);
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y")
+        .arg("jsn")
+        .arg("-")
+        .write_stdin("a: 1")
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains("a similar value exists: 'json'"));
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-x")
+        .arg("yml")
+        .arg("-y")
+        .arg("json")
+        .arg("-")
+        .write_stdin("a: 1")
+        .assert()
+        .success()
+        .stdout("{\n  \"a\": 1\n}\n");
+}
+
+#[test]
+fn a_failed_conversion_leaves_the_source_alone() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let misnamed = dir.path().join("data.yaml");
+    fs::write(&misnamed, "{\"a\":1}\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.args(["-x", "json", "-y", "yaml", "--no-config"])
+        .arg(&misnamed)
+        .assert()
+        .failure();
+    assert_eq!(fs::read_to_string(&misnamed).unwrap(), "{\"a\":1}\n");
+
+    let truncated = dir.path().join("data.json");
+    fs::write(&truncated, "{\"a\": [1,").unwrap();
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.args(["-y", "yaml", "--no-config"])
+        .arg(&truncated)
+        .assert()
+        .failure();
+    assert_eq!(fs::read_to_string(&truncated).unwrap(), "{\"a\": [1,");
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+}
+
+#[test]
+fn completions_and_the_man_page_are_printed() {
+    Lazy::force(&LOGGER);
+    for shell in ["bash", "zsh", "fish", "powershell", "elvish"] {
+        let mut cmd = Command::cargo_bin("x2y").unwrap();
+        cmd.arg("completions")
+            .arg(shell)
+            .assert()
+            .success()
+            .stdout(predicates::str::contains("infer-schema"))
+            .stdout(predicates::str::contains("max-depth"));
+    }
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("man")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(".TH x2y 1"))
+        .stdout(predicates::str::contains("infer\\-schema"));
+}
+
+#[cfg(target_family = "unix")]
+#[test]
+fn bash_completes_the_files_x2y_reads() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    for file in ["a.yaml", "b.json", "c.txt", "d e.toml"] {
+        fs::write(dir.path().join(file), "").unwrap();
+    }
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    let output = cmd.args(["completions", "bash"]).output().unwrap();
+    let script = dir.path().join("x2y.bash");
+    fs::write(&script, output.stdout).unwrap();
+
+    let complete = |words: &str| {
+        let test = format!(
+            "source x2y.bash\n\
+             COMP_WORDS=({})\n\
+             COMP_CWORD=$((${{#COMP_WORDS[@]}} - 1))\n\
+             _x2y_or_documents x2y \"${{COMP_WORDS[COMP_CWORD]}}\" \"${{COMP_WORDS[COMP_CWORD-1]}}\" 2>/dev/null\n\
+             printf '%s\\n' \"${{COMPREPLY[@]}}\" | sort",
+            words
+        );
+        let Ok(output) = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(test)
+            .current_dir(dir.path())
+            .output()
+        else {
+            return None;
+        };
+        Some(String::from_utf8(output.stdout).unwrap())
+    };
+
+    let Some(documents) = complete("x2y get .a ''") else {
+        // bash isn't installed.
+        return;
+    };
+    assert_eq!(documents, "a.yaml\nb.json\nd e.toml\nsub\n");
+    assert_eq!(
+        complete("x2y validate --schema ''").unwrap(),
+        "a.yaml\nb.json\nd e.toml\nsub\n"
+    );
+    assert_eq!(complete("x2y set .a 1 d").unwrap(), "d e.toml\n");
+    assert!(complete("x2y --cache ''").unwrap().contains("c.txt\n"));
+}
+
+#[test]
+fn watched_files_are_converted_again_when_they_change() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("config.yaml");
+    let output = dir.path().join("config.json");
+    fs::write(&source, "a: 1\n").unwrap();
+    let mut watch = std::process::Command::cargo_bin("x2y")
+        .unwrap()
+        .args([
+            "watch",
+            "-x",
+            "yaml",
+            "-y",
+            "json",
+            "--debounce",
+            "50",
+            "--no-config",
+        ])
+        .arg(dir.path())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let wait_for = |expected: &str| {
+        for _ in 0..100 {
+            if fs::read_to_string(&output).is_ok_and(|json| json == expected) {
+                return true;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        false
+    };
+
+    let started = wait_for("{\n  \"a\": 1\n}\n");
+    // An invalid document is reported without stopping the watcher.
+    fs::write(&source, "a: [\n").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    fs::write(&source, "a: 2\n").unwrap();
+    let changed = wait_for("{\n  \"a\": 2\n}\n");
+    watch.kill().unwrap();
+    watch.wait().unwrap();
+
+    assert!(started);
+    assert!(changed);
+    assert!(source.exists());
+}
+
+#[test]
+fn a_cached_directory_only_converts_changed_files() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.yaml");
+    let b = dir.path().join("b.yaml");
+    fs::write(&a, "a: 1\n").unwrap();
+    fs::write(&b, "b: 1\n").unwrap();
+    let run = || {
+        Command::cargo_bin("x2y")
+            .unwrap()
+            .args(["-x", "yaml", "-y", "json", "--cache", "--no-config"])
+            .arg(dir.path())
+            .assert()
+            .success();
+    };
+
+    run();
+    let modified = fs::metadata(dir.path().join("a.json"))
+        .unwrap()
+        .modified()
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    fs::write(&b, "b: 2\n").unwrap();
+    run();
+
+    assert!(a.exists() && b.exists());
+    assert!(dir.path().join(".x2y-cache").exists());
+    let a_json = fs::metadata(dir.path().join("a.json")).unwrap();
+    assert_eq!(a_json.modified().unwrap(), modified);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("b.json")).unwrap(),
+        "{\n  \"b\": 2\n}\n"
+    );
+
+    // The output of a removed source goes with it.
+    fs::remove_file(&b).unwrap();
+    run();
+    assert!(!dir.path().join("b.json").exists());
+    assert!(dir.path().join("a.json").exists());
+}
+
+#[test]
+fn a_cached_directory_is_checked_again_when_the_checks_change() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let files = dir.path().join("files");
+    fs::create_dir(&files).unwrap();
+    fs::write(files.join("a.yaml"), "port: 99999\n").unwrap();
+    let schema = dir.path().join("schema.yaml");
+    let run = |args: &[&str]| {
+        Command::cargo_bin("x2y")
+            .unwrap()
+            .args(["-x", "yaml", "-y", "json", "--cache", "--no-config"])
+            .args(args)
+            .arg(&files)
+            .assert()
+    };
+
+    run(&[]).success();
+    run(&["--strict", "--verify"]).success();
+    fs::write(&schema, "properties: {port: {maximum: 100000}}\n").unwrap();
+    run(&["--schema", schema.to_str().unwrap()]).success();
+    // The schema's contents count, not only its name.
+    fs::write(&schema, "properties: {port: {maximum: 65535}}\n").unwrap();
+    run(&["--schema", schema.to_str().unwrap()]).code(7);
+}
+
+#[test]
+fn settings_come_from_the_closest_config_file() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("k8s/app")).unwrap();
+    fs::create_dir_all(root.join("vendor")).unwrap();
+    fs::write(
+        root.join(".x2y.toml"),
+        "input_format = \"yaml\"\noutput_format = \"json\"\nindent = 4\nexclude = [\"vendor/**\"]\n\n\
+         [[rules]]\npath = \"k8s/**/*.yaml\"\nindent = 2\n",
+    )
+    .unwrap();
+    for file in ["top.yaml", "k8s/app/deploy.yaml", "vendor/lib.yaml"] {
+        fs::write(root.join(file), "a: 1\n").unwrap();
+    }
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.current_dir(root.join("k8s"))
+        .arg("config")
+        .arg("show")
+        .arg("app/deploy.yaml")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("output_format = \"json\"\n"))
+        .stdout(predicates::str::contains("indent = 2\n"));
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.current_dir(root).arg(".").assert().success();
+
+    assert_eq!(
+        fs::read_to_string(root.join("top.json")).unwrap(),
+        "{\n    \"a\": 1\n}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(root.join("k8s/app/deploy.json")).unwrap(),
+        "{\n  \"a\": 1\n}\n"
+    );
+    assert!(root.join("vendor/lib.yaml").exists());
+    assert!(root.join(".x2y.toml").exists());
+
+    // Flags take precedence.
+    fs::write(root.join("top.yaml"), "a: 1\n").unwrap();
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.current_dir(root)
+        .arg("-y")
+        .arg("toml")
+        .arg("top.yaml")
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(root.join("top.toml")).unwrap(),
+        "a = 1\n"
+    );
+}
+
+#[test]
+fn config_switches_can_be_turned_off_from_the_command_line() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::write(
+        root.join(".x2y.toml"),
+        "sort_keys = true\ncompact = true\ntrailing_newline = false\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.current_dir(root)
+        .args(["-x", "yaml", "-y", "json", "-"])
+        .write_stdin("b: 1\na: 2\n")
+        .assert()
+        .success()
+        .stdout("{\"a\":2,\"b\":1}");
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.current_dir(root)
+        .args(["-x", "yaml", "-y", "json", "-"])
+        .args(["--no-sort-keys", "--no-compact", "--trailing-newline"])
+        .write_stdin("b: 1\na: 2\n")
+        .assert()
+        .success()
+        .stdout("{\n  \"b\": 1,\n  \"a\": 2\n}\n");
+
+    // The last of a flag and its --no- form wins.
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.current_dir(root)
+        .args(["-x", "yaml", "-y", "json", "-"])
+        .args(["--no-sort-keys", "--sort-keys"])
+        .write_stdin("b: 1\na: 2\n")
+        .assert()
+        .success()
+        .stdout("{\"a\":2,\"b\":1}");
+}
+
+#[test]
+fn a_broken_config_file_does_not_stop_completions_or_the_man_page() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join(".x2y.toml"), "indent = \"wide\"\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.current_dir(dir.path())
+        .args(["completions", "bash"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.current_dir(dir.path()).arg("man").assert().success();
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.current_dir(dir.path())
+        .args(["config", "show"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn values_are_edited_in_place() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("config.toml");
+    fs::write(&file_path, "# the server\n[server]\nport = 80 # http\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("set")
+        .arg(".server.port")
+        .arg("8080")
+        .arg(&file_path)
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("set")
+        .arg(".server.host")
+        .arg("localhost")
+        .arg(&file_path)
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("get")
+        .arg(".server.host")
+        .arg(&file_path)
+        .assert()
+        .success()
+        .stdout("localhost\n");
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("del")
+        .arg(".server.host")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "# the server\n[server]\nport = 8080 # http\n"
+    );
+}
+
+#[test]
+fn yaml_edits_warn_that_comments_and_anchors_are_not_kept() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("config.yaml");
+    fs::write(
+        &file_path,
+        "# defaults\nbase: &base {port: 80}\nprod: *base\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("set")
+        .arg(".prod.port")
+        .arg("8080")
+        .arg(&file_path)
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "warning: .: the comments and anchors in the file aren't kept, \
+             yaml is written again as a whole",
+        ));
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "base:\n  port: 80\nprod:\n  port: 8080\n"
+    );
+}
+
+#[cfg(target_family = "unix")]
+#[test]
+fn in_place_edits_replace_the_file_it_links_to_with_its_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("config.yaml");
+    let link = dir.path().join("link.yaml");
+    fs::write(&file_path, "port: 80\n").unwrap();
+    fs::set_permissions(&file_path, fs::Permissions::from_mode(0o640)).unwrap();
+    std::os::unix::fs::symlink(&file_path, &link).unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("set")
+        .arg(".port")
+        .arg("8080")
+        .arg(&link)
+        .assert()
+        .success();
+
+    assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "port: 8080\n");
+    assert_eq!(
+        fs::metadata(&file_path).unwrap().permissions().mode() & 0o777,
+        0o640
+    );
+    let leftovers = fs::read_dir(dir.path()).unwrap().count();
+    assert_eq!(leftovers, 2);
+}
+
+#[test]
+fn files_of_different_formats_are_merged() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let base = dir.path().join("base.yaml");
+    let prod = dir.path().join("prod.toml");
+    let overrides = dir.path().join("overrides.json");
+    fs::write(&base, "server:\n  host: a\n  port: 80\nplugins: [x]\n").unwrap();
+    fs::write(&prod, "[server]\nport = 8080\n").unwrap();
+    fs::write(&overrides, "{\"plugins\": [\"y\"]}").unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y json")
+        .arg("--compact")
+        .arg("merge")
+        .arg("--arrays")
+        .arg("append")
+        .arg(&base)
+        .arg(&prod)
+        .arg(&overrides)
+        .assert()
+        .success()
+        .stdout("{\"server\":{\"host\":\"a\",\"port\":8080},\"plugins\":[\"x\",\"y\"]}\n");
+}
+
+#[test]
+fn documents_in_different_formats_are_diffed() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.yaml");
+    let b = dir.path().join("b.json");
+    fs::write(&a, "name: x\nport: 80\n").unwrap();
+    fs::write(&b, "{\"name\": \"x\", \"port\": 8080, \"tls\": true}").unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("diff")
+        .arg(&a)
+        .arg(&b)
+        .assert()
+        .code(1)
+        .stdout("~ .port: 80 -> 8080\n+ .tls: true\n");
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("diff")
+        .arg(&a)
+        .arg(&a)
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[test]
+fn a_json_patch_is_applied_to_a_toml_file() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("config.toml");
+    let patch = dir.path().join("changes.json");
+    fs::write(&target, "[server]\nport = 80\n").unwrap();
+    fs::write(
+        &patch,
+        r#"[{"op": "test", "path": "/server/port", "value": 80}, {"op": "add", "path": "/server/tls", "value": true}]"#,
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("patch")
+        .arg("--patch")
+        .arg(&patch)
+        .arg(&target)
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "[server]\nport = 80\ntls = true\n"
+    );
+
+    // The test operation now fails and the file is left alone.
+    fs::write(&target, "[server]\nport = 8080\n").unwrap();
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("patch")
+        .arg("--patch")
+        .arg(&patch)
+        .arg(&target)
+        .assert()
+        .code(6);
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "[server]\nport = 8080\n"
+    );
+}
+
+#[test]
+fn documents_are_validated_against_a_schema() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let schema = dir.path().join("schema.json");
+    let configs = dir.path().join("configs");
+    fs::create_dir(&configs).unwrap();
+    fs::write(
+        &schema,
+        r#"{"type": "object", "properties": {"port": {"type": "integer", "maximum": 65535}}}"#,
+    )
+    .unwrap();
+    fs::write(configs.join("ok.yaml"), "port: 80\n").unwrap();
+    fs::write(configs.join("bad.toml"), "port = 99999\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("validate")
+        .arg("--schema")
+        .arg(&schema)
+        .arg(&configs)
+        .assert()
+        .code(7)
+        .stderr(predicates::str::contains(
+            ".port: 99999 is greater than the maximum of 65535",
+        ))
+        .stderr(predicates::str::contains("bad.toml:1:1"));
+
+    // An invalid file isn't converted.
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y json")
+        .arg("--schema")
+        .arg(&schema)
+        .arg(configs.join("bad.toml"))
+        .assert()
+        .code(7);
+    assert!(configs.join("bad.toml").exists());
+    assert!(!configs.join("bad.json").exists());
+}
+
+#[test]
+fn documents_are_validated_as_they_would_be_read() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let schema = dir.path().join("schema.json");
+    let file = dir.path().join("config.yaml");
+    fs::write(
+        &schema,
+        r#"{"properties": {"port": {"type": "integer"}, "tls": {"type": "object"}}}"#,
+    )
+    .unwrap();
+    fs::write(
+        &file,
+        "port: !Ref 80
+tls: {cert: a}
+",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("validate")
+        .arg("--schema")
+        .arg(&schema)
+        .arg("--yaml-tags")
+        .arg("drop")
+        .arg(&file)
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("validate")
+        .arg("--schema")
+        .arg(&schema)
+        .arg("--max-depth")
+        .arg("1")
+        .arg(&file)
+        .assert()
+        .code(9);
+}
+
+#[test]
+fn a_schema_is_inferred_from_samples() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.yaml"), "name: a\nport: 80\n").unwrap();
+    fs::write(dir.path().join("b.toml"), "name = \"b\"\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("infer-schema")
+        .arg(dir.path())
+        .arg("-y")
+        .arg("json")
+        .arg("--sort-keys")
+        .arg("--compact")
+        .assert()
+        .success()
+        .stdout(concat!(
+            r#"{"$schema":"https://json-schema.org/draft/2020-12/schema","#,
+            r#""properties":{"name":{"type":"string"},"port":{"type":"integer"}},"#,
+            r#""required":["name"],"type":"object"}"#,
+            "\n"
+        ));
+}
+
+#[test]
+fn a_lossy_conversion_fails_verification() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let lossy = dir.path().join("lossy.toml");
+    let lossless = dir.path().join("lossless.toml");
+    fs::write(&lossy, "released = 1979-05-27\n").unwrap();
+    fs::write(&lossless, "name = \"x2y\"\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y json")
+        .arg("--verify")
+        .arg(&lossy)
+        .assert()
+        .code(8)
+        .stderr(predicates::str::contains(
+            ".released: was a datetime, is now a string",
+        ));
+    assert!(lossy.exists());
+    assert!(!dir.path().join("lossy.json").exists());
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y json")
+        .arg("--verify")
+        .arg(&lossless)
+        .assert()
+        .success();
+    assert!(!lossless.exists());
+    assert!(dir.path().join("lossless.json").exists());
+}
+
+#[test]
+fn coercions_are_warnings_unless_strict() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let strict = dir.path().join("strict.yaml");
+    let lenient = dir.path().join("lenient.yaml");
+    fs::write(&strict, "enabled: yes\n").unwrap();
+    fs::write(&lenient, "enabled: yes\n").unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y json")
+        .arg("--strict")
+        .arg(&strict)
+        .assert()
+        .code(8)
+        .stderr(predicates::str::contains(
+            ".enabled: `yes` is a string, YAML 1.1 reads it as a boolean",
+        ));
+    assert!(strict.exists());
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y json")
+        .arg(&lenient)
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "warning: .enabled: `yes` is a string, YAML 1.1 reads it as a boolean",
+        ));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("lenient.json")).unwrap(),
+        "{\n  \"enabled\": \"yes\"\n}\n"
+    );
+}
+
+#[test]
+fn yaml_tags_survive_a_round_trip_through_json() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let template = dir.path().join("template.yaml");
+    let source = "Resources:\n  Topic:\n    Properties:\n      TopicName: !Sub ${Env}-events\n      Arn: !GetAtt\n      - Queue\n      - Arn\n";
+    fs::write(&template, source).unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y json").arg(&template).assert().success();
+    let json = fs::read_to_string(dir.path().join("template.json")).unwrap();
+    assert!(json.contains(r#""!Sub": "${Env}-events""#));
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y yaml")
+        .arg(dir.path().join("template.json"))
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&template).unwrap(), source);
+}
+
+#[test]
+fn documents_over_a_limit_are_refused() {
+    Lazy::force(&LOGGER);
+    let dir = tempfile::tempdir().unwrap();
+    let nested = dir.path().join("nested.json");
+    fs::write(&nested, "{\"a\": {\"b\": {\"c\": 1}}}").unwrap();
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y yaml")
+        .arg("--max-depth")
+        .arg("2")
+        .arg(&nested)
+        .assert()
+        .code(9)
+        .stderr(predicates::str::contains(
+            "nested.json is over the limit of 2 levels of nesting at .a.b, raise it with --max-depth",
+        ));
+    assert!(nested.exists());
+
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-x json")
+        .arg("-y yaml")
+        .arg("--max-input-size")
+        .arg("8")
+        .arg("-")
+        .write_stdin("{\"a\": \"too long\"}")
+        .assert()
+        .code(9)
+        .stderr(predicates::str::contains(
+            "the document is over the limit of 8 bytes of input",
+        ));
+}
+
+#[test]
+fn a_stream_of_json_values_becomes_yaml_documents() {
+    Lazy::force(&LOGGER);
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-x json")
+        .arg("-y yaml")
+        .arg("-")
+        .write_stdin("{\"id\": 1}\n{\"id\": 2}\n")
+        .assert()
+        .success()
+        .stdout("id: 1\n---\nid: 2\n");
+
+    let dir = tempfile::tempdir().unwrap();
+    let broken = dir.path().join("broken.json");
+    fs::write(&broken, "{\"a\": [1, 2,]}").unwrap();
+    let mut cmd = Command::cargo_bin("x2y").unwrap();
+    cmd.arg("-y yaml")
+        .arg(&broken)
+        .assert()
+        .code(4)
+        .stderr(predicates::str::contains("broken.json:1:13"));
+    assert!(broken.exists());
+    assert!(!dir.path().join("broken.yaml").exists());
+}